@@ -0,0 +1,123 @@
+//! Times the hot Buffer paths on the CPU blueprint. Run with `cargo bench --bench buffer`.
+//!
+//! Each lookup benchmark also times the linear chunk scan Buffer used before chunks were indexed,
+//! so the two can be compared in a single run.
+
+use std::time::{Duration, Instant};
+
+use logic_paint_rs::{
+    coords::{CellCoord, ChunkCoord},
+    substrate::{buffer::Buffer, buffer_delta::BufferDelta},
+    upc::UPC,
+    utils::{convert::import_legacy_blueprint, Selection},
+};
+
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    let buffer = import_legacy_blueprint(include_str!("../misc/cpu.lpbp").to_string()).unwrap();
+    let bounds = bounds(&buffer);
+    let coords: Vec<CellCoord> = (bounds.lower_left.0.y..bounds.upper_right.0.y)
+        .flat_map(|y| (bounds.lower_left.0.x..bounds.upper_right.0.x).map(move |x| (x, y).into()))
+        .collect();
+
+    println!(
+        "cpu.lpbp: {} chunks, {} cells, {} cells in bounds\n",
+        buffer.chunks().len(),
+        buffer.cell_count(),
+        coords.len()
+    );
+
+    report("get_cell (indexed)", || {
+        coords
+            .iter()
+            .filter(|&&c| buffer.get_cell(c) != UPC::default())
+            .count()
+    });
+
+    report("get_cell (linear scan)", || {
+        coords
+            .iter()
+            .filter(|&&c| linear_get_cell(&buffer, c) != UPC::default())
+            .count()
+    });
+
+    report("iter_cells", || buffer.iter_cells().count());
+
+    report("iter_region (quarter)", || {
+        let quarter = Selection {
+            lower_left: bounds.lower_left,
+            upper_right: CellCoord((bounds.lower_left.0 + bounds.upper_right.0) / 2),
+        };
+        buffer.iter_region(&quarter).count()
+    });
+
+    // A long diagonal stroke, like dragging a brush across the whole design.
+    report("set_cell stroke", || {
+        let mut stroke = buffer.clone();
+        let len = (bounds.upper_right.0 - bounds.lower_left.0).min_element();
+        for i in 0..len {
+            let c = CellCoord(bounds.lower_left.0 + i);
+            let cell = stroke.get_cell(c);
+            stroke.set_cell(c, cell.mirror());
+        }
+        stroke.cell_count()
+    });
+
+    report("BufferDelta::new", || {
+        let mut edited = buffer.clone();
+        edited.set_cell(bounds.lower_left, UPC::default());
+        BufferDelta::new(&buffer, &edited).len()
+    });
+
+    report("clone_selection (all)", || {
+        buffer
+            .clone_selection(&bounds, bounds.lower_left)
+            .cell_count()
+    });
+}
+
+/// The bounding selection of all allocated chunks.
+fn bounds(buffer: &Buffer) -> Selection {
+    let chunk_coords = || buffer.chunks().iter().map(|c| c.chunk_coord);
+    let ll = chunk_coords()
+        .map(|c| c.first_cell_coord().0)
+        .reduce(|a, b| a.min(b));
+    let ur = chunk_coords()
+        .map(|c| c.last_cell_coord().0)
+        .reduce(|a, b| a.max(b));
+
+    Selection::from_rectangle_inclusive(CellCoord(ll.unwrap()), CellCoord(ur.unwrap()))
+}
+
+/// How `Buffer::get_cell` found chunks before they were indexed.
+fn linear_get_cell(buffer: &Buffer, cell_coord: CellCoord) -> UPC {
+    let chunk_coord: ChunkCoord = cell_coord.into();
+    buffer
+        .chunks()
+        .iter()
+        .find(|c| c.chunk_coord == chunk_coord)
+        .map(|c| c.get_cell(cell_coord))
+        .unwrap_or_default()
+}
+
+fn report<T, F>(name: &str, mut f: F)
+where
+    F: FnMut() -> T,
+{
+    // Warm up (and keep the result alive so the work isn't optimized out).
+    std::hint::black_box(f());
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let now = Instant::now();
+        std::hint::black_box(f());
+        total += now.elapsed();
+    }
+
+    println!(
+        "{:<24} {:>10.3} ms",
+        name,
+        total.as_secs_f64() * 1000.0 / ITERATIONS as f64
+    );
+}
@@ -0,0 +1,418 @@
+use std::{error::Error, fs, io::Write, path::Path};
+
+use logic_paint_rs::{
+    simulator::{
+        equivalence::EquivalenceCheck, fault_campaign::FaultCampaign, test_bench::TestBench,
+    },
+    substrate::{
+        buffer::Buffer, compiler::CompilerResults, drc::DesignRuleCheck, logic_gates::GateNetlist,
+        netlist::Netlist,
+    },
+    utils::convert::import_legacy_blueprint,
+    Simulator,
+};
+
+mod stimulus;
+
+use stimulus::Stimulus;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+Usage: logic-paint run <BLUEPRINT> [OPTIONS]
+       logic-paint drc <BLUEPRINT>
+       logic-paint netlist <BLUEPRINT> [--spice] [--out <FILE>]
+       logic-paint stats <BLUEPRINT>
+       logic-paint test <BLUEPRINT> <BENCH>
+       logic-paint faults <BLUEPRINT> <BENCH>
+       logic-paint equiv <BLUEPRINT> <BLUEPRINT> --inputs <A,B,..> [--clocks <N>] [--seed <N>]
+
+Loads a blueprint (legacy JSON, binary or base64), compiles it and runs it headless, printing
+socket states as CSV.
+
+Options:
+  --stimulus <FILE>   Socket declarations and input changes to apply (see below)
+  --clocks <N>        Number of fundamental clocks to run [default: through the last stimulus
+                      event, or 1]
+  --probe <A,B,..>    Sockets to report, in order [default: all sockets]
+  --trace             Report a row after every clock, not just the final one
+  --threads <N>       Propagate on N threads (helps large designs only) [default: 1]
+  --out <FILE>        Write the CSV to a file instead of stdout
+  --vcd <FILE>        Also record the probed sockets every tick, as a VCD file
+  --toggles <FILE>    Also count how often every trace switches, written as a TSV report with
+                      the traces that never switched first
+  --break <COND>      Stop after the clock in which COND fires, reporting it (repeatable). COND
+                      is `rise <SIGNAL>`, `fall <SIGNAL>`, `<BUS> == <VALUE>` or `tick <N>`,
+                      where a signal is a socket name (or `#N` for trace N) and a bus is the
+                      sockets BUS0 (LSB), BUS1, ..
+
+The drc command checks the blueprint's design rules, printing every violation and exiting with
+an error if there are any.
+
+The netlist command writes the compiled transistor netlist as structural Verilog (or SPICE with
+--spice), with sockets as ports. The module is named after the blueprint file.
+
+The stats command prints transistor and trace counts, and how many of each standard logic gate
+were recognized among the transistors.
+
+The test command runs a test bench file against the blueprint, one vector per clock, and exits
+with an error on the first mismatch:
+  inputs A B[4]       Sockets to drive. B[4] is the bus B0 (LSB) through B3
+  outputs Y           Sockets to check after each clock
+  1 0x3 | 1           Drive, then expect. `x` holds an input or skips an output check
+
+The faults command runs the test bench once for every trace stuck high and low, and every
+transistor stuck open and closed, then prints the fault coverage and each fault it missed.
+
+The equiv command checks that two blueprints behave identically, matching sockets by name. The
+--inputs sockets are driven exhaustively (up to 12 of them) and then randomly for --clocks clocks
+at a time [default: 32], and every socket is compared. On a mismatch it prints the stimulus that
+causes it (as a stimulus file) and exits with an error.
+
+Stimulus file:
+  # Comment
+  socket CLK 10 -4    Place a socket named CLK at cell (10, -4)
+  @0 A=1 B=0          Before clock 0, drive A high and B low (held until changed)
+";
+
+#[derive(Default)]
+struct RunArgs {
+    blueprint: String,
+    stimulus: Option<String>,
+    clocks: Option<usize>,
+    probes: Option<Vec<String>>,
+    trace: bool,
+    threads: usize,
+    out: Option<String>,
+    vcd: Option<String>,
+    toggles: Option<String>,
+    breakpoints: Vec<String>,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("run") => parse_run_args(&args[1..]).and_then(run),
+        Some("drc") => match &args[1..] {
+            [blueprint] => drc(blueprint),
+            _ => Err(USAGE.into()),
+        },
+        Some("netlist") => netlist(&args[1..]),
+        Some("stats") => match &args[1..] {
+            [blueprint] => stats(blueprint),
+            _ => Err(USAGE.into()),
+        },
+        Some("test") => match &args[1..] {
+            [blueprint, bench] => test(blueprint, bench),
+            _ => Err(USAGE.into()),
+        },
+        Some("faults") => match &args[1..] {
+            [blueprint, bench] => faults(blueprint, bench),
+            _ => Err(USAGE.into()),
+        },
+        Some("equiv") => equiv(&args[1..]),
+        Some("-h") | Some("--help") | Some("help") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_run_args(args: &[String]) -> Result<RunArgs> {
+    let mut run_args = RunArgs {
+        threads: 1,
+        ..Default::default()
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+
+        match arg.as_str() {
+            "--stimulus" => run_args.stimulus = Some(value()?),
+            "--clocks" => run_args.clocks = Some(value()?.parse()?),
+            "--probe" => {
+                run_args.probes = Some(value()?.split(',').map(|s| s.to_string()).collect())
+            }
+            "--trace" => run_args.trace = true,
+            "--threads" => run_args.threads = value()?.parse()?,
+            "--out" => run_args.out = Some(value()?),
+            "--vcd" => run_args.vcd = Some(value()?),
+            "--toggles" => run_args.toggles = Some(value()?),
+            "--break" => run_args.breakpoints.push(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ if run_args.blueprint.is_empty() => run_args.blueprint = arg.clone(),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+
+    if run_args.blueprint.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    Ok(run_args)
+}
+
+/// Loads a blueprint in any of the formats the editor can produce, sniffing the content.
+fn load_blueprint(path: impl AsRef<Path>) -> Result<Buffer> {
+    let bytes = fs::read(path)?;
+
+    if bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+        return Ok(import_legacy_blueprint(String::from_utf8(bytes)?)?);
+    }
+
+    match Buffer::from_bytes(&bytes) {
+        Ok(buffer) => Ok(buffer),
+        Err(e) => match std::str::from_utf8(&bytes) {
+            Ok(text) => Ok(Buffer::from_base64_string(text.trim())?),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+fn run(args: RunArgs) -> Result<()> {
+    let mut buffer = load_blueprint(&args.blueprint)?;
+
+    let stimulus = match &args.stimulus {
+        Some(path) => {
+            Stimulus::parse(&fs::read_to_string(path)?).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => Default::default(),
+    };
+
+    for (name, x, y) in &stimulus.sockets {
+        buffer.set_socket((*x, *y).into(), Some(name.clone()));
+    }
+
+    // Events are sorted, so the last one is applied on the last clock run by default.
+    let last_event_clock = stimulus.events.last().map(|e| e.clock);
+    let clocks = args
+        .clocks
+        .unwrap_or_else(|| last_event_clock.map_or(1, |clock| clock + 1));
+    if let Some(last) = last_event_clock.filter(|&last| last >= clocks) {
+        eprintln!(
+            "warning: the stimulus events on clocks {} through {} are never applied, pass \
+             --clocks {} to run them",
+            clocks,
+            last,
+            last + 1
+        );
+    }
+
+    let mut sim = Simulator::new(&buffer)?;
+    sim.set_threads(args.threads);
+    let probes = args.probes.clone().unwrap_or_else(|| sim.socket_names());
+
+    if args.vcd.is_some() {
+        sim.record(probes.clone(), true)?;
+    }
+
+    if args.toggles.is_some() {
+        sim.record_toggle_coverage();
+    }
+
+    for condition in &args.breakpoints {
+        sim.add_breakpoint(condition)?;
+    }
+
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    writeln!(out, "clock,{}", probes.join(","))?;
+
+    let mut events = stimulus.events.iter().peekable();
+    for clock in 0..clocks {
+        while let Some(event) = events.next_if(|e| e.clock == clock) {
+            sim.set_input(&event.socket, event.high)?;
+        }
+
+        sim.clock(1)?;
+        let hits = sim.take_breakpoint_hits();
+
+        if args.trace || !hits.is_empty() || clock + 1 == clocks {
+            write_row(&mut out, &mut sim, clock, &probes)?;
+        }
+
+        if !hits.is_empty() {
+            for hit in hits {
+                eprintln!("clock {}: {}", clock, hit);
+            }
+            break;
+        }
+    }
+
+    if let (Some(path), Some(waveform)) = (&args.vcd, sim.waveform()) {
+        waveform.write_vcd(&mut fs::File::create(path)?)?;
+    }
+
+    if let (Some(path), Some(toggles)) = (&args.toggles, sim.toggle_coverage()) {
+        toggles.write_report(
+            &mut fs::File::create(path)?,
+            &sim.context().compiler_results,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn drc(blueprint: &str) -> Result<()> {
+    let check = DesignRuleCheck::from_buffer(&load_blueprint(blueprint)?);
+
+    for message in check.messages() {
+        println!("{}", message);
+    }
+
+    if check.is_clean() {
+        Ok(())
+    } else {
+        Err(format!("{} design rule violations", check.diagnostics.len()).into())
+    }
+}
+
+fn netlist(args: &[String]) -> Result<()> {
+    let mut blueprint = None;
+    let mut spice = false;
+    let mut out_path = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--spice" => spice = true,
+            "--out" => out_path = Some(args.next().ok_or("--out requires a value")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ if blueprint.is_none() => blueprint = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+
+    let blueprint = blueprint.ok_or(USAGE)?;
+    let buffer = load_blueprint(blueprint)?;
+    let name = Path::new(blueprint)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let netlist = Netlist::new(&name, &buffer, &CompilerResults::from_buffer(&buffer));
+
+    let mut out: Box<dyn Write> = match out_path {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    if spice {
+        netlist.write_spice(&mut out)?;
+    } else {
+        netlist.write_verilog(&mut out)?;
+    }
+
+    Ok(())
+}
+
+fn stats(blueprint: &str) -> Result<()> {
+    let buffer = load_blueprint(blueprint)?;
+    let compiler_results = CompilerResults::from_buffer(&buffer);
+    let gate_netlist = GateNetlist::new(&buffer, &compiler_results);
+
+    println!("transistors: {}", compiler_results.gates.len());
+    // Trace 0 is the reserved null trace.
+    println!("traces: {}", compiler_results.traces.len() - 1);
+
+    let mut counts: Vec<_> = gate_netlist.counts().into_iter().collect();
+    counts.sort_by_key(|(kind, count)| (std::cmp::Reverse(*count), kind.to_string()));
+    for (kind, count) in counts {
+        println!("{}: {}", kind, count);
+    }
+    println!("unmatched transistors: {}", gate_netlist.unmatched.len());
+
+    Ok(())
+}
+
+fn test(blueprint: &str, bench: &str) -> Result<()> {
+    let test_bench =
+        TestBench::parse(&fs::read_to_string(bench)?).map_err(|e| format!("{}: {}", bench, e))?;
+    let report = test_bench.run(&load_blueprint(blueprint)?)?;
+
+    if report.passed() {
+        println!("{}", report);
+        Ok(())
+    } else {
+        Err(report.to_string().into())
+    }
+}
+
+fn faults(blueprint: &str, bench: &str) -> Result<()> {
+    let test_bench =
+        TestBench::parse(&fs::read_to_string(bench)?).map_err(|e| format!("{}: {}", bench, e))?;
+    let campaign = FaultCampaign::run(&load_blueprint(blueprint)?, &test_bench)?;
+
+    for description in campaign.undetected() {
+        println!("undetected: {}", description);
+    }
+    println!("{}", campaign);
+
+    Ok(())
+}
+
+fn equiv(args: &[String]) -> Result<()> {
+    let mut blueprints = vec![];
+    let mut check = EquivalenceCheck::new(vec![]);
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+
+        match arg.as_str() {
+            "--inputs" => check.inputs = value()?.split(',').map(|s| s.to_string()).collect(),
+            "--clocks" => check.clocks = value()?.parse()?,
+            "--seed" => check.seed = value()?.parse()?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => blueprints.push(arg.clone()),
+        }
+    }
+
+    let [a, b] = &blueprints[..] else {
+        return Err(USAGE.into());
+    };
+
+    match check.check(&load_blueprint(a)?, &load_blueprint(b)?)? {
+        None => {
+            println!("equivalent");
+            Ok(())
+        }
+        Some(counterexample) => {
+            print!("{}", counterexample);
+            Err("not equivalent".into())
+        }
+    }
+}
+
+fn write_row(
+    out: &mut dyn Write,
+    sim: &mut Simulator,
+    clock: usize,
+    probes: &[String],
+) -> Result<()> {
+    let mut row = clock.to_string();
+    for probe in probes {
+        row.push(',');
+        row.push(if sim.get_output(probe)? { '1' } else { '0' });
+    }
+    writeln!(out, "{}", row)?;
+    Ok(())
+}
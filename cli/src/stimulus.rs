@@ -0,0 +1,139 @@
+/// A parsed stimulus file. The format is line based, with `#` starting a comment:
+///
+/// ```text
+/// # Place a socket (in addition to any stored in the blueprint) at cell coord (10, -4).
+/// socket CLK 10 -4
+///
+/// # Before clock 0 executes, drive A high and B low. Inputs hold until changed.
+/// @0 A=1 B=0
+/// @5 A=0
+/// ```
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stimulus {
+    pub sockets: Vec<(String, i32, i32)>,
+
+    /// Input changes, sorted by the clock they are applied before.
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub clock: usize,
+    pub socket: String,
+    pub high: bool,
+}
+
+impl Stimulus {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut stimulus = Stimulus::default();
+
+        for (i, line) in src.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+
+            let Some(first) = words.next() else {
+                continue;
+            };
+
+            if first == "socket" {
+                let parts: Vec<_> = words.collect();
+                let [name, x, y] = parts[..] else {
+                    return Err(format!("line {}: expected `socket NAME X Y`", line_number));
+                };
+                let x = parse_number(x, line_number)?;
+                let y = parse_number(y, line_number)?;
+                stimulus.sockets.push((name.to_string(), x, y));
+            } else if let Some(clock) = first.strip_prefix('@') {
+                let clock = parse_number(clock, line_number)?;
+                for assignment in words {
+                    let Some((socket, value)) = assignment.split_once('=') else {
+                        return Err(format!(
+                            "line {}: expected `NAME=0` or `NAME=1`, found `{}`",
+                            line_number, assignment
+                        ));
+                    };
+                    let high = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => {
+                            return Err(format!(
+                                "line {}: socket values must be 0 or 1, found `{}`",
+                                line_number, value
+                            ))
+                        }
+                    };
+                    stimulus.events.push(Event {
+                        clock,
+                        socket: socket.to_string(),
+                        high,
+                    });
+                }
+            } else {
+                return Err(format!(
+                    "line {}: unknown directive `{}`",
+                    line_number, first
+                ));
+            }
+        }
+
+        // Stable, so later lines win for the same clock and socket.
+        stimulus.events.sort_by_key(|e| e.clock);
+
+        Ok(stimulus)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(word: &str, line_number: usize) -> Result<T, String> {
+    word.parse()
+        .map_err(|_| format!("line {}: invalid number `{}`", line_number, word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sockets_and_events() {
+        let stimulus = Stimulus::parse(
+            "
+            # comment
+            socket CLK 10 -4
+            @5 A=0 # trailing comment
+            @0 A=1 B=0
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(stimulus.sockets, vec![("CLK".to_string(), 10, -4)]);
+        assert_eq!(
+            stimulus.events,
+            vec![
+                Event {
+                    clock: 0,
+                    socket: "A".to_string(),
+                    high: true
+                },
+                Event {
+                    clock: 0,
+                    socket: "B".to_string(),
+                    high: false
+                },
+                Event {
+                    clock: 5,
+                    socket: "A".to_string(),
+                    high: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let err = Stimulus::parse("@0 A=1\n@1 A=2").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+
+        let err = Stimulus::parse("bogus").unwrap_err();
+        assert!(err.starts_with("line 1:"), "{}", err);
+    }
+}
@@ -0,0 +1,543 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{AluOperand, OpCode, RESET_PC, ShiftOperand};
+
+/// An assembled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// The memory image, starting at address zero and ending after the last assembled byte.
+    pub image: Vec<u8>,
+
+    /// The address of every label.
+    pub symbols: HashMap<String, u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+enum Statement<'a> {
+    Instruction(&'a str, Vec<&'a str>),
+    Fill(&'a str),
+}
+
+/// Assembles LC-3b assembly into a memory image. One statement per line, `;` starts a comment:
+///
+/// ```text
+///         .ORIG x0002         ; Where assembly starts (this is also the default)
+///         LDI R0, #10
+/// LOOP    ADD R0, R0, #-1     ; Labels may also be written `LOOP:`, and must be when alone
+///         BRp LOOP
+///         LEA R1, DATA
+///         LDW R2, R1, #0      ; Memory offsets are field values, so words for LDW/STW
+///         JMP R7              ; RET is an alias for JMP R7
+/// DATA    .FILL xBEEF
+///         .BLKW 4             ; Four zero words
+///         .END                ; Anything after is ignored
+/// ```
+///
+/// Mnemonics (case insensitive) follow the `OpCode` variants: `BR[n][z][p]`, `NOP`, `JMP`,
+/// `RET`, `ADD`, `SUB`, `AND`, `OR`, `XOR`, `LSHF`, `RSHFL`, `RSHFA`, `LDB`, `LDW`, `LDI`, `LEA`,
+/// `STB` and `STW`. Numbers are decimal (`#-5` or `5`) or hex (`x1F` or `0x1F`). `BR` and `LEA`
+/// take either a label or a word offset from the incremented PC.
+pub fn assemble(src: &str) -> Result<Program, AsmError> {
+    let mut symbols = HashMap::new();
+    let mut statements = vec![];
+    let mut address = RESET_PC as u32;
+
+    // First pass: lay out statements and collect labels.
+    for (i, line) in src.lines().enumerate() {
+        let line_number = i + 1;
+        let err = |message: String| AsmError {
+            line: line_number,
+            message,
+        };
+
+        let line = line.split(';').next().unwrap();
+        let mut words = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|w| !w.is_empty());
+
+        let Some(mut first) = words.next() else {
+            continue;
+        };
+
+        let explicit_label = first.strip_suffix(':');
+        if explicit_label.is_some() || !is_keyword(first) {
+            let label = explicit_label.unwrap_or(first);
+            if label.is_empty() || parse_number(label).is_some() {
+                return Err(err(format!("invalid label `{}`", label)));
+            }
+            if address > 0xFFFF {
+                return Err(err(format!("label `{}` is past the end of memory", label)));
+            }
+            if symbols.insert(label.to_string(), address as u16).is_some() {
+                return Err(err(format!("duplicate label `{}`", label)));
+            }
+
+            match words.next() {
+                // A label has to be followed by a keyword, unless marked with a colon. Otherwise
+                // it's more likely a mistyped mnemonic, like `ADDD R1, R1, #1` or `RETT`.
+                Some(next) if explicit_label.is_some() || is_keyword(next) => first = next,
+                None if explicit_label.is_some() => continue,
+                _ => return Err(err(format!("unknown instruction `{}`", first))),
+            }
+        }
+
+        let operands: Vec<_> = words.collect();
+        let operand = |index: usize| {
+            operands
+                .get(index)
+                .copied()
+                .ok_or_else(|| err(format!("{} expects an operand", first)))
+        };
+
+        match first.to_ascii_uppercase().as_str() {
+            ".ORIG" => {
+                let origin = parse_number(operand(0)?)
+                    .filter(|&o| (0..=0xFFFF).contains(&o) && o % 2 == 0)
+                    .ok_or_else(|| err("expected an even address for .ORIG".to_string()))?;
+                address = origin as u32;
+            }
+            ".END" => break,
+            ".BLKW" => {
+                let count = parse_number(operand(0)?)
+                    .filter(|&c| c >= 0)
+                    .ok_or_else(|| err("expected a word count for .BLKW".to_string()))?;
+                address = (count as u32)
+                    .checked_mul(2)
+                    .and_then(|bytes| address.checked_add(bytes))
+                    .ok_or_else(|| err("program extends past the end of memory".to_string()))?;
+            }
+            ".FILL" => {
+                statements.push((line_number, address, Statement::Fill(operand(0)?)));
+                address += 2;
+            }
+            _ if first.starts_with('.') => {
+                return Err(err(format!("unknown directive `{}`", first)));
+            }
+            _ => {
+                statements.push((
+                    line_number,
+                    address,
+                    Statement::Instruction(first, operands),
+                ));
+                address += 2;
+            }
+        }
+
+        if address > 0x1_0000 {
+            return Err(err("program extends past the end of memory".to_string()));
+        }
+    }
+
+    // Second pass: encode.
+    let mut image = vec![0; address as usize];
+    for (line_number, address, statement) in statements {
+        let word = match statement {
+            Statement::Fill(value) => match parse_number(value) {
+                Some(value) => field(value, 16, true)
+                    .or_else(|_| field(value, 16, false))
+                    .map_err(|message| AsmError {
+                        line: line_number,
+                        message,
+                    })?,
+                None => *symbols.get(value).ok_or_else(|| AsmError {
+                    line: line_number,
+                    message: format!("unknown label `{}`", value),
+                })?,
+            },
+            Statement::Instruction(mnemonic, operands) => {
+                let assembler = Assembler {
+                    symbols: &symbols,
+                    address: address as u16,
+                    mnemonic,
+                    operands: &operands,
+                };
+                let opcode = assembler.assemble().map_err(|message| AsmError {
+                    line: line_number,
+                    message,
+                })?;
+                opcode.into()
+            }
+        };
+
+        let address = address as usize;
+        if image.len() < address + 2 {
+            image.resize(address + 2, 0);
+        }
+        image[address..address + 2].copy_from_slice(&word.to_be_bytes());
+    }
+
+    Ok(Program { image, symbols })
+}
+
+/// Assembles a single instruction.
+struct Assembler<'a> {
+    symbols: &'a HashMap<String, u16>,
+    address: u16,
+    mnemonic: &'a str,
+    operands: &'a [&'a str],
+}
+
+impl Assembler<'_> {
+    fn assemble(&self) -> Result<OpCode, String> {
+        let mnemonic = self.mnemonic.to_ascii_uppercase();
+
+        if let Some(flags) = mnemonic.strip_prefix("BR") {
+            if !flags.chars().all(|c| "NZP".contains(c)) {
+                return Err(format!("unknown instruction `{}`", self.mnemonic));
+            }
+            self.expect_operands(1)?;
+            // A bare BR is unconditional.
+            let always = flags.is_empty();
+            return Ok(OpCode::BR {
+                n: always || flags.contains('N'),
+                z: always || flags.contains('Z'),
+                p: always || flags.contains('P'),
+                pc_word_offset: self.pc_offset(0)?,
+            });
+        }
+
+        Ok(match mnemonic.as_str() {
+            "NOP" => {
+                self.expect_operands(0)?;
+                OpCode::BR {
+                    n: false,
+                    z: false,
+                    p: false,
+                    pc_word_offset: 0,
+                }
+            }
+            "JMP" => {
+                self.expect_operands(1)?;
+                OpCode::JMP {
+                    base_r: self.register(0)?,
+                }
+            }
+            "RET" => {
+                self.expect_operands(0)?;
+                OpCode::JMP { base_r: 7 }
+            }
+            "ADD" => OpCode::Add(self.alu_operand(true)?),
+            "SUB" => OpCode::Sub(self.alu_operand(true)?),
+            "AND" => OpCode::And(self.alu_operand(false)?),
+            "OR" => OpCode::Or(self.alu_operand(false)?),
+            "XOR" => OpCode::Xor(self.alu_operand(false)?),
+            "LSHF" => OpCode::LShift(self.shift_operand()?),
+            "RSHFL" => OpCode::RShiftLog(self.shift_operand()?),
+            "RSHFA" => OpCode::RShiftArith(self.shift_operand()?),
+            "LDB" => {
+                self.expect_operands(3)?;
+                OpCode::LDB {
+                    dr: self.register(0)?,
+                    base_r: self.register(1)?,
+                    offset: self.immediate(2, 6, true)?,
+                }
+            }
+            "LDW" => {
+                self.expect_operands(3)?;
+                OpCode::LDW {
+                    dr: self.register(0)?,
+                    base_r: self.register(1)?,
+                    offset: self.immediate(2, 6, true)? << 1,
+                }
+            }
+            "STB" => {
+                self.expect_operands(3)?;
+                OpCode::STB {
+                    sr: self.register(0)?,
+                    base_r: self.register(1)?,
+                    offset: self.immediate(2, 6, true)?,
+                }
+            }
+            "STW" => {
+                self.expect_operands(3)?;
+                OpCode::STW {
+                    sr: self.register(0)?,
+                    base_r: self.register(1)?,
+                    offset: self.immediate(2, 6, true)? << 1,
+                }
+            }
+            "LDI" => {
+                self.expect_operands(2)?;
+                OpCode::LDI {
+                    dr: self.register(0)?,
+                    value: self.immediate(1, 9, true)?,
+                }
+            }
+            "LEA" => {
+                self.expect_operands(2)?;
+                OpCode::LEA {
+                    dr: self.register(0)?,
+                    pc_offset: self.pc_offset(1)?,
+                }
+            }
+            _ => return Err(format!("unknown instruction `{}`", self.mnemonic)),
+        })
+    }
+
+    fn alu_operand(&self, signed: bool) -> Result<AluOperand, String> {
+        self.expect_operands(3)?;
+        let dr = self.register(0)?;
+        let sr1 = self.register(1)?;
+
+        Ok(match parse_register(self.operands[2]) {
+            Some(sr2) => AluOperand::Registers { dr, sr1, sr2 },
+            None => AluOperand::Immediate {
+                dr,
+                sr1,
+                immediate: self.immediate(2, 4, signed)?,
+            },
+        })
+    }
+
+    fn shift_operand(&self) -> Result<ShiftOperand, String> {
+        self.expect_operands(3)?;
+        Ok(ShiftOperand {
+            dr: self.register(0)?,
+            sr: self.register(1)?,
+            amount: self.immediate(2, 4, false)?,
+        })
+    }
+
+    fn expect_operands(&self, count: usize) -> Result<(), String> {
+        if self.operands.len() != count {
+            return Err(format!(
+                "{} expects {} operand(s), found {}",
+                self.mnemonic,
+                count,
+                self.operands.len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn register(&self, index: usize) -> Result<usize, String> {
+        parse_register(self.operands[index])
+            .ok_or_else(|| format!("expected a register, found `{}`", self.operands[index]))
+    }
+
+    fn immediate(&self, index: usize, bits: u32, signed: bool) -> Result<u16, String> {
+        let value = parse_number(self.operands[index])
+            .ok_or_else(|| format!("expected a number, found `{}`", self.operands[index]))?;
+        field(value, bits, signed)
+    }
+
+    /// A 9 bit word offset from the incremented PC, given as a label or a number. Returned as
+    /// the (sign extended) byte offset `OpCode` holds.
+    fn pc_offset(&self, index: usize) -> Result<u16, String> {
+        let operand = self.operands[index];
+        let word_offset = match parse_number(operand) {
+            Some(offset) => offset,
+            None => {
+                let target = *self
+                    .symbols
+                    .get(operand)
+                    .ok_or_else(|| format!("unknown label `{}`", operand))?;
+                (target as i32 - (self.address as i32 + 2)) / 2
+            }
+        };
+
+        Ok(field(word_offset, 9, true)? << 1)
+    }
+}
+
+/// Checks that `value` fits in a field of `bits` bits, returning it as (sign extended) u16.
+fn field(value: i32, bits: u32, signed: bool) -> Result<u16, String> {
+    let (min, max) = if signed {
+        (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+    } else {
+        (0, (1 << bits) - 1)
+    };
+
+    if value < min || value > max {
+        return Err(format!(
+            "{} doesn't fit in {} bits (expected {} to {})",
+            value, bits, min, max
+        ));
+    }
+
+    Ok(value as u16)
+}
+
+fn is_keyword(word: &str) -> bool {
+    let word = word.to_ascii_uppercase();
+    word.starts_with('.')
+        || word
+            .strip_prefix("BR")
+            .is_some_and(|flags| flags.chars().all(|c| "NZP".contains(c)))
+        || [
+            "NOP", "JMP", "RET", "ADD", "SUB", "AND", "OR", "XOR", "LSHF", "RSHFL", "RSHFA", "LDB",
+            "LDW", "LDI", "LEA", "STB", "STW",
+        ]
+        .contains(&word.as_str())
+}
+
+fn parse_register(word: &str) -> Option<usize> {
+    let index = word.strip_prefix(['R', 'r'])?.parse().ok()?;
+    (index < 8).then_some(index)
+}
+
+fn parse_number(word: &str) -> Option<i32> {
+    let word = word.strip_prefix('#').unwrap_or(word);
+    let (negative, word) = match word.strip_prefix('-') {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+
+    let hex = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix(['x', 'X']));
+    let value = match hex {
+        Some(hex) => i32::from_str_radix(hex, 16).ok()?,
+        None => word.parse().ok()?,
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LC3B;
+
+    fn words(program: &Program) -> Vec<u16> {
+        program.image[RESET_PC as usize..]
+            .chunks(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_every_decodable_word() {
+        for word in 0..=u16::MAX {
+            let Ok(opcode) = OpCode::try_from(word) else {
+                continue;
+            };
+
+            // Don't-care bits (like the unused bits of JMP) are encoded as zero, so compare
+            // after decoding again.
+            let encoded = u16::from(opcode);
+            assert_eq!(OpCode::try_from(encoded), Ok(opcode), "{:#06x}", word);
+        }
+    }
+
+    #[test]
+    fn assembles_documented_encodings() {
+        let program = assemble(
+            "
+            LDI R0, #5
+            ADD R1, R0, #3
+            LSHF R2, R1, #2
+            LDI R4, #-8
+            RSHFA R5, R4, #1
+            STW R1, R0, #1
+            BRz #1
+            LDB R3, R0, #0
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            words(&program),
+            vec![
+                0xA005, 0x1223, 0xD442, 0xA9F8, 0xDB31, 0x7201, 0x0401, 0x2600
+            ]
+        );
+    }
+
+    #[test]
+    fn assembled_programs_run() {
+        let program = assemble(
+            "
+            ; Sums DATA[0..3] into R2.
+                    LEA R0, DATA
+                    LDI R1, #3
+                    AND R2, R2, #0
+            LOOP:   LDW R3, R0, #0
+                    ADD R2, R2, R3
+                    ADD R0, R0, #2
+                    SUB R1, R1, #1
+                    BRp LOOP
+            DONE    BR DONE
+            DATA    .FILL #10
+                    .FILL x20
+                    .FILL DONE
+                    .END
+                    this is not assembled
+            ",
+        )
+        .unwrap();
+
+        let done = program.symbols["DONE"];
+        assert_eq!(done, RESET_PC + 16);
+
+        let mut lc3b = LC3B::new(&program.image);
+        while lc3b.pc != done {
+            lc3b.step().unwrap();
+        }
+
+        assert_eq!(lc3b.reg_file[2], 10 + 0x20 + done);
+    }
+
+    #[test]
+    fn honours_orig_and_blkw() {
+        let program = assemble(
+            "
+            .ORIG x10
+            .BLKW 2
+            NOP
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.image.len(), 0x16);
+        assert!(program.image.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let err = |src| assemble(src).unwrap_err().to_string();
+
+        assert_eq!(
+            err("NOP\nADD R0, R1, #8"),
+            "line 2: 8 doesn't fit in 4 bits (expected -8 to 7)"
+        );
+        assert_eq!(err("BRz NOWHERE"), "line 1: unknown label `NOWHERE`");
+        assert_eq!(err("A NOP\nA NOP"), "line 2: duplicate label `A`");
+        assert_eq!(err("ADDD R1, R1, #1"), "line 1: unknown instruction `ADDD`");
+        assert_eq!(err("A: ADDD R1"), "line 1: unknown instruction `ADDD`");
+        assert_eq!(err("NOP\nRETT"), "line 2: unknown instruction `RETT`");
+        assert_eq!(
+            err("ADD R0, R1"),
+            "line 1: ADD expects 3 operand(s), found 2"
+        );
+        assert_eq!(
+            err("LDW R8, R0, #0"),
+            "line 1: expected a register, found `R8`"
+        );
+        assert_eq!(
+            err("NOP\n.BLKW #2147483647"),
+            "line 2: program extends past the end of memory"
+        );
+        assert_eq!(
+            err(".BLKW x7FFF\nNOP"),
+            "line 2: program extends past the end of memory"
+        );
+        assert_eq!(
+            err(".BLKW x7FFF\nEND:"),
+            "line 2: label `END` is past the end of memory"
+        );
+    }
+}
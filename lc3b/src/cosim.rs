@@ -0,0 +1,532 @@
+use std::fmt;
+
+use logic_paint_rs::{
+    module::{Module, ModuleGpio, ModuleGpioHandle, ModuleRam},
+    substrate::{execution_context::SimState, snapshot::SnapshotError},
+};
+
+use crate::{LC3B, OpCode};
+
+const PC_GPIO: usize = 8 * 16;
+const N_GPIO: usize = PC_GPIO + 16;
+const Z_GPIO: usize = N_GPIO + 1;
+const P_GPIO: usize = N_GPIO + 2;
+const RETIRE_GPIO: usize = N_GPIO + 3;
+
+/// Snapshots keep the model's memory by the page, and only the pages that differ from the image.
+/// The whole 64 KB every clock would take megabytes across a snapshot ring.
+const PAGE_SIZE: usize = 256;
+
+/// The model without its memory, as snapshotted.
+type Registers = (
+    ([u16; 8], u16, u16, u16),
+    (bool, bool, bool),
+    (u16, u16, u16),
+    (u8, Option<OpCode>, u64, u64),
+);
+
+/// The first point where the painted CPU and the reference model disagree.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct Divergence {
+    /// How many instructions retired before this one.
+    pub instruction: u64,
+
+    /// The address of the diverging instruction.
+    pub pc: u16,
+    pub ir: u16,
+
+    pub mismatch: Mismatch,
+}
+
+/// How the painted CPU and the model disagree.
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum Mismatch {
+    /// The model can't decode the instruction, so there is nothing to compare the CPU against.
+    IllegalInstruction,
+
+    /// A field of architectural state differs: `R0` through `R7`, `PC`, `N`, `Z` or `P`.
+    Field {
+        name: String,
+        expected: u16,
+        actual: u16,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instruction {} ({:#06x} at {:#06x}): ",
+            self.instruction, self.ir, self.pc
+        )?;
+
+        match &self.mismatch {
+            Mismatch::IllegalInstruction => write!(f, "illegal instruction"),
+            Mismatch::Field {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} expected {:#06x} but was {:#06x}",
+                name, expected, actual
+            ),
+        }
+    }
+}
+
+/// Runs the reference model in lock-step with a painted CPU. The CPU's architectural state is
+/// read off sockets (see `socket_names`): `R{r}_{bit}` for the register file, `PC{bit}` and `N`,
+/// `Z`, `P`, all least significant bit 0. The CPU pulses `RETIRE` once per instruction, after its
+/// results are visible on those sockets, at which point the model executes the same instruction
+/// and compares. Only the first divergence is kept, comparison stops after it.
+///
+/// The painted CPU must expose every one of those sockets, or compiling fails with
+/// `CompilerError::MissingBondWire`. `misc/cpu.lpbp` is a legacy blueprint of painted cells only
+/// and has no sockets at all, so they have to be placed on its register file, PC, condition codes
+/// and a retire strobe before it can run against the model. Until then this has never run in
+/// lock-step with a painted CPU, only against sockets driven by the tests.
+pub struct ModuleCoSim {
+    gpios: Vec<ModuleGpio>,
+    /// The memory the model was reset with.
+    image: Box<[u8; 65_536]>,
+    pub model: LC3B,
+    pub divergence: Option<Divergence>,
+}
+
+impl ModuleCoSim {
+    /// `image` must match the memory image loaded into the painted CPU.
+    pub fn new(image: &[u8]) -> Self {
+        let gpios = Self::socket_names()
+            .into_iter()
+            .enumerate()
+            .map(|(handle, name)| ModuleGpio {
+                handle,
+                name,
+                bonding: None,
+                trigger: handle == RETIRE_GPIO,
+                si_output_high: false,
+                si_input_high: false,
+            })
+            .collect();
+
+        let model = LC3B::new(image);
+        Self {
+            gpios,
+            image: model.memory.clone(),
+            model,
+            divergence: None,
+        }
+    }
+
+    /// The sockets a painted CPU must expose, in GPIO order: `R0_0` through `R7_15`, `PC0`
+    /// through `PC15`, `N`, `Z`, `P` and `RETIRE`.
+    pub fn socket_names() -> Vec<String> {
+        let mut names: Vec<_> = (0..8)
+            .flat_map(|r| (0..16).map(move |bit| format!("R{}_{}", r, bit)))
+            .collect();
+        names.extend((0..16).map(|bit| format!("PC{}", bit)));
+        names.extend(["N", "Z", "P", "RETIRE"].map(String::from));
+        names
+    }
+
+    fn read_word(&self, first_handle: usize) -> u16 {
+        (0..16).fold(0, |v, bit| {
+            v | ((self.gpios[first_handle + bit].si_output_high as u16) << bit)
+        })
+    }
+
+    /// Executes one instruction on the model and compares it against the CPU.
+    fn check_instruction(&mut self) {
+        let instruction = self.model.instructions;
+        let pc = self.model.pc;
+
+        let diverge = |ir: u16, mismatch: Mismatch| Divergence {
+            instruction,
+            pc,
+            ir,
+            mismatch,
+        };
+
+        if let Err(crate::Error::IllegalInstruction(ir)) = self.model.step() {
+            self.divergence = Some(diverge(ir, Mismatch::IllegalInstruction));
+            return;
+        }
+
+        let ir = self.model.ir;
+        let field = |name: &str, expected: u16, actual: u16| Mismatch::Field {
+            name: name.to_string(),
+            expected,
+            actual,
+        };
+        let mut expected = vec![];
+        for r in 0..8 {
+            expected.push((format!("R{}", r), self.model.reg_file[r], r * 16));
+        }
+        expected.push(("PC".to_string(), self.model.pc, PC_GPIO));
+
+        for (name, value, first_handle) in expected {
+            let actual = self.read_word(first_handle);
+            if actual != value {
+                self.divergence = Some(diverge(ir, field(&name, value, actual)));
+                return;
+            }
+        }
+
+        for (name, value, handle) in [
+            ("N", self.model.n, N_GPIO),
+            ("Z", self.model.z, Z_GPIO),
+            ("P", self.model.p, P_GPIO),
+        ] {
+            let actual = self.gpios[handle].si_output_high;
+            if actual != value {
+                self.divergence = Some(diverge(ir, field(name, value as u16, actual as u16)));
+                return;
+            }
+        }
+    }
+}
+
+impl Module for ModuleCoSim {
+    fn get_name(&self) -> &str {
+        "LC-3b Co-Sim"
+    }
+
+    fn reset(&mut self) {
+        self.model = LC3B::new(&self.image[..]);
+        self.divergence = None;
+    }
+
+    fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+        &mut self.gpios
+    }
+
+    fn trigger(&mut self, _sim_state: &SimState, handles: Vec<ModuleGpioHandle>) {
+        let retired = handles.contains(&RETIRE_GPIO) && self.gpios[RETIRE_GPIO].si_output_high;
+        if retired && self.divergence.is_none() {
+            self.check_instruction();
+        }
+    }
+
+    /// The model lives outside the GPIOs, so it's snapshotted too, keeping it in step with the
+    /// painted CPU across a rewind. Only the memory pages written since reset are kept.
+    fn save_state(&self) -> Vec<u8> {
+        // Destructured so that a new model field can't be left out.
+        let LC3B {
+            reg_file,
+            pc,
+            ir,
+            mar,
+            n,
+            z,
+            p,
+            d_bus,
+            a_bus,
+            b_bus,
+            memory,
+            state,
+            opcode,
+            cycles,
+            instructions,
+        } = &self.model;
+
+        let registers: Registers = (
+            (*reg_file, *pc, *ir, *mar),
+            (*n, *z, *p),
+            (*d_bus, *a_bus, *b_bus),
+            (*state, *opcode, *cycles, *instructions),
+        );
+        let pages: Vec<(usize, &[u8])> = memory
+            .chunks(PAGE_SIZE)
+            .zip(self.image.chunks(PAGE_SIZE))
+            .enumerate()
+            .filter(|(_, (page, image))| page != image)
+            .map(|(i, (page, _))| (i, page))
+            .collect();
+
+        bincode::encode_to_vec(
+            (registers, pages, &self.divergence),
+            bincode::config::standard(),
+        )
+        .unwrap()
+    }
+
+    fn load_state(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let (registers, pages, divergence): (Registers, Vec<(usize, Vec<u8>)>, Option<Divergence>) =
+            bincode::decode_from_slice(bytes, bincode::config::standard())?.0;
+
+        let mut memory = self.image.clone();
+        for (i, page) in pages {
+            memory
+                .get_mut(i * PAGE_SIZE..)
+                .and_then(|memory| memory.get_mut(..page.len()))
+                .ok_or(SnapshotError::Mismatch)?
+                .copy_from_slice(&page);
+        }
+
+        let (
+            (reg_file, pc, ir, mar),
+            (n, z, p),
+            (d_bus, a_bus, b_bus),
+            (state, opcode, cycles, instructions),
+        ) = registers;
+        self.model = LC3B {
+            reg_file,
+            pc,
+            ir,
+            mar,
+            n,
+            z,
+            p,
+            d_bus,
+            a_bus,
+            b_bus,
+            memory,
+            state,
+            opcode,
+            cycles,
+            instructions,
+        };
+        self.divergence = divergence;
+        Ok(())
+    }
+}
+
+/// Loads a (byte addressed) memory image into a RAM or ROM module. Each RAM word holds
+/// `data_width / 8` consecutive bytes, big-endian like the LC-3b itself, so a 16 bit wide RAM is
+/// addressed by word (byte address >> 1) and an 8 bit wide one by byte.
+pub fn load_ram(ram: &mut ModuleRam, image: &[u8]) {
+    let data_width = ram.data_width();
+    assert!(
+        data_width >= 8 && data_width.is_multiple_of(8),
+        "RAM words must be whole bytes"
+    );
+
+    let words: Vec<u64> = image
+        .chunks(data_width / 8)
+        .map(|bytes| {
+            (0..data_width / 8).fold(0, |word, i| {
+                (word << 8) | *bytes.get(i).unwrap_or(&0) as u64
+            })
+        })
+        .collect();
+
+    ram.load_image(&words);
+}
+
+#[cfg(test)]
+mod tests {
+    use logic_paint_rs::{
+        Simulator,
+        simulator::SimulatorError,
+        substrate::{buffer::Buffer, execution_context::CompilerError},
+        utils::convert::import_legacy_blueprint,
+    };
+
+    use super::*;
+
+    /// Plays the part of the painted CPU: copies a model's state onto the GPIOs and retires.
+    fn retire(cosim: &mut ModuleCoSim, cpu: &LC3B) {
+        let gpios = cosim.get_gpios_mut();
+        for r in 0..8 {
+            for bit in 0..16 {
+                gpios[r * 16 + bit].si_output_high = (cpu.reg_file[r] >> bit) & 1 == 1;
+            }
+        }
+        for bit in 0..16 {
+            gpios[PC_GPIO + bit].si_output_high = (cpu.pc >> bit) & 1 == 1;
+        }
+        gpios[N_GPIO].si_output_high = cpu.n;
+        gpios[Z_GPIO].si_output_high = cpu.z;
+        gpios[P_GPIO].si_output_high = cpu.p;
+        gpios[RETIRE_GPIO].si_output_high = true;
+
+        cosim.trigger(&SimState::default(), vec![RETIRE_GPIO]);
+    }
+
+    #[test]
+    fn reports_first_divergence() {
+        let image = [0, 0, 0xA0, 0x05, 0x12, 0x23, 0x14, 0x23];
+        let mut cosim = ModuleCoSim::new(&image);
+        let mut cpu = LC3B::new(&image);
+
+        // LDI R0, #5 matches.
+        cpu.step().unwrap();
+        retire(&mut cosim, &cpu);
+        assert_eq!(cosim.divergence, None);
+
+        // ADD R1, R0, #3, but the CPU gets it wrong.
+        cpu.step().unwrap();
+        cpu.reg_file[1] = 7;
+        retire(&mut cosim, &cpu);
+
+        let divergence = cosim.divergence.clone().unwrap();
+        assert_eq!(divergence.instruction, 1);
+        assert_eq!(divergence.pc, 0x0004);
+        assert_eq!(
+            divergence.mismatch,
+            Mismatch::Field {
+                name: "R1".to_string(),
+                expected: 8,
+                actual: 7
+            }
+        );
+        assert_eq!(
+            divergence.to_string(),
+            "instruction 1 (0x1223 at 0x0004): R1 expected 0x0008 but was 0x0007"
+        );
+
+        // Comparison stops after the first divergence.
+        cpu.step().unwrap();
+        retire(&mut cosim, &cpu);
+        assert_eq!(cosim.divergence, Some(divergence));
+    }
+
+    #[test]
+    fn snapshots_the_model() {
+        let image = [0, 0, 0xA0, 0x05, 0x12, 0x23];
+        let mut cosim = ModuleCoSim::new(&image);
+        let mut cpu = LC3B::new(&image);
+
+        cpu.step().unwrap();
+        retire(&mut cosim, &cpu);
+        cosim.model.memory[0x3000] = 0xAB;
+        let state = cosim.save_state();
+
+        // Only the written page is kept, not the whole memory.
+        assert!(state.len() < 2 * PAGE_SIZE, "{} bytes", state.len());
+
+        cpu.step().unwrap();
+        cpu.reg_file[1] = 7;
+        retire(&mut cosim, &cpu);
+        assert!(cosim.divergence.is_some());
+        cosim.model.memory[0x3000] = 0;
+        cosim.model.memory[0x4000] = 0xCD;
+
+        // Rewinding to before the second instruction rewinds the model with it.
+        cosim.load_state(&state).unwrap();
+        assert_eq!(cosim.divergence, None);
+        assert_eq!(cosim.model.instructions, 1);
+        assert_eq!(cosim.model.pc, 0x0004);
+        assert_eq!(cosim.model.memory[0x3000], 0xAB);
+        assert_eq!(cosim.model.memory[0x4000], 0);
+        assert_eq!(cosim.model.memory[..6], image);
+    }
+
+    #[test]
+    fn reports_illegal_instructions() {
+        // Opcode 0xF is unassigned, so the model can't decode it.
+        let image = [0, 0, 0xF0, 0x25];
+        let mut cosim = ModuleCoSim::new(&image);
+        retire(&mut cosim, &LC3B::new(&image));
+
+        let divergence = cosim.divergence.clone().unwrap();
+        assert_eq!((divergence.pc, divergence.ir), (0x0002, 0xF025));
+        assert_eq!(divergence.mismatch, Mismatch::IllegalInstruction);
+        assert_eq!(
+            divergence.to_string(),
+            "instruction 0 (0xf025 at 0x0002): illegal instruction"
+        );
+    }
+
+    /// A socket (on a short metal wire) for each of the co-sim's GPIOs, standing in for the
+    /// painted CPU. The test drives them through the Simulator's socket driver.
+    fn socket_board() -> Buffer {
+        let mut buffer = Buffer::default();
+        for (i, name) in ModuleCoSim::socket_names().into_iter().enumerate() {
+            let x = i as i32 * 2;
+            buffer.draw_metal((x, 0).into(), (x, 1).into(), false);
+            buffer.set_socket((x, 0).into(), Some(name));
+        }
+        buffer
+    }
+
+    /// Drives a model's state onto the board's sockets, then pulses RETIRE.
+    fn retire_through_substrate(sim: &mut Simulator, cpu: &LC3B) {
+        let mut words: Vec<_> = (0..8)
+            .map(|r| (format!("R{}_", r), cpu.reg_file[r]))
+            .collect();
+        words.push(("PC".to_string(), cpu.pc));
+        for (prefix, value) in words {
+            for bit in 0..16 {
+                let name = format!("{}{}", prefix, bit);
+                sim.set_input(&name, (value >> bit) & 1 == 1).unwrap();
+            }
+        }
+        for (name, high) in [("N", cpu.n), ("Z", cpu.z), ("P", cpu.p)] {
+            sim.set_input(name, high).unwrap();
+        }
+
+        // Each clock ticks until the substrate settles, so the level reaches the co-sim in one.
+        sim.set_input("RETIRE", true).unwrap();
+        sim.clock(1).unwrap();
+        sim.set_input("RETIRE", false).unwrap();
+        sim.clock(1).unwrap();
+    }
+
+    #[test]
+    fn bonds_to_sockets_and_compares_through_the_substrate() {
+        let image = [0, 0, 0xA0, 0x05, 0x12, 0x23, 0x14, 0x23];
+        let cosim = ModuleCoSim::new(&image);
+        let mut sim = Simulator::with_modules(&socket_board(), vec![Box::new(cosim)]).unwrap();
+        let mut cpu = LC3B::new(&image);
+        let divergence = |sim: &Simulator| {
+            let module = sim.context().modules[0].as_any();
+            module
+                .downcast_ref::<ModuleCoSim>()
+                .unwrap()
+                .divergence
+                .clone()
+        };
+
+        cpu.step().unwrap();
+        retire_through_substrate(&mut sim, &cpu);
+        cpu.step().unwrap();
+        retire_through_substrate(&mut sim, &cpu);
+        assert_eq!(divergence(&sim), None);
+
+        cpu.step().unwrap();
+        cpu.z = true;
+        retire_through_substrate(&mut sim, &cpu);
+
+        let divergence = divergence(&sim).unwrap();
+        assert_eq!(divergence.instruction, 2);
+        assert!(matches!(divergence.mismatch, Mismatch::Field { name, .. } if name == "Z"));
+    }
+
+    #[test]
+    fn missing_sockets_fail_to_bond() {
+        let mut buffer = socket_board();
+        buffer.set_socket((0, 0).into(), None);
+
+        let cosim = ModuleCoSim::new(&[]);
+        assert!(Simulator::with_modules(&buffer, vec![Box::new(cosim)]).is_err());
+    }
+
+    /// Fails once `misc/cpu.lpbp` gains the co-sim's sockets, to be replaced by a test running it
+    /// in lock-step with the model.
+    #[test]
+    fn painted_cpu_has_no_cosim_sockets_yet() {
+        let json = include_str!("../../misc/cpu.lpbp").to_string();
+        let buffer = import_legacy_blueprint(json).unwrap();
+
+        let cosim = ModuleCoSim::new(&[]);
+        assert!(matches!(
+            Simulator::with_modules(&buffer, vec![Box::new(cosim)]),
+            Err(SimulatorError::Compiler(CompilerError::MissingBondWire(name))) if name == "R0_0"
+        ));
+    }
+
+    #[test]
+    fn loads_assembled_programs_into_ram() {
+        let program = crate::asm::assemble("LDI R0, #5\nADD R1, R0, #3").unwrap();
+
+        let mut ram = ModuleRam::new("M", 4, 16);
+        load_ram(&mut ram, &program.image);
+        assert_eq!(&ram.memory()[..4], &[0, 0xA005, 0x1223, 0]);
+
+        let mut ram = ModuleRam::new("M", 4, 8);
+        load_ram(&mut ram, &program.image);
+        assert_eq!(&ram.memory()[..7], &[0, 0, 0xA0, 0x05, 0x12, 0x23, 0]);
+    }
+}
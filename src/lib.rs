@@ -3,16 +3,19 @@ use std::time::Duration;
 use wasm_bindgen::prelude::*;
 use wasm_thread as thread;
 
-mod coords;
-mod module;
+pub mod coords;
+pub mod module;
 mod project;
-mod substrate;
+pub mod simulator;
+pub mod substrate;
 mod tools;
-mod upc;
-mod utils;
+pub mod upc;
+pub mod utils;
 mod viewport;
 mod wgl2;
 
+pub use simulator::{Simulator, SimulatorError};
+
 #[wasm_bindgen(start)]
 pub fn main() {
     #[cfg(feature = "console_error_panic_hook")]
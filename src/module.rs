@@ -1,4 +1,6 @@
-use crate::substrate::execution_context::SimState;
+use std::any::Any;
+
+use crate::substrate::{execution_context::SimState, snapshot::SnapshotError};
 
 pub type ModuleGpioHandle = usize;
 
@@ -14,9 +16,8 @@ pub struct ModuleGpio {
     /// The 'bond wire' that connects this GPIO to a substrate Socket.
     pub bonding: Option<String>,
 
-    /// When set to true, the module will be notified (at the completion of the tick) when the
-    /// `si_output_high` transitions from false to true. There is no way to trigger on a falling
-    /// edge, just use an inverter infront of the socket.
+    /// When set to true, the module will be notified (at the completion of the tick) whenever
+    /// `si_output_high` changes, rising or falling. Check `si_output_high` for the new level.
     pub trigger: bool,
 
     // Set to true when the substrate is driving the pin high.
@@ -26,7 +27,24 @@ pub struct ModuleGpio {
     pub si_input_high: bool,
 }
 
-pub trait Module {
+/// Allows downcasting a module to its concrete type, for reading back state like probe samples.
+/// Implemented for every module, so implementors needn't do anything.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Module + Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Module: AsAny {
     /// Provides the human-readable name for this module.
     fn get_name(&self) -> &str;
 
@@ -47,6 +65,20 @@ pub trait Module {
     fn clock(&mut self, sim_state: &SimState) {
         let _ = sim_state;
     }
+
+    /// Serializes internal state for a simulation snapshot. GPIO levels are snapshotted for every
+    /// module, so modules without any other state needn't implement this. Any module that does
+    /// keep state outside its GPIOs (memory, a model, counters) must, or rewinding will silently
+    /// leave that state behind.
+    fn save_state(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Restores internal state from bytes returned by `save_state`.
+    fn load_state(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let _ = bytes;
+        Ok(())
+    }
 }
 
 // ==== Clock module ==============================================================================
@@ -83,7 +115,427 @@ impl Module for ModuleClock {
         &mut self.gpios
     }
 
-    fn clock(&mut self, sim_state: &SimState) {
-        self.gpios[0].si_input_high = sim_state.ticks % 2 == 0;
+    fn clock(&mut self, _sim_state: &SimState) {
+        self.gpios[0].si_input_high = !self.gpios[0].si_input_high;
+    }
+}
+
+/// Creates `count` GPIOs named `{prefix}0` through `{prefix}{count - 1}`, with handles starting
+/// at `first_handle`.
+fn make_bus(prefix: &str, count: usize, first_handle: usize, trigger: bool) -> Vec<ModuleGpio> {
+    (0..count)
+        .map(|i| ModuleGpio {
+            handle: first_handle + i,
+            name: format!("{}{}", prefix, i),
+            bonding: None,
+            trigger,
+            si_output_high: false,
+            si_input_high: false,
+        })
+        .collect()
+}
+
+/// Reads a bus (least significant bit first) off the substrate.
+fn read_bus(gpios: &[ModuleGpio]) -> u64 {
+    gpios
+        .iter()
+        .enumerate()
+        .fold(0, |v, (i, gpio)| v | ((gpio.si_output_high as u64) << i))
+}
+
+/// Drives a bus (least significant bit first) into the substrate.
+fn write_bus(gpios: &mut [ModuleGpio], value: u64) {
+    for (i, gpio) in gpios.iter_mut().enumerate() {
+        gpio.si_input_high = (value >> i) & 1 == 1;
+    }
+}
+
+// ==== Const module ==============================================================================
+/// Drives a fixed bit pattern onto GPIOs `{prefix}0` (the LSB) through `{prefix}{width - 1}`.
+pub struct ModuleConst {
+    gpios: Vec<ModuleGpio>,
+    value: u64,
+}
+
+impl ModuleConst {
+    pub fn new(prefix: &str, width: usize, value: u64) -> Self {
+        assert!(width <= 64, "const modules are at most 64 bits wide");
+
+        let mut module = Self {
+            gpios: make_bus(prefix, width, 0, false),
+            value,
+        };
+        module.reset();
+        module
+    }
+
+    pub fn get_value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn set_value(&mut self, value: u64) {
+        self.value = value;
+        write_bus(&mut self.gpios, value);
+    }
+}
+
+impl Module for ModuleConst {
+    fn get_name(&self) -> &str {
+        "Const"
+    }
+
+    fn reset(&mut self) {
+        write_bus(&mut self.gpios, self.value);
+    }
+
+    fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+        &mut self.gpios
+    }
+}
+
+// ==== Probe module ==============================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ProbeEdge {
+    pub tick: usize,
+    pub handle: ModuleGpioHandle,
+    pub rising: bool,
+}
+
+/// Observes GPIOs `{prefix}0` (the LSB) through `{prefix}{width - 1}` without driving them. Every
+/// edge is recorded as it happens, and the bus value is sampled once per clock.
+pub struct ModuleProbe {
+    gpios: Vec<ModuleGpio>,
+    pub edges: Vec<ProbeEdge>,
+    pub samples: Vec<u64>,
+}
+
+impl ModuleProbe {
+    pub fn new(prefix: &str, width: usize) -> Self {
+        assert!(width <= 64, "probe modules are at most 64 bits wide");
+
+        Self {
+            gpios: make_bus(prefix, width, 0, true),
+            edges: vec![],
+            samples: vec![],
+        }
+    }
+
+    /// The current value of the probed bus.
+    pub fn get_value(&self) -> u64 {
+        read_bus(&self.gpios)
+    }
+}
+
+impl Module for ModuleProbe {
+    fn get_name(&self) -> &str {
+        "Probe"
+    }
+
+    fn reset(&mut self) {
+        self.edges.clear();
+        self.samples.clear();
+    }
+
+    fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+        &mut self.gpios
+    }
+
+    fn trigger(&mut self, sim_state: &SimState, handles: Vec<ModuleGpioHandle>) {
+        for handle in handles {
+            self.edges.push(ProbeEdge {
+                tick: sim_state.ticks,
+                handle,
+                rising: self.gpios[handle].si_output_high,
+            });
+        }
+    }
+
+    fn clock(&mut self, _sim_state: &SimState) {
+        self.samples.push(self.get_value());
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        bincode::encode_to_vec((&self.edges, &self.samples), bincode::config::standard()).unwrap()
+    }
+
+    fn load_state(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let (edges, samples): (Vec<ProbeEdge>, Vec<u64>) =
+            bincode::decode_from_slice(bytes, bincode::config::standard())?.0;
+        self.edges = edges;
+        self.samples = samples;
+        Ok(())
+    }
+}
+
+// ==== RAM module ================================================================================
+/// A synchronous RAM (or ROM) of `2^address_width` words. Each clock the address bus
+/// `{prefix}A0..` is read; when `{prefix}WE` is high (and the module isn't read-only) the data-in
+/// bus `{prefix}DI0..` is written to that address. The word at the address is then driven onto
+/// the data-out bus `{prefix}DO0..`. All buses are least significant bit first.
+pub struct ModuleRam {
+    gpios: Vec<ModuleGpio>,
+    address_width: usize,
+    data_width: usize,
+    read_only: bool,
+    memory: Vec<u64>,
+    image: Vec<u64>,
+}
+
+impl ModuleRam {
+    pub fn new(prefix: &str, address_width: usize, data_width: usize) -> Self {
+        assert!(
+            address_width <= 24,
+            "RAM modules have at most 24 address lines"
+        );
+        assert!(data_width <= 64, "RAM modules are at most 64 bits wide");
+
+        let mut gpios = make_bus(&format!("{}A", prefix), address_width, 0, false);
+        gpios.extend(make_bus(
+            &format!("{}DI", prefix),
+            data_width,
+            address_width,
+            false,
+        ));
+        gpios.extend(make_bus(
+            &format!("{}DO", prefix),
+            data_width,
+            address_width + data_width,
+            false,
+        ));
+        gpios.push(ModuleGpio {
+            handle: address_width + data_width * 2,
+            name: format!("{}WE", prefix),
+            bonding: None,
+            trigger: false,
+            si_output_high: false,
+            si_input_high: false,
+        });
+
+        Self {
+            gpios,
+            address_width,
+            data_width,
+            read_only: false,
+            memory: vec![0; 1 << address_width],
+            image: vec![],
+        }
+    }
+
+    /// Creates a ROM, which ignores write-enable and is initialized with `image`.
+    pub fn new_rom(prefix: &str, address_width: usize, data_width: usize, image: &[u64]) -> Self {
+        let mut rom = Self::new(prefix, address_width, data_width);
+        rom.read_only = true;
+        rom.load_image(image);
+        rom
+    }
+
+    /// Sets the memory image (starting at address zero) the module holds now and after each
+    /// reset. Words beyond the image are zero, words beyond the address space are dropped.
+    pub fn load_image(&mut self, image: &[u64]) {
+        let mask = self.data_mask();
+        self.image = image
+            .iter()
+            .take(self.memory.len())
+            .map(|w| w & mask)
+            .collect();
+        self.reset();
+    }
+
+    pub fn address_width(&self) -> usize {
+        self.address_width
+    }
+
+    pub fn data_width(&self) -> usize {
+        self.data_width
+    }
+
+    pub fn memory(&self) -> &[u64] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u64] {
+        &mut self.memory
+    }
+
+    fn data_mask(&self) -> u64 {
+        if self.data_width == 64 {
+            u64::MAX
+        } else {
+            (1 << self.data_width) - 1
+        }
+    }
+}
+
+impl Module for ModuleRam {
+    fn get_name(&self) -> &str {
+        if self.read_only {
+            "ROM"
+        } else {
+            "RAM"
+        }
+    }
+
+    fn reset(&mut self) {
+        self.memory.fill(0);
+        self.memory[..self.image.len()].copy_from_slice(&self.image);
+        for gpio in &mut self.gpios {
+            gpio.si_input_high = false;
+        }
+    }
+
+    fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+        &mut self.gpios
+    }
+
+    fn clock(&mut self, _sim_state: &SimState) {
+        let (a, d) = (self.address_width, self.data_width);
+        let address = read_bus(&self.gpios[..a]) as usize;
+
+        if !self.read_only && self.gpios[a + d * 2].si_output_high {
+            self.memory[address] = read_bus(&self.gpios[a..a + d]);
+        }
+
+        write_bus(&mut self.gpios[a + d..a + d * 2], self.memory[address]);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        bincode::encode_to_vec(&self.memory, bincode::config::standard()).unwrap()
+    }
+
+    fn load_state(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let memory: Vec<u64> = bincode::decode_from_slice(bytes, bincode::config::standard())?.0;
+        if memory.len() != self.memory.len() {
+            return Err(SnapshotError::Mismatch);
+        }
+        self.memory = memory;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        simulator::fixtures::draw_wire,
+        substrate::{buffer::Buffer, execution_context::ExecutionContext},
+    };
+
+    fn set_bus(module: &mut dyn Module, prefix: &str, value: u64) {
+        for gpio in module.get_gpios_mut() {
+            if let Some(bit) = gpio.name.strip_prefix(prefix) {
+                if let Ok(bit) = bit.parse::<u32>() {
+                    gpio.si_output_high = (value >> bit) & 1 == 1;
+                }
+            }
+        }
+    }
+
+    fn driven(gpios: &[ModuleGpio]) -> u64 {
+        gpios
+            .iter()
+            .enumerate()
+            .fold(0, |v, (i, gpio)| v | ((gpio.si_input_high as u64) << i))
+    }
+
+    #[test]
+    fn const_drives_bit_pattern() {
+        let mut module = ModuleConst::new("K", 4, 0b1010);
+        let driven: Vec<_> = module
+            .get_gpios_mut()
+            .iter()
+            .map(|g| (g.name.clone(), g.si_input_high))
+            .collect();
+        assert_eq!(
+            driven,
+            vec![
+                ("K0".to_string(), false),
+                ("K1".to_string(), true),
+                ("K2".to_string(), false),
+                ("K3".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn clock_toggles_every_clock() {
+        let mut buffer = Buffer::new();
+        draw_wire(&mut buffer, 0, 2, "CLK", None);
+        let mut ctx =
+            ExecutionContext::compile_from_buffer(&buffer, vec![Box::new(ModuleClock::default())])
+                .unwrap();
+
+        // However many ticks each clock takes to settle.
+        let mut levels = vec![];
+        for _ in 0..4 {
+            ctx.clock_once().unwrap();
+            levels.push(ctx.state.trace_states[1]);
+        }
+        assert_eq!(levels, [true, false, true, false]);
+    }
+
+    #[test]
+    fn probe_records_edges_through_substrate() {
+        let mut buffer = Buffer::new();
+        draw_wire(&mut buffer, 0, 10, "IN", Some("OUT"));
+
+        let mut probe = ModuleProbe::new("P", 1);
+        probe.get_gpios_mut()[0].bonding = Some("OUT".to_string());
+        let mut input = ModuleConst::new("K", 1, 1);
+        input.get_gpios_mut()[0].bonding = Some("IN".to_string());
+
+        let mut ctx =
+            ExecutionContext::compile_from_buffer(&buffer, vec![Box::new(input), Box::new(probe)])
+                .unwrap();
+
+        // Tick 0 ignores module inputs, so the wire goes high on tick 1.
+        for _ in 0..3 {
+            ctx.tick_once();
+        }
+
+        let probe = ctx.modules[1]
+            .as_any()
+            .downcast_ref::<ModuleProbe>()
+            .unwrap();
+        assert_eq!(probe.get_value(), 1);
+        assert_eq!(
+            probe.edges,
+            vec![ProbeEdge {
+                tick: 1,
+                handle: 0,
+                rising: true
+            }]
+        );
+    }
+
+    #[test]
+    fn ram_reads_and_writes() {
+        let state = SimState::default();
+        let mut ram = ModuleRam::new("", 4, 8);
+        ram.load_image(&[0x11, 0x22, 0x1FF]);
+        assert_eq!(&ram.memory()[..4], &[0x11, 0x22, 0xFF, 0]);
+
+        // Read address 1.
+        set_bus(&mut ram, "A", 1);
+        ram.clock(&state);
+        assert_eq!(driven(&ram.gpios[12..20]), 0x22);
+
+        // Write address 3, which also reads it back.
+        set_bus(&mut ram, "A", 3);
+        set_bus(&mut ram, "DI", 0xAB);
+        ram.gpios[20].si_output_high = true;
+        ram.clock(&state);
+        assert_eq!(ram.memory()[3], 0xAB);
+        assert_eq!(driven(&ram.gpios[12..20]), 0xAB);
+
+        // Reset restores the image.
+        ram.reset();
+        assert_eq!(ram.memory()[3], 0);
+
+        // ROMs ignore writes.
+        let mut rom = ModuleRam::new_rom("", 4, 8, &[7]);
+        rom.gpios[20].si_output_high = true;
+        set_bus(&mut rom, "DI", 0xAB);
+        rom.clock(&state);
+        assert_eq!(rom.memory()[0], 7);
     }
 }
@@ -1,10 +1,24 @@
 use crate::{
     coords::CellCoord,
+    error, log,
     module::Module,
-    substrate::{buffer::Buffer, execution_context::ExecutionContext, io::IoState, mask::Mask},
+    simulator::truth_table::{TruthTable, TruthTableError},
+    substrate::{
+        breakpoint::{BreakpointError, Condition},
+        buffer::Buffer,
+        buffer_delta::BufferDelta,
+        compiler::CompilerResults,
+        execution_context::ExecutionContext,
+        history::History,
+        io::IoState,
+        mask::Mask,
+        scheduler::DEFAULT_PARALLEL_THRESHOLD,
+        snapshot::DEFAULT_SNAPSHOT_CAPACITY,
+    },
     tools::{
         camera_controller::ToolCameraController, draw_metal::ToolPaintMetal, draw_si::ToolPaintSi,
-        place_socket::ToolPlaceSocket, visual::ToolVisual, Tool, ToolInput, ToolOutput,
+        execute::ToolExecute, place_socket::ToolPlaceSocket, visual::ToolVisual, ExecutionCommand,
+        Tool, ToolInput, ToolOutput,
     },
     utils::Selection,
     wgl2::Camera,
@@ -42,6 +56,28 @@ pub struct Project {
 
     /// When set, buffer must be static.
     execution_context: Option<ExecutionContext>,
+
+    /// The results of the last compile and the buffer they were compiled from, kept after
+    /// execution stops so the next start only recompiles what was edited since.
+    last_compile: Option<(Buffer, CompilerResults)>,
+
+    /// When set (and executing), `clocks_per_frame` fundamental clocks are executed each frame.
+    running: bool,
+
+    /// The number of fundamental clocks executed per frame in run mode.
+    clocks_per_frame: usize,
+
+    /// How many threads execution propagates on (see `ExecutionContext::set_threads`).
+    threads: usize,
+
+    /// Undo/redo stacks, recorded each time a tool checkpoints the buffer.
+    history: History,
+
+    /// Breakpoint conditions, added to each execution context as it starts.
+    breakpoints: Vec<Condition>,
+
+    /// Descriptions of the breakpoints that last paused execution.
+    breakpoint_hits: Vec<String>,
 }
 
 #[wasm_bindgen(getter_with_clone)]
@@ -73,9 +109,11 @@ impl Project {
         tools.push(Box::new(ToolPaintSi::default()) as Box<dyn Tool>);
         tools.push(Box::new(ToolPaintMetal::default()) as Box<dyn Tool>);
         tools.push(Box::new(ToolPlaceSocket::default()) as Box<dyn Tool>);
+        tools.push(Box::new(ToolExecute::default()) as Box<dyn Tool>);
         tools.push(Box::new(ToolCameraController::default()) as Box<dyn Tool>);
 
         Self {
+            history: History::new(buffer.clone()),
             buffer,
             mask: Default::default(),
             selection: Default::default(),
@@ -87,7 +125,169 @@ impl Project {
             tools,
             active_tool: 0,
             execution_context: None,
+            last_compile: None,
+            running: false,
+            clocks_per_frame: 1,
+            threads: 1,
+            breakpoints: vec![],
+            breakpoint_hits: vec![],
+        }
+    }
+
+    /// Should be called once per rendered frame. While in run mode this executes
+    /// `clocks_per_frame` fundamental clocks and updates the mask with the new trace states.
+    pub fn frame(&mut self) {
+        if self.running {
+            self.clock_execution(self.clocks_per_frame);
+        }
+    }
+
+    /// Sets how many fundamental clocks run mode executes each frame (at least one).
+    pub fn set_clocks_per_frame(&mut self, clocks: usize) {
+        self.clocks_per_frame = clocks.max(1);
+    }
+
+    /// Sets how many threads execution propagates on, which helps large designs run more clocks
+    /// per frame. Threads are spawned once here, not per tick. Applies immediately when
+    /// executing, and each time execution starts.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+        if let Some(execution_context) = &mut self.execution_context {
+            execution_context.set_threads(self.threads, DEFAULT_PARALLEL_THRESHOLD);
+        }
+    }
+
+    /// Reverts the buffer to the previous checkpoint, dropping any in-progress tool edits.
+    /// Returns the restored buffer (for persisting) or None if there is nothing to undo or the
+    /// buffer is frozen for execution.
+    pub fn undo(&mut self) -> Option<Buffer> {
+        if self.execution_context.is_some() {
+            return None;
+        }
+
+        let buffer = self.history.undo()?;
+        self.restore_buffer(buffer.clone());
+        Some(buffer)
+    }
+
+    /// Re-applies the last undone checkpoint. Returns the restored buffer (for persisting) or None
+    /// if there is nothing to redo or the buffer is frozen for execution.
+    pub fn redo(&mut self) -> Option<Buffer> {
+        if self.execution_context.is_some() {
+            return None;
+        }
+
+        let buffer = self.history.redo()?;
+        self.restore_buffer(buffer.clone());
+        Some(buffer)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.execution_context.is_none() && self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.execution_context.is_none() && self.history.can_redo()
+    }
+
+    /// Caps how many cell changes the undo/redo history holds before the oldest entries are
+    /// dropped.
+    pub fn set_history_limit(&mut self, cells: usize) {
+        self.history.set_limit(cells);
+    }
+
+    /// The number of clocks that can be rewound (or scrubbed through), or zero when not
+    /// executing.
+    pub fn recorded_clocks(&self) -> usize {
+        self.execution_context
+            .as_ref()
+            .and_then(|execution_context| execution_context.snapshots.as_ref())
+            .map_or(0, |snapshots| snapshots.len().saturating_sub(1))
+    }
+
+    /// Shows the trace states from `clocks` recorded clocks ago in the mask, without changing the
+    /// simulation. Pauses run mode. Returns false if that far back isn't recorded.
+    pub fn scrub(&mut self, clocks: usize) -> bool {
+        let Some(index) = self.recorded_clocks().checked_sub(clocks) else {
+            return false;
+        };
+        let Some(execution_context) = &mut self.execution_context else {
+            return false;
+        };
+
+        self.running = false;
+        if execution_context.scrub_buffer_mask(index).is_err() {
+            return false;
+        }
+        self.mask = execution_context.buffer_mask.clone();
+        true
+    }
+
+    /// Shows how often each trace has switched since execution started as a heatmap in the mask,
+    /// with traces that never switched highlighted. Pauses run mode. Returns false if not
+    /// executing.
+    pub fn show_toggle_coverage(&mut self) -> bool {
+        let Some(execution_context) = &self.execution_context else {
+            return false;
+        };
+        let Some(toggle_coverage) = &execution_context.toggle_coverage else {
+            return false;
+        };
+
+        self.running = false;
+        self.mask = toggle_coverage.to_mask(&execution_context.compiler_results);
+        true
+    }
+
+    /// The toggle counts since execution started, as a tab separated report with the least
+    /// switched traces first. None if not executing.
+    pub fn toggle_report(&self) -> Option<String> {
+        let execution_context = self.execution_context.as_ref()?;
+        execution_context
+            .toggle_coverage
+            .as_ref()
+            .map(|t| t.to_report_string(&execution_context.compiler_results))
+    }
+
+    /// Exhaustively simulates the selected cells, with the named sockets (inside the selection)
+    /// as inputs and outputs. See `TruthTable::expressions` for minimized expressions.
+    pub fn truth_table(
+        &self,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+    ) -> Result<TruthTable, TruthTableError> {
+        TruthTable::from_selection(&self.buffer, &self.selection, inputs, outputs)
+    }
+
+    /// Sets the breakpoints that pause execution, replacing any prior ones. Each is parsed from
+    /// `rise <signal>`, `fall <signal>`, `<bus> == <value>` or `tick <count>`. Takes effect
+    /// immediately if executing. On error, nothing is changed.
+    pub fn set_breakpoints(&mut self, conditions: Vec<String>) -> Result<(), BreakpointError> {
+        let conditions = conditions
+            .iter()
+            .map(|condition| condition.parse())
+            .collect::<Result<Vec<Condition>, _>>()?;
+
+        if let Some(execution_context) = &mut self.execution_context {
+            execution_context.set_breakpoints(&self.buffer, &conditions)?;
         }
+
+        self.breakpoints = conditions;
+        Ok(())
+    }
+
+    /// Describes each breakpoint that last paused execution. Cleared by the next clock or tick.
+    pub fn breakpoint_hits(&self) -> Vec<String> {
+        self.breakpoint_hits.clone()
+    }
+
+    /// Rewinds the simulation `clocks` recorded clocks, dropping the clocks after it. Pauses run
+    /// mode. Returns false if that far back isn't recorded.
+    pub fn rewind(&mut self, clocks: usize) -> bool {
+        self.running = false;
+        let mut result = Ok(());
+        self.step_execution(|execution_context| result = execution_context.rewind(clocks));
+        result.is_ok()
     }
 
     pub fn dispatch_event(&mut self, io_state: &IoState, camera: &Camera) -> EditorDispatchResult {
@@ -100,6 +300,17 @@ impl Project {
             camera: None,
         };
 
+        // Ctrl+Z undoes, Ctrl+Shift+Z redoes. Handled ahead of tools, which never see the event.
+        if io_state.get_key("Control").down && io_state.get_key_code("KeyZ").clicked {
+            dispatch_result.buffer_persist = if io_state.get_key("Shift").down {
+                self.redo()
+            } else {
+                self.undo()
+            };
+
+            return dispatch_result;
+        }
+
         let mut tool_input = ToolInput {
             active: false,
             io_state: io_state.clone(),
@@ -152,8 +363,11 @@ impl Project {
         idx: usize,
         output: ToolOutput,
     ) {
+        // The buffer is frozen while executing.
         if let Some(buffer) = output.buffer {
-            self.buffer = buffer;
+            if self.execution_context.is_none() {
+                self.buffer = buffer;
+            }
         }
 
         if let Some(mask) = output.mask {
@@ -173,6 +387,7 @@ impl Project {
         }
 
         if output.checkpoint {
+            self.history.checkpoint(&self.buffer);
             dispatch_result.buffer_persist = Some(self.buffer.clone());
         }
 
@@ -182,5 +397,134 @@ impl Project {
                 serialized_state: bytes,
             });
         }
+
+        if let Some(command) = output.execution {
+            self.handle_execution_command(command);
+        }
+    }
+
+    /// Swaps in a buffer restored from history. The active tool is re-activated so that it drops
+    /// its own (now stale) checkpoint and any in-progress edit.
+    fn restore_buffer(&mut self, buffer: Buffer) {
+        self.buffer = buffer;
+        self.mask = Default::default();
+
+        let output = self.tools[self.active_tool].activate(self.buffer.clone());
+        if let Some(cursor_style) = output.cursor_style {
+            self.cursor_style = cursor_style;
+        }
+    }
+
+    fn handle_execution_command(&mut self, command: ExecutionCommand) {
+        match command {
+            ExecutionCommand::Start => {
+                // Checked up front, as a failed compile would drop the modules.
+                if let Err(err) = ExecutionContext::check_bonds(&self.buffer, &mut self.modules) {
+                    error!("Failed to compile buffer: {:?}", err);
+                    return;
+                }
+
+                let compiler_results = match self.last_compile.take() {
+                    Some((compiled_buffer, mut compiler_results)) => {
+                        let delta = BufferDelta::new(&compiled_buffer, &self.buffer);
+                        compiler_results.recompile(&self.buffer, &delta);
+                        compiler_results
+                    }
+                    None => CompilerResults::from_buffer(&self.buffer),
+                };
+
+                let modules = std::mem::take(&mut self.modules);
+                match ExecutionContext::from_compiler_results(
+                    &self.buffer,
+                    compiler_results,
+                    modules,
+                ) {
+                    Ok(mut execution_context) => {
+                        execution_context.set_threads(self.threads, DEFAULT_PARALLEL_THRESHOLD);
+                        execution_context.record_snapshots(DEFAULT_SNAPSHOT_CAPACITY);
+                        execution_context.record_toggle_coverage();
+                        for condition in &self.breakpoints {
+                            if let Err(err) =
+                                execution_context.add_breakpoint(&self.buffer, condition.clone())
+                            {
+                                error!("{}", err);
+                            }
+                        }
+                        self.execution_context = Some(execution_context);
+                        self.step_execution(|_| {});
+                    }
+                    Err(err) => {
+                        error!("Failed to compile buffer: {:?}", err);
+                    }
+                }
+            }
+            ExecutionCommand::Stop => {
+                self.running = false;
+                if let Some(execution_context) = self.execution_context.take() {
+                    self.modules = execution_context.modules;
+                    self.last_compile =
+                        Some((self.buffer.clone(), execution_context.compiler_results));
+                }
+            }
+            ExecutionCommand::Run => self.running = true,
+            ExecutionCommand::Pause => self.running = false,
+            ExecutionCommand::Clock => {
+                self.running = false;
+                self.clock_execution(1);
+            }
+            ExecutionCommand::Tick => {
+                self.running = false;
+                self.step_execution(ExecutionContext::tick_once);
+            }
+            ExecutionCommand::StepBack => {
+                self.running = false;
+                self.step_execution(|execution_context| {
+                    if let Err(err) = execution_context.rewind(1) {
+                        error!("{}", err);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Executes `clocks` fundamental clocks then updates the mask. A clock that fails to settle
+    /// pauses run mode and highlights the switching traces instead. A breakpoint firing stops
+    /// after the clock it fired in.
+    fn clock_execution(&mut self, clocks: usize) {
+        let mut result = Ok(0);
+        self.step_execution(|execution_context| {
+            for _ in 0..clocks {
+                result = execution_context.clock_once();
+                if result.is_err() || execution_context.breakpoints.has_hits() {
+                    break;
+                }
+            }
+        });
+
+        if let (Err(err), Some(execution_context)) = (result, &self.execution_context) {
+            error!("{}", err);
+            self.running = false;
+            self.mask = err.to_mask(&execution_context.compiler_results);
+        }
+    }
+
+    /// Steps the execution context (if any) with `step` then copies the resulting trace states
+    /// into the render mask. Pauses run mode if a breakpoint fired.
+    fn step_execution<F>(&mut self, step: F)
+    where
+        F: FnOnce(&mut ExecutionContext),
+    {
+        if let Some(execution_context) = &mut self.execution_context {
+            step(execution_context);
+            execution_context.update_buffer_mask();
+            self.mask = execution_context.buffer_mask.clone();
+
+            let hits = execution_context.breakpoints.take_hits();
+            self.breakpoint_hits = hits.iter().map(|hit| hit.to_string()).collect();
+            for hit in &self.breakpoint_hits {
+                self.running = false;
+                log!("{}", hit);
+            }
+        }
     }
 }
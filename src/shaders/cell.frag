@@ -18,6 +18,8 @@ const vec4 p_color = vec4(0.0, 0.87, 1.0, 1.0);
 const vec3 metal_color = vec3(0.2, 0.2, 0.2);
 const vec3 active_color = vec3(1.0, 1.0, 1.0);
 const vec3 active_blend_strength_si_gate_metal = vec3(0.8, 0.8, 0.5);
+const vec3 heat_color = vec3(1.0, 0.45, 0.0);
+const float heat_blend_strength = 0.85;
 const vec3 grid_color = vec3(1.0, 1.0, 1.0);
 const vec3 background_color = vec3(0.0, 0.0, 0.0);
 const vec3 socket_color = vec3(1.0, 0.0, 0.0);
@@ -121,6 +123,12 @@ void main() {
     bool si_ul_active = (mask.b & (1u << 0u)) > 0u || (!is_mosfet && gate_active);
     bool si_dr_active = (mask.a & (1u << 0u)) > 0u || (!is_mosfet && gate_active);
 
+    // Heatmap masks (like toggle coverage) store a level in the upper 7 bits.
+    float metal_heat = float(mask.r >> 1u) / 127.0;
+    float gate_heat = float(mask.g >> 1u) / 127.0;
+    float si_ul_heat = max(float(mask.b >> 1u) / 127.0, is_mosfet ? 0.0 : gate_heat);
+    float si_dr_heat = max(float(mask.a >> 1u) / 127.0, is_mosfet ? 0.0 : gate_heat);
+
     bool cell_selected =
         cell_coord.x >= cell_select_ll.x &&
         cell_coord.y >= cell_select_ll.y &&
@@ -179,11 +187,16 @@ void main() {
 
     vec3 stripe_stren = active_blend_strength_si_gate_metal * stripe_blend;
 
+    float si_heat = mosfet_vertical
+        ?  (tile_uv.x < 0.5 ? si_ul_heat : si_dr_heat)
+        :  (tile_uv.y > 0.5 ? si_ul_heat : si_dr_heat);
+
     si_color = mix(
         si_color,
         active_color,
         si_active ? stripe_stren.r : 0.0
     );
+    si_color = mix(si_color, heat_color, si_heat * heat_blend_strength);
     float si_blend = (si_n || si_p) && si_connection ? 1.0 : 0.0;
 
     vec3 gate_color = si_n ? p_color.rgb : n_color.rgb;
@@ -192,6 +205,7 @@ void main() {
         active_color,
         gate_active ? stripe_stren.g : 0.0
     );
+    gate_color = mix(gate_color, heat_color, gate_heat * heat_blend_strength);
     float gate_blend = gate_connection ? 1.0 : 0.0;
 
     vec3 blended_metal_color = mix(
@@ -199,6 +213,11 @@ void main() {
         active_color,
         metal_active ? stripe_stren.b : 0.0
     );
+    blended_metal_color = mix(
+        blended_metal_color,
+        heat_color,
+        metal_heat * heat_blend_strength
+    );
     float metal_blend = metal && metal_connection ? 1.0 : 0.0;
 
     vec3 via_color = mix(si_color, vec3(1.0), 1.0);
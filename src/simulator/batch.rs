@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use crate::{
+    impl_js_error,
+    substrate::{
+        buffer::Buffer,
+        compiler::CompilerResults,
+        execution_context::{self, DEFAULT_MAX_TICKS_PER_CLOCK},
+    },
+};
+
+/// The number of vectors simulated in each pass, one per bit of the trace and gate states.
+pub const LANES: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError {
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+
+    #[error("socket \"{socket}\" has {len} vectors, expected {expected}")]
+    LengthMismatch {
+        socket: String,
+        len: usize,
+        expected: usize,
+    },
+
+    #[error("vector {vector} didn't settle within {ticks} ticks")]
+    Oscillation { vector: usize, ticks: usize },
+}
+
+impl_js_error!(BatchError);
+
+/// Simulates up to 64 independent input vectors at once, for exhaustively testing combinational
+/// blocks. Each trace and gate state is a `u64` bitset with one lane per vector, so a single
+/// propagation pass evaluates every lane. Uses the same `CompilerResults` as ExecutionContext,
+/// with the same semantics: each vector starts from a reset circuit (as a new ExecutionContext
+/// would) and runs one fundamental clock with its inputs driven.
+///
+/// There are no modules; sockets are driven and read directly.
+pub struct BatchSimulator {
+    compiler_results: CompilerResults,
+
+    /// Socket name to its trace.
+    sockets: HashMap<String, usize>,
+
+    /// A vector that is still switching after this many ticks is reported as oscillating.
+    pub max_ticks: usize,
+
+    /// One lane per vector, as of the last tick.
+    pub trace_states: Vec<u64>,
+    pub gate_states: Vec<u64>,
+
+    /// The lanes in which each trace is driven high.
+    driven: Vec<u64>,
+}
+
+impl BatchSimulator {
+    pub fn new(buffer: &Buffer) -> Self {
+        Self::from_compiler_results(buffer, CompilerResults::from_buffer(buffer))
+    }
+
+    /// Sockets are looked up in `buffer`, which `compiler_results` must have been compiled from.
+    pub fn from_compiler_results(buffer: &Buffer, compiler_results: CompilerResults) -> Self {
+        let sockets = buffer
+            .sockets
+            .iter()
+            .filter_map(|socket| {
+                execution_context::socket_trace(buffer, &compiler_results, &socket.name)
+                    .map(|trace| (socket.name.clone(), trace))
+            })
+            .collect();
+
+        Self {
+            trace_states: vec![0; compiler_results.traces.len()],
+            gate_states: vec![0; compiler_results.gates.len()],
+            driven: vec![0; compiler_results.traces.len()],
+            max_ticks: DEFAULT_MAX_TICKS_PER_CLOCK,
+            compiler_results,
+            sockets,
+        }
+    }
+
+    /// Runs every vector, where vector `i` drives each input socket with `inputs[socket][i]`.
+    /// Returns the state of each `outputs` socket after every vector, in the same shape. Every
+    /// input must have the same number of vectors, though that can be any number: they are
+    /// simulated `LANES` at a time.
+    pub fn run(
+        &mut self,
+        inputs: &HashMap<String, Vec<bool>>,
+        outputs: &[String],
+    ) -> Result<HashMap<String, Vec<bool>>, BatchError> {
+        let count = inputs.values().map(Vec::len).max().unwrap_or(0);
+        let inputs = inputs
+            .iter()
+            .map(|(name, vectors)| {
+                if vectors.len() != count {
+                    return Err(BatchError::LengthMismatch {
+                        socket: name.clone(),
+                        len: vectors.len(),
+                        expected: count,
+                    });
+                }
+                Ok((self.socket_trace(name)?, vectors))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_traces = outputs
+            .iter()
+            .map(|name| self.socket_trace(name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut results: Vec<Vec<bool>> = vec![Vec::with_capacity(count); outputs.len()];
+
+        for start in (0..count).step_by(LANES) {
+            let lanes = (count - start).min(LANES);
+            let mut drive = vec![];
+            for (trace, vectors) in &inputs {
+                let word = vectors[start..start + lanes]
+                    .iter()
+                    .enumerate()
+                    .fold(0, |word, (lane, &high)| word | (high as u64) << lane);
+                drive.push((*trace, word));
+            }
+
+            self.settle(&drive).map_err(|(ticks, switching)| {
+                let lane_mask = u64::MAX >> (LANES - lanes);
+                BatchError::Oscillation {
+                    vector: start + (switching & lane_mask).trailing_zeros() as usize,
+                    ticks,
+                }
+            })?;
+
+            for (result, &trace) in results.iter_mut().zip(&output_traces) {
+                let word = self.trace_states[trace];
+                result.extend((0..lanes).map(|lane| word & (1 << lane) != 0));
+            }
+        }
+
+        Ok(outputs.iter().cloned().zip(results).collect())
+    }
+
+    /// Resets the circuit, then drives each `(trace, lanes)` pair high in those lanes and ticks
+    /// until no gate changes in any lane. Returns the number of ticks taken, or the ticks and the
+    /// lanes still switching if that takes more than `max_ticks`.
+    pub fn settle(&mut self, drive: &[(usize, u64)]) -> Result<usize, (usize, u64)> {
+        self.trace_states.iter_mut().for_each(|s| *s = 0);
+        self.gate_states.iter_mut().for_each(|s| *s = 0);
+        self.driven.iter_mut().for_each(|d| *d = 0);
+
+        // Like the first tick of an ExecutionContext, which ignores modules to set a consistent
+        // gate state.
+        self.tick();
+
+        for &(trace, lanes) in drive {
+            self.driven[trace] |= lanes;
+        }
+
+        let mut ticks = 1;
+        loop {
+            let switching = self.tick();
+            ticks += 1;
+
+            if switching == 0 {
+                return Ok(ticks);
+            }
+
+            if ticks >= self.max_ticks {
+                return Err((ticks, switching));
+            }
+        }
+    }
+
+    /// Executes a single tick in every lane: traces are set high in the lanes where they are
+    /// connected to a driven trace through conducting gates, then gate states are computed from
+    /// the new trace states. Returns the lanes in which any gate changed state.
+    pub fn tick(&mut self) -> u64 {
+        let gates = &self.compiler_results.gates;
+        let trace_states = &mut self.trace_states;
+        trace_states.copy_from_slice(&self.driven);
+
+        // A lane of a gate conducts high from either EC trace to the other, until nothing changes.
+        loop {
+            let mut change = false;
+
+            for (gate, &conducting) in gates.iter().zip(&self.gate_states) {
+                if conducting == 0 {
+                    continue;
+                }
+
+                let left = trace_states[gate.left_ec_trace];
+                let right = trace_states[gate.right_ec_trace];
+                let high = (left | right) & conducting;
+
+                if left | high != left || right | high != right {
+                    trace_states[gate.left_ec_trace] = left | high;
+                    trace_states[gate.right_ec_trace] = right | high;
+                    change = true;
+                }
+            }
+
+            if !change {
+                break;
+            }
+        }
+
+        let mut switching = 0;
+        for (gate, gate_state) in gates.iter().zip(&mut self.gate_states) {
+            let base = trace_states[gate.base_trace];
+            let next = if gate.is_npn { base } else { !base };
+            switching |= *gate_state ^ next;
+            *gate_state = next;
+        }
+
+        switching
+    }
+
+    pub fn socket_trace(&self, name: &str) -> Result<usize, BatchError> {
+        self.sockets
+            .get(name)
+            .copied()
+            .ok_or_else(|| BatchError::UnknownSocket(name.to_string()))
+    }
+
+    pub fn compiler_results(&self) -> &CompilerResults {
+        &self.compiler_results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{
+        fixtures::{random_buffer, wire, Rng},
+        Simulator, SimulatorError,
+    };
+
+    #[test]
+    fn runs_more_vectors_than_lanes() {
+        let buffer = wire();
+
+        let vectors: Vec<bool> = (0..100).map(|i| i % 3 == 0).collect();
+        let inputs = HashMap::from([("IN".to_string(), vectors.clone())]);
+
+        let mut batch = BatchSimulator::new(&buffer);
+        let outputs = batch.run(&inputs, &["OUT".to_string()]).unwrap();
+        assert_eq!(outputs["OUT"], vectors);
+
+        let inputs = HashMap::from([
+            ("IN".to_string(), vectors),
+            ("OUT".to_string(), vec![false; 3]),
+        ]);
+        assert!(matches!(
+            batch.run(&inputs, &[]),
+            Err(BatchError::LengthMismatch { len: 3, .. })
+        ));
+        assert!(matches!(
+            batch.run(&HashMap::new(), &["NOPE".to_string()]),
+            Err(BatchError::UnknownSocket(_))
+        ));
+    }
+
+    #[test]
+    fn batch_matches_scalar_on_random_circuits() {
+        const VECTORS: usize = 70;
+        const MAX_TICKS: usize = 1_000;
+        let mut compared = 0;
+
+        for seed in 1..=20_u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let buffer = random_buffer(&mut rng, 16, 150, 8);
+            let names = Simulator::new(&buffer).unwrap().socket_names();
+            if names.is_empty() {
+                continue;
+            }
+            let inputs: Vec<(String, Vec<bool>)> = names
+                .iter()
+                .take(4)
+                .map(|name| {
+                    let values = (0..VECTORS).map(|_| rng.below(2) == 1).collect();
+                    (name.clone(), values)
+                })
+                .collect();
+
+            // Each vector from reset on the scalar simulator, None if it oscillates.
+            let scalar: Vec<Option<Vec<bool>>> = (0..VECTORS)
+                .map(|vector| {
+                    let mut sim = Simulator::new(&buffer).unwrap();
+                    sim.context_mut().max_ticks_per_clock = MAX_TICKS;
+                    for (name, values) in &inputs {
+                        sim.set_input(name, values[vector]).unwrap();
+                    }
+                    match sim.clock(1) {
+                        Ok(()) => Some(names.iter().map(|n| sim.get_output(n).unwrap()).collect()),
+                        Err(SimulatorError::Oscillation(_)) => None,
+                        Err(err) => panic!("seed {} vector {}: {}", seed, vector, err),
+                    }
+                })
+                .collect();
+
+            let mut batch = BatchSimulator::new(&buffer);
+            batch.max_ticks = MAX_TICKS;
+            let select = |vectors: &[usize]| -> HashMap<String, Vec<bool>> {
+                inputs
+                    .iter()
+                    .map(|(name, values)| {
+                        (name.clone(), vectors.iter().map(|&v| values[v]).collect())
+                    })
+                    .collect()
+            };
+
+            // Both agree on which vectors oscillate...
+            for vector in (0..VECTORS).filter(|&v| scalar[v].is_none()) {
+                assert!(
+                    matches!(
+                        batch.run(&select(&[vector]), &names),
+                        Err(BatchError::Oscillation { .. })
+                    ),
+                    "seed {} vector {} settles in the batch only",
+                    seed,
+                    vector
+                );
+            }
+
+            // ...and on the outputs of every other vector.
+            let settled: Vec<usize> = (0..VECTORS).filter(|&v| scalar[v].is_some()).collect();
+            let outputs = batch
+                .run(&select(&settled), &names)
+                .unwrap_or_else(|err| panic!("seed {}: {}", seed, err));
+            for (i, &vector) in settled.iter().enumerate() {
+                let expected = scalar[vector].as_ref().unwrap();
+                for (name, &expected) in names.iter().zip(expected) {
+                    assert_eq!(
+                        outputs[name][i], expected,
+                        "seed {} vector {} socket {}",
+                        seed, vector, name
+                    );
+                }
+                compared += 1;
+            }
+        }
+
+        // Most random circuits settle, so comparing few vectors means the test went vacuous.
+        assert!(
+            compared >= 20 * VECTORS / 2,
+            "only {} vectors were compared",
+            compared
+        );
+    }
+}
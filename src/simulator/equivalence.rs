@@ -0,0 +1,274 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    impl_js_error,
+    simulator::{
+        truth_table::{TruthTable, TruthTableError},
+        Simulator, SimulatorError,
+    },
+    substrate::buffer::Buffer,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EquivalenceError {
+    #[error("sockets not in both buffers: {}", .0.join(", "))]
+    UnmatchedSockets(Vec<String>),
+
+    #[error("no socket named \"{0}\" to use as an input")]
+    UnknownInput(String),
+
+    #[error(transparent)]
+    TruthTable(#[from] TruthTableError),
+
+    #[error(transparent)]
+    Simulator(#[from] SimulatorError),
+}
+
+impl_js_error!(EquivalenceError);
+
+/// Checks that two buffers (say, a block and its re-layout) behave identically. Sockets are
+/// matched by name, and must be the same in both. The `inputs` sockets are driven, and every
+/// socket is compared after each clock.
+///
+/// With at most `max_exhaustive_inputs` inputs, every input combination is first checked from
+/// reset (see `TruthTable`). Then, for sequential behavior, `sequences` runs of `clocks` clocks
+/// each are checked with random inputs on every clock. Random stimulus can miss a difference, so
+/// passing is evidence of equivalence, not proof (unless the blocks are combinational and were
+/// checked exhaustively).
+#[wasm_bindgen(getter_with_clone)]
+pub struct EquivalenceCheck {
+    pub inputs: Vec<String>,
+    pub max_exhaustive_inputs: usize,
+    pub sequences: usize,
+    pub clocks: usize,
+
+    /// Seeds the random stimulus, so a counterexample can be reproduced.
+    pub seed: u64,
+}
+
+/// Stimulus, from reset, after which the two buffers disagree. In the format of the CLI's
+/// stimulus files, so it can be replayed with `logic-paint run --stimulus`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    #[wasm_bindgen(skip)]
+    pub inputs: Vec<String>,
+
+    /// The input values applied before each clock, in the order of `inputs`. The buffers
+    /// disagree after the last clock.
+    #[wasm_bindgen(skip)]
+    pub stimulus: Vec<Vec<bool>>,
+
+    /// Each socket that differs after the last clock, with its value in the first and second
+    /// buffer.
+    #[wasm_bindgen(skip)]
+    pub mismatches: Vec<(String, bool, bool)>,
+}
+
+#[wasm_bindgen]
+impl EquivalenceCheck {
+    #[wasm_bindgen(constructor)]
+    pub fn new(inputs: Vec<String>) -> Self {
+        Self {
+            inputs,
+            max_exhaustive_inputs: 12,
+            sequences: 64,
+            clocks: 32,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Returns the first counterexample found, or None if the buffers agreed on every stimulus.
+    pub fn check(
+        &self,
+        a: &Buffer,
+        b: &Buffer,
+    ) -> Result<Option<Counterexample>, EquivalenceError> {
+        let sockets = matched_sockets(a, b)?;
+        if let Some(input) = self.inputs.iter().find(|i| !sockets.contains(i)) {
+            return Err(EquivalenceError::UnknownInput(input.clone()));
+        }
+
+        if self.inputs.len() <= self.max_exhaustive_inputs {
+            let table_a = TruthTable::from_buffer(a, self.inputs.clone(), sockets.clone())?;
+            let table_b = TruthTable::from_buffer(b, self.inputs.clone(), sockets.clone())?;
+
+            for (row, (outputs_a, outputs_b)) in table_a.rows.iter().zip(&table_b.rows).enumerate()
+            {
+                if outputs_a != outputs_b {
+                    return Ok(Some(Counterexample {
+                        inputs: self.inputs.clone(),
+                        stimulus: vec![table_a.input_values(row)],
+                        mismatches: mismatches(&sockets, outputs_a, outputs_b),
+                    }));
+                }
+            }
+        }
+
+        let mut rng = self.seed.max(1);
+        for _ in 0..self.sequences {
+            let mut sim_a = Simulator::new(a)?;
+            let mut sim_b = Simulator::new(b)?;
+            let mut stimulus = vec![];
+
+            for _ in 0..self.clocks {
+                let values: Vec<bool> = self
+                    .inputs
+                    .iter()
+                    .map(|_| xorshift(&mut rng) & 1 == 1)
+                    .collect();
+
+                for (input, &high) in self.inputs.iter().zip(&values) {
+                    sim_a.set_input(input, high)?;
+                    sim_b.set_input(input, high)?;
+                }
+                stimulus.push(values);
+
+                sim_a.clock(1)?;
+                sim_b.clock(1)?;
+
+                let outputs_a = read(&sim_a, &sockets)?;
+                let outputs_b = read(&sim_b, &sockets)?;
+                if outputs_a != outputs_b {
+                    return Ok(Some(Counterexample {
+                        inputs: self.inputs.clone(),
+                        stimulus,
+                        mismatches: mismatches(&sockets, &outputs_a, &outputs_b),
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[wasm_bindgen]
+impl Counterexample {
+    /// The counterexample as a stimulus file, with the mismatches in comments.
+    pub fn to_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// The sockets of both buffers, sorted by name, or an error listing those only in one.
+fn matched_sockets(a: &Buffer, b: &Buffer) -> Result<Vec<String>, EquivalenceError> {
+    let names = |buffer: &Buffer| {
+        let mut names: Vec<String> = buffer.sockets.iter().map(|s| s.name.clone()).collect();
+        names.sort();
+        names
+    };
+    let (names_a, names_b) = (names(a), names(b));
+
+    let mut unmatched: Vec<String> = names_a
+        .iter()
+        .filter(|name| !names_b.contains(name))
+        .chain(names_b.iter().filter(|name| !names_a.contains(name)))
+        .cloned()
+        .collect();
+
+    if unmatched.is_empty() {
+        Ok(names_a)
+    } else {
+        unmatched.sort();
+        Err(EquivalenceError::UnmatchedSockets(unmatched))
+    }
+}
+
+fn read(sim: &Simulator, sockets: &[String]) -> Result<Vec<bool>, SimulatorError> {
+    sockets
+        .iter()
+        .map(|socket| sim.get_output(socket))
+        .collect()
+}
+
+fn mismatches(sockets: &[String], a: &[bool], b: &[bool]) -> Vec<(String, bool, bool)> {
+    sockets
+        .iter()
+        .zip(a.iter().zip(b))
+        .filter(|(_, (a, b))| a != b)
+        .map(|(socket, (&a, &b))| (socket.clone(), a, b))
+        .collect()
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (clock, values) in self.stimulus.iter().enumerate() {
+            write!(f, "@{}", clock)?;
+            for (input, &high) in self.inputs.iter().zip(values) {
+                write!(f, " {}={}", input, high as u8)?;
+            }
+            writeln!(f)?;
+        }
+
+        for (socket, a, b) in &self.mismatches {
+            writeln!(
+                f,
+                "# after clock {}, {} is {} in the first buffer and {} in the second",
+                self.stimulus.len() - 1,
+                socket,
+                *a as u8,
+                *b as u8
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::fixtures::{draw_wire, wire};
+
+    /// Wires IN to OUT, and (if `bridged`) also to the unrelated wire SIDE.
+    fn wires(bridged: bool) -> Buffer {
+        let mut buffer = wire();
+        draw_wire(&mut buffer, 1, 2, "SIDE", None);
+        if bridged {
+            buffer.draw_metal_link(Some((1, 0).into()), (1, 1).into());
+        }
+        buffer
+    }
+
+    #[test]
+    fn finds_counterexamples() {
+        let check = EquivalenceCheck::new(vec!["IN".to_string()]);
+        assert_eq!(check.check(&wires(false), &wires(false)).unwrap(), None);
+
+        let counterexample = check.check(&wires(false), &wires(true)).unwrap().unwrap();
+        assert_eq!(counterexample.stimulus, vec![vec![true]]);
+        assert_eq!(
+            counterexample.to_string(),
+            "@0 IN=1\n# after clock 0, SIDE is 0 in the first buffer and 1 in the second\n"
+        );
+
+        // The same, found by random stimulus alone.
+        let check = EquivalenceCheck {
+            max_exhaustive_inputs: 0,
+            ..EquivalenceCheck::new(vec!["IN".to_string()])
+        };
+        let counterexample = check.check(&wires(false), &wires(true)).unwrap().unwrap();
+        assert_eq!(counterexample.stimulus.last(), Some(&vec![true]));
+        assert_eq!(
+            counterexample.mismatches,
+            vec![("SIDE".to_string(), false, true)]
+        );
+
+        let mut renamed = wires(false);
+        renamed.set_socket((0, 1).into(), Some("OTHER".to_string()));
+        assert!(matches!(
+            check.check(&wires(false), &renamed),
+            Err(EquivalenceError::UnmatchedSockets(names)) if names == ["OTHER", "SIDE"]
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    impl_js_error,
+    simulator::{
+        test_bench::{TestBench, TestBenchError},
+        Simulator, SimulatorError,
+    },
+    substrate::{
+        buffer::Buffer,
+        compiler::CompilerResults,
+        fault::{Fault, FaultError},
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FaultCampaignError {
+    #[error("the test bench fails without any faults, {0}")]
+    GoldenFailed(String),
+
+    #[error(transparent)]
+    TestBench(#[from] TestBenchError),
+
+    #[error(transparent)]
+    Simulator(#[from] SimulatorError),
+
+    #[error(transparent)]
+    Fault(#[from] FaultError),
+}
+
+impl_js_error!(FaultCampaignError);
+
+/// Runs a test bench once per single stuck-at fault, to measure how many of them its vectors
+/// detect (fault coverage). The test bench must pass on the fault-free circuit.
+#[wasm_bindgen]
+pub struct FaultCampaign {
+    /// One per fault, in the order they were run.
+    #[wasm_bindgen(skip)]
+    pub results: Vec<FaultResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultResult {
+    pub fault: Fault,
+
+    /// From `Fault::describe`.
+    pub description: String,
+
+    /// How the test bench caught the fault (its first mismatch), or None if it passed anyway.
+    pub detection: Option<String>,
+}
+
+#[wasm_bindgen]
+impl FaultCampaign {
+    /// Runs every single stuck-at fault (see `Fault::all`). This simulates the whole test bench
+    /// twice per trace and twice per gate, so large designs may want `run_faults` with a sample
+    /// of the faults instead.
+    #[wasm_bindgen(constructor)]
+    pub fn run(
+        buffer: &Buffer,
+        test_bench: &TestBench,
+    ) -> Result<FaultCampaign, FaultCampaignError> {
+        let compiler_results = CompilerResults::from_buffer(buffer);
+        let faults = Fault::all(&compiler_results);
+        Self::run_faults(buffer, compiler_results, test_bench, faults)
+    }
+
+    pub fn detected(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.detection.is_some())
+            .count()
+    }
+
+    /// The fraction of faults detected, from 0 to 1.
+    pub fn coverage(&self) -> f64 {
+        if self.results.is_empty() {
+            1.0
+        } else {
+            self.detected() as f64 / self.results.len() as f64
+        }
+    }
+
+    /// Describes each fault the test bench missed.
+    pub fn undetected(&self) -> Vec<String> {
+        self.results
+            .iter()
+            .filter(|r| r.detection.is_none())
+            .map(|r| r.description.clone())
+            .collect()
+    }
+
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FaultCampaign {
+    /// Runs the given faults, which must be for `compiler_results` (compiled from `buffer`).
+    pub fn run_faults(
+        buffer: &Buffer,
+        compiler_results: CompilerResults,
+        test_bench: &TestBench,
+        faults: Vec<Fault>,
+    ) -> Result<FaultCampaign, FaultCampaignError> {
+        let simulator =
+            || Simulator::with_compiler_results(buffer, compiler_results.clone(), vec![]);
+
+        let golden = test_bench.run_on(&mut simulator()?)?;
+        if !golden.passed() {
+            return Err(FaultCampaignError::GoldenFailed(golden.to_string()));
+        }
+
+        let mut results = Vec::with_capacity(faults.len());
+        for fault in faults {
+            let mut sim = simulator()?;
+            sim.context_mut().inject_fault(fault)?;
+
+            let detection = match test_bench.run_on(&mut sim) {
+                Ok(report) => report.first_mismatch.map(|mismatch| mismatch.to_string()),
+                // A fault that makes the circuit oscillate is as good as detected.
+                Err(TestBenchError::Simulator(SimulatorError::Oscillation(err))) => {
+                    Some(err.to_string())
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            results.push(FaultResult {
+                fault,
+                description: fault.describe(&compiler_results),
+                detection,
+            });
+        }
+
+        Ok(FaultCampaign { results })
+    }
+}
+
+impl fmt::Display for FaultCampaign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "detected {} of {} faults ({:.1}% coverage)",
+            self.detected(),
+            self.results.len(),
+            self.coverage() * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::fixtures::wire;
+
+    #[test]
+    fn measures_coverage() {
+        let bench = |src: &str| TestBench::parse(src).unwrap();
+
+        let campaign =
+            FaultCampaign::run(&wire(), &bench("inputs IN\noutputs OUT\n0 | 0\n1 | 1")).unwrap();
+        assert_eq!(campaign.results.len(), 2);
+        assert_eq!(campaign.coverage(), 1.0);
+
+        // Never driving IN high misses OUT stuck low.
+        let campaign =
+            FaultCampaign::run(&wire(), &bench("inputs IN\noutputs OUT\n0 | 0")).unwrap();
+        assert_eq!(
+            campaign.to_string(),
+            "detected 1 of 2 faults (50.0% coverage)"
+        );
+        let undetected = campaign.undetected();
+        assert_eq!(undetected.len(), 1);
+        assert!(undetected[0].ends_with("stuck low"), "{}", undetected[0]);
+
+        assert!(matches!(
+            FaultCampaign::run(&wire(), &bench("inputs IN\noutputs OUT\n1 | 0")),
+            Err(FaultCampaignError::GoldenFailed(_))
+        ));
+    }
+}
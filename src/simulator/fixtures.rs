@@ -0,0 +1,106 @@
+//! Circuits shared by the tests.
+
+use crate::{
+    substrate::buffer::Buffer,
+    upc::{Metal, NormalizedCell},
+};
+
+/// Draws a straight metal wire from `(0, y)` to `(length - 1, y)`, with the socket `from` on its
+/// left end and `to` (if any) on its right end.
+pub fn draw_wire(buffer: &mut Buffer, y: i32, length: i32, from: &str, to: Option<&str>) {
+    buffer.draw_metal_link(None, (0, y).into());
+    for x in 1..length {
+        buffer.draw_metal_link(Some((x - 1, y).into()), (x, y).into());
+    }
+    buffer.set_socket((0, y).into(), Some(from.to_string()));
+    if let Some(to) = to {
+        buffer.set_socket((length - 1, y).into(), Some(to.to_string()));
+    }
+}
+
+/// A single two cell wire, from the socket IN to the socket OUT.
+pub fn wire() -> Buffer {
+    let mut buffer = Buffer::new();
+    draw_wire(&mut buffer, 0, 2, "IN", Some("OUT"));
+    buffer
+}
+
+/// Two wires, IN0 to OUT0 and IN1 to OUT1.
+pub fn wires() -> Buffer {
+    let mut buffer = Buffer::new();
+    for y in 0..2 {
+        let (from, to) = (format!("IN{}", y), format!("OUT{}", y));
+        draw_wire(&mut buffer, y, 2, &from, Some(&to));
+    }
+    buffer
+}
+
+/// Xorshift, to keep the tests deterministic without pulling in a crate.
+pub struct Rng(pub u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Paints random silicon and metal strokes (creating plenty of transistors where they cross),
+/// vias, and sockets on random metal cells.
+pub fn random_buffer(rng: &mut Rng, size: i32, strokes: usize, sockets: usize) -> Buffer {
+    let mut buffer = Buffer::new();
+    paint_random_strokes(&mut buffer, rng, size, strokes);
+    add_random_sockets(&mut buffer, rng, size, sockets);
+    buffer
+}
+
+pub fn paint_random_strokes(buffer: &mut Buffer, rng: &mut Rng, size: i32, strokes: usize) {
+    let dirs = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+    for _ in 0..strokes {
+        let mut x = rng.below(size as u64) as i32;
+        let mut y = rng.below(size as u64) as i32;
+        let (dx, dy) = dirs[rng.below(4) as usize];
+        let kind = rng.below(3);
+        let mut from = None;
+
+        for _ in 0..1 + rng.below(size as u64 / 2) {
+            let to = (x, y).into();
+            match kind {
+                0 => buffer.draw_metal_link(from, to),
+                _ => buffer.draw_si_link(from, to, kind == 1),
+            }
+            from = Some(to);
+            x += dx;
+            y += dy;
+            if x < 0 || y < 0 || x >= size || y >= size {
+                break;
+            }
+        }
+    }
+
+    for _ in 0..strokes * 4 {
+        let x = rng.below(size as u64) as i32;
+        let y = rng.below(size as u64) as i32;
+        buffer.draw_via((x, y).into());
+    }
+}
+
+pub fn add_random_sockets(buffer: &mut Buffer, rng: &mut Rng, size: i32, count: usize) {
+    for i in 0..count {
+        for _ in 0..100 {
+            let coord = (rng.below(size as u64) as i32, rng.below(size as u64) as i32).into();
+            let cell: NormalizedCell = buffer.get_cell(coord).into();
+            if matches!(cell.metal, Metal::Trace { .. }) {
+                buffer.set_socket(coord, Some(format!("S{}", i)));
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use crate::{
+    module::{Module, ModuleGpio, ModuleGpioHandle},
+    substrate::{
+        breakpoint::{BreakpointError, BreakpointHandle, BreakpointHit},
+        buffer::Buffer,
+        buffer_serde::BufferSerdeError,
+        compiler::CompilerResults,
+        execution_context::{CompilerError, ExecutionContext, OscillationError, SimState},
+        scheduler::DEFAULT_PARALLEL_THRESHOLD,
+        toggle_coverage::ToggleCoverage,
+        waveform::{SampleRate, WaveformRecorder},
+    },
+    utils::convert::{import_legacy_blueprint, LegacyImportError},
+};
+
+pub mod batch;
+pub mod equivalence;
+pub mod fault_campaign;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod test_bench;
+pub mod truth_table;
+mod wasm;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SimulatorError {
+    #[error(transparent)]
+    Serde(#[from] BufferSerdeError),
+
+    #[error(transparent)]
+    LegacyImport(#[from] LegacyImportError),
+
+    #[error(transparent)]
+    Compiler(#[from] CompilerError),
+
+    #[error(transparent)]
+    Oscillation(#[from] OscillationError),
+
+    #[error(transparent)]
+    Breakpoint(#[from] BreakpointError),
+
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+}
+
+/// A headless facade over the compiler and ExecutionContext. Every socket in the buffer is bonded
+/// to an internal driver module, so inputs can be set and outputs read by socket name without
+/// writing a Module. Inputs are sampled at the start of each tick and are held until changed.
+///
+/// Plain Rust, so it runs headless (in tests, the CLI and other native tools). The JS bindings
+/// wrap it in `wasm::JsSimulator`.
+pub struct Simulator {
+    context: ExecutionContext,
+
+    /// Socket name to the handle of the driver GPIO bonded to it.
+    sockets: HashMap<String, ModuleGpioHandle>,
+
+    /// Index of the driver module in `context.modules`.
+    driver_handle: usize,
+}
+
+impl Simulator {
+    pub fn new(buffer: &Buffer) -> Result<Simulator, SimulatorError> {
+        Self::with_modules(buffer, vec![])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Simulator, SimulatorError> {
+        Self::new(&Buffer::from_bytes(bytes)?)
+    }
+
+    pub fn from_legacy_blueprint(json_str: String) -> Result<Simulator, SimulatorError> {
+        Self::new(&import_legacy_blueprint(json_str)?)
+    }
+
+    /// Drives the named socket high (or stops driving it). Takes effect on the next tick.
+    pub fn set_input(&mut self, name: &str, high: bool) -> Result<(), SimulatorError> {
+        let handle = self.socket_handle(name)?;
+        self.context.modules[self.driver_handle].get_gpios_mut()[handle].si_input_high = high;
+        Ok(())
+    }
+
+    /// Reads the state of the named socket's trace as of the last completed tick.
+    pub fn get_output(&self, name: &str) -> Result<bool, SimulatorError> {
+        Ok(self.context.state.trace_states[self.socket_trace(name)?])
+    }
+
+    /// Executes a single tick (one transistor propagation-delay).
+    pub fn tick(&mut self) {
+        self.context.tick_once();
+    }
+
+    /// Executes `count` fundamental clocks, each ticking until the circuit settles. Stops early
+    /// after a clock in which a breakpoint fired (see `take_breakpoint_hits`).
+    pub fn clock(&mut self, count: usize) -> Result<(), SimulatorError> {
+        for _ in 0..count {
+            self.context.clock_once()?;
+
+            if self.context.breakpoints.has_hits() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a breakpoint, parsed from `rise <signal>`, `fall <signal>`, `<bus> == <value>` or
+    /// `tick <count>`. Signals are socket names, or trace indices written `#12`.
+    pub fn add_breakpoint(&mut self, condition: &str) -> Result<BreakpointHandle, SimulatorError> {
+        let condition = condition.parse()?;
+        let sockets = &self.sockets;
+        let driver_handle = self.driver_handle;
+        let context = &mut self.context;
+        let bond_wires = &context.bond_wires;
+
+        Ok(context
+            .breakpoints
+            .add(condition, context.compiler_results.traces.len(), |name| {
+                let handle = *sockets.get(name)?;
+                bond_wires
+                    .iter()
+                    .find(|b| b.module_handle == driver_handle && b.gpio_handle == handle)
+                    .map(|b| b.trace)
+            })?)
+    }
+
+    pub fn remove_breakpoint(&mut self, handle: BreakpointHandle) {
+        self.context.breakpoints.remove(handle);
+    }
+
+    /// The breakpoints that fired since this was last called, in order.
+    pub fn take_breakpoint_hits(&mut self) -> Vec<BreakpointHit> {
+        self.context.breakpoints.take_hits()
+    }
+
+    /// Propagates on `threads` threads (for large designs, small ones are faster single-threaded).
+    pub fn set_threads(&mut self, threads: usize) {
+        self.context
+            .set_threads(threads, DEFAULT_PARALLEL_THRESHOLD);
+    }
+
+    /// Starts recording the named sockets (replacing any prior recording), sampled after every
+    /// tick or after every clock.
+    pub fn record(&mut self, names: Vec<String>, per_tick: bool) -> Result<(), SimulatorError> {
+        let mut recorder = WaveformRecorder::new(if per_tick {
+            SampleRate::Tick
+        } else {
+            SampleRate::Clock
+        });
+
+        for name in names {
+            let trace = self.socket_trace(&name)?;
+            recorder.add_trace(name, trace);
+        }
+
+        self.context.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// The recording as a VCD file, if recording.
+    pub fn vcd(&self) -> Option<String> {
+        self.context.recorder.as_ref().map(|r| r.to_vcd_string())
+    }
+
+    /// Starts counting how many times each trace switches (replacing any prior counts).
+    pub fn record_toggle_coverage(&mut self) {
+        self.context.record_toggle_coverage();
+    }
+
+    pub fn toggle_coverage(&self) -> Option<&ToggleCoverage> {
+        self.context.toggle_coverage.as_ref()
+    }
+
+    /// The toggle counts as a tab separated report, if counting.
+    pub fn toggle_report(&self) -> Option<String> {
+        self.context
+            .toggle_coverage
+            .as_ref()
+            .map(|t| t.to_report_string(&self.context.compiler_results))
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.context.state.ticks
+    }
+
+    pub fn socket_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.sockets.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Compiles the buffer with additional modules. These are bonded by GPIO name as usual, in
+    /// addition to the socket driver (socket inputs are OR'ed together).
+    pub fn with_modules(
+        buffer: &Buffer,
+        modules: Vec<Box<dyn Module>>,
+    ) -> Result<Self, SimulatorError> {
+        Self::with_compiler_results(buffer, CompilerResults::from_buffer(buffer), modules)
+    }
+
+    /// Like `with_modules`, with the buffer already compiled (to simulate it many times over
+    /// without recompiling).
+    pub fn with_compiler_results(
+        buffer: &Buffer,
+        compiler_results: CompilerResults,
+        mut modules: Vec<Box<dyn Module>>,
+    ) -> Result<Self, SimulatorError> {
+        let driver = SocketDriver::new(buffer);
+        let sockets = driver
+            .gpios
+            .iter()
+            .map(|gpio| (gpio.name.clone(), gpio.handle))
+            .collect();
+
+        let driver_handle = modules.len();
+        modules.push(Box::new(driver));
+
+        Ok(Self {
+            context: ExecutionContext::from_compiler_results(buffer, compiler_results, modules)?,
+            sockets,
+            driver_handle,
+        })
+    }
+
+    pub fn state(&self) -> &SimState {
+        &self.context.state
+    }
+
+    pub fn context(&self) -> &ExecutionContext {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut ExecutionContext {
+        &mut self.context
+    }
+
+    pub fn waveform(&self) -> Option<&WaveformRecorder> {
+        self.context.recorder.as_ref()
+    }
+
+    /// The trace a socket is bonded to. Sockets that didn't compile to a trace (not on metal)
+    /// are reported as unknown.
+    pub fn socket_trace(&self, name: &str) -> Result<usize, SimulatorError> {
+        let handle = self.socket_handle(name)?;
+        self.context
+            .bond_wires
+            .iter()
+            .find(|b| b.module_handle == self.driver_handle && b.gpio_handle == handle)
+            .map(|b| b.trace)
+            .ok_or_else(|| SimulatorError::UnknownSocket(name.to_string()))
+    }
+
+    fn socket_handle(&self, name: &str) -> Result<ModuleGpioHandle, SimulatorError> {
+        self.sockets
+            .get(name)
+            .copied()
+            .ok_or_else(|| SimulatorError::UnknownSocket(name.to_string()))
+    }
+}
+
+/// Bonds one GPIO to each socket in a buffer, with no behavior of its own.
+struct SocketDriver {
+    gpios: Vec<ModuleGpio>,
+}
+
+impl SocketDriver {
+    fn new(buffer: &Buffer) -> Self {
+        Self {
+            gpios: buffer
+                .sockets
+                .iter()
+                .enumerate()
+                .map(|(handle, socket)| ModuleGpio {
+                    handle,
+                    name: socket.name.clone(),
+                    bonding: None,
+                    trigger: false,
+                    si_output_high: false,
+                    si_input_high: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Module for SocketDriver {
+    fn get_name(&self) -> &str {
+        "socket-driver"
+    }
+
+    fn reset(&mut self) {
+        for gpio in &mut self.gpios {
+            gpio.si_output_high = false;
+            gpio.si_input_high = false;
+        }
+    }
+
+    fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+        &mut self.gpios
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        coords::{CellCoord, LocalCoord},
+        simulator::fixtures::{draw_wire, wire},
+    };
+
+    #[test]
+    fn drives_and_reads_sockets_by_name() {
+        let mut buffer = Buffer::new();
+        draw_wire(&mut buffer, 0, 10, "IN", Some("OUT"));
+
+        let mut sim = Simulator::from_bytes(&buffer.to_bytes().unwrap()).unwrap();
+        assert_eq!(sim.socket_names(), vec!["IN", "OUT"]);
+        sim.record(vec!["OUT".to_string()], false).unwrap();
+
+        sim.clock(1).unwrap();
+        assert!(!sim.get_output("OUT").unwrap());
+
+        sim.set_input("IN", true).unwrap();
+        sim.clock(1).unwrap();
+        assert!(sim.get_output("OUT").unwrap());
+
+        sim.set_input("IN", false).unwrap();
+        sim.clock(1).unwrap();
+        assert!(!sim.get_output("OUT").unwrap());
+
+        assert_eq!(
+            sim.waveform().unwrap().changes("OUT").unwrap(),
+            &[(0, false), (1, true), (2, false)]
+        );
+
+        assert!(matches!(
+            sim.set_input("NOPE", true),
+            Err(SimulatorError::UnknownSocket(_))
+        ));
+    }
+
+    #[test]
+    fn stops_clocking_on_breakpoints() {
+        let mut sim = Simulator::new(&wire()).unwrap();
+        let handle = sim.add_breakpoint("rise OUT").unwrap();
+        assert!(matches!(
+            sim.add_breakpoint("fall NOPE"),
+            Err(SimulatorError::Breakpoint(BreakpointError::UnknownSocket(
+                _
+            )))
+        ));
+
+        sim.clock(1).unwrap();
+        sim.set_input("IN", true).unwrap();
+        sim.clock(10).unwrap();
+        assert_eq!(sim.state().clocks, 2);
+
+        let hits = sim.take_breakpoint_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].handle, handle);
+        assert!(hits[0].tick <= sim.ticks());
+
+        sim.clock(10).unwrap();
+        assert_eq!(sim.state().clocks, 12);
+    }
+
+    #[test]
+    fn counts_toggle_coverage() {
+        let mut buffer = Buffer::new();
+        draw_wire(&mut buffer, 0, 2, "IN", None);
+        draw_wire(&mut buffer, 1, 2, "SIDE", None);
+
+        let mut sim = Simulator::new(&buffer).unwrap();
+        sim.record_toggle_coverage();
+        for high in [false, true, false] {
+            sim.set_input("IN", high).unwrap();
+            sim.clock(1).unwrap();
+        }
+
+        let coverage = sim.toggle_coverage().unwrap();
+        let (input, side) = (
+            sim.socket_trace("IN").unwrap(),
+            sim.socket_trace("SIDE").unwrap(),
+        );
+        assert_eq!((coverage.rises[input], coverage.falls[input]), (1, 1));
+        assert_eq!(coverage.never_switched(), vec![side]);
+        assert!(sim.toggle_report().unwrap().starts_with(
+            "# 1 of 2 traces rose and fell (50.0% toggle coverage), 1 never switched"
+        ));
+
+        // The wire that never switched is highlighted, the other is shaded.
+        let mask = coverage.to_mask(&sim.context().compiler_results);
+        let metal = |x: i32, y: i32| {
+            let coord: CellCoord = (x, y).into();
+            mask.get_chunk(coord).unwrap().cells[LocalCoord::from(coord).to_upc_idx()]
+        };
+        assert_eq!(metal(1, 1), 1);
+        assert!(metal(1, 0) > 1 && metal(1, 0) & 1 == 0);
+    }
+}
@@ -0,0 +1,474 @@
+use std::{fmt, str::FromStr};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    impl_js_error,
+    simulator::{Simulator, SimulatorError},
+    substrate::buffer::Buffer,
+};
+
+/// The widest bus a test bench column can name.
+pub const MAX_PORT_WIDTH: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TestBenchError {
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+
+    #[error(transparent)]
+    Simulator(#[from] SimulatorError),
+}
+
+impl_js_error!(TestBenchError);
+
+/// A set of test vectors, one per fundamental clock. The format is line based, with `#` starting
+/// a comment:
+///
+/// ```text
+/// # The columns of each vector: sockets driven, and sockets expected after the clock.
+/// # `B[4]` is the bus of sockets B0 (the LSB) through B3.
+/// inputs  A B[4]
+/// outputs Y[4] CARRY
+///
+/// # Inputs, then expected outputs. Values are decimal, hex (0x) or binary (0b), and `x` is a
+/// # don't-care: an input that holds its previous value, or an output that isn't checked.
+/// # Binary values can have don't-care bits.
+/// 1 0x3 | 0x4 0
+/// x 15  | 0b00x0 x
+/// ```
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestBench {
+    #[wasm_bindgen(skip)]
+    pub inputs: Vec<Port>,
+
+    #[wasm_bindgen(skip)]
+    pub outputs: Vec<Port>,
+
+    #[wasm_bindgen(skip)]
+    pub vectors: Vec<Vector>,
+}
+
+/// A column of a test bench: a single socket, or a bus of sockets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    /// As written, `A` or `B[4]`.
+    pub name: String,
+
+    /// The socket names, LSB first.
+    pub sockets: Vec<String>,
+}
+
+/// A value with don't-care bits. Only bits set in `care` are driven or checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pattern {
+    pub value: u64,
+    pub care: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    /// The line of the test bench source it was parsed from.
+    pub line: usize,
+
+    /// One pattern per input port.
+    pub inputs: Vec<Pattern>,
+
+    /// One pattern per output port.
+    pub outputs: Vec<Pattern>,
+}
+
+/// The first output that didn't match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The index of the failing vector.
+    pub vector: usize,
+    pub line: usize,
+    pub port: String,
+    pub width: usize,
+    pub expected: Pattern,
+    pub actual: u64,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestReport {
+    /// The number of vectors run.
+    #[wasm_bindgen(skip)]
+    pub vectors: usize,
+
+    /// The number of vectors with at least one mismatched output.
+    #[wasm_bindgen(skip)]
+    pub failed: usize,
+
+    #[wasm_bindgen(skip)]
+    pub first_mismatch: Option<Mismatch>,
+}
+
+#[wasm_bindgen]
+impl TestBench {
+    #[wasm_bindgen(constructor)]
+    pub fn parse(src: &str) -> Result<TestBench, TestBenchError> {
+        src.parse()
+    }
+
+    /// Compiles `buffer` and runs every vector against it, from a fresh simulation.
+    pub fn run(&self, buffer: &Buffer) -> Result<TestReport, TestBenchError> {
+        self.run_on(&mut Simulator::new(buffer)?)
+    }
+}
+
+impl TestBench {
+    /// Runs every vector against an existing simulation (which may have modules bonded, or
+    /// breakpoints set). Each vector drives its inputs, executes one fundamental clock, then
+    /// checks its outputs.
+    pub fn run_on(&self, sim: &mut Simulator) -> Result<TestReport, TestBenchError> {
+        let mut report = TestReport {
+            vectors: self.vectors.len(),
+            failed: 0,
+            first_mismatch: None,
+        };
+
+        for (index, vector) in self.vectors.iter().enumerate() {
+            for (port, pattern) in self.inputs.iter().zip(&vector.inputs) {
+                for (bit, socket) in port.sockets.iter().enumerate() {
+                    if pattern.care & (1 << bit) != 0 {
+                        sim.set_input(socket, pattern.value & (1 << bit) != 0)?;
+                    }
+                }
+            }
+
+            sim.clock(1)?;
+
+            let mut failed = false;
+            for (port, expected) in self.outputs.iter().zip(&vector.outputs) {
+                let mut actual = 0;
+                for (bit, socket) in port.sockets.iter().enumerate() {
+                    actual |= (sim.get_output(socket)? as u64) << bit;
+                }
+
+                if actual & expected.care != expected.value {
+                    failed = true;
+                    if report.first_mismatch.is_none() {
+                        report.first_mismatch = Some(Mismatch {
+                            vector: index,
+                            line: vector.line,
+                            port: port.name.clone(),
+                            width: port.sockets.len(),
+                            expected: *expected,
+                            actual,
+                        });
+                    }
+                }
+            }
+
+            if failed {
+                report.failed += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[wasm_bindgen]
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// A one-line summary, including the first mismatch (if any).
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl FromStr for TestBench {
+    type Err = TestBenchError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut inputs = None;
+        let mut outputs = None;
+        let mut vectors = vec![];
+
+        for (i, line) in src.lines().enumerate() {
+            let line_number = i + 1;
+            let error = |message: String| TestBenchError::Parse {
+                line: line_number,
+                message,
+            };
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                None => continue,
+                Some("inputs") => inputs = Some(parse_ports(words).map_err(error)?),
+                Some("outputs") => outputs = Some(parse_ports(words).map_err(error)?),
+                Some(_) => {
+                    let (Some(inputs), Some(outputs)) = (&inputs, &outputs) else {
+                        return Err(error(
+                            "vectors must follow the `inputs` and `outputs` lines".to_string(),
+                        ));
+                    };
+                    let Some((input_values, output_values)) = line.split_once('|') else {
+                        return Err(error("expected `INPUTS | OUTPUTS`".to_string()));
+                    };
+
+                    vectors.push(Vector {
+                        line: line_number,
+                        inputs: parse_patterns(inputs, input_values).map_err(error)?,
+                        outputs: parse_patterns(outputs, output_values).map_err(error)?,
+                    });
+                }
+            }
+        }
+
+        Ok(TestBench {
+            inputs: inputs.unwrap_or_default(),
+            outputs: outputs.unwrap_or_default(),
+            vectors,
+        })
+    }
+}
+
+fn parse_ports<'a>(words: impl Iterator<Item = &'a str>) -> Result<Vec<Port>, String> {
+    words
+        .map(|word| {
+            let Some((name, width)) = word.strip_suffix(']').and_then(|w| w.split_once('[')) else {
+                return Ok(Port {
+                    name: word.to_string(),
+                    sockets: vec![word.to_string()],
+                });
+            };
+
+            match width.parse::<usize>() {
+                Ok(width) if width > 0 && width <= MAX_PORT_WIDTH => Ok(Port {
+                    name: word.to_string(),
+                    sockets: (0..width).map(|bit| format!("{}{}", name, bit)).collect(),
+                }),
+                _ => Err(format!(
+                    "invalid bus `{}`, widths are 1 to {}",
+                    word, MAX_PORT_WIDTH
+                )),
+            }
+        })
+        .collect()
+}
+
+fn parse_patterns(ports: &[Port], values: &str) -> Result<Vec<Pattern>, String> {
+    let values: Vec<&str> = values.split_whitespace().collect();
+    if values.len() != ports.len() {
+        return Err(format!(
+            "expected {} values, found {}",
+            ports.len(),
+            values.len()
+        ));
+    }
+
+    ports
+        .iter()
+        .zip(values)
+        .map(|(port, value)| parse_pattern(value, port.sockets.len()))
+        .collect()
+}
+
+fn parse_pattern(word: &str, width: usize) -> Result<Pattern, String> {
+    let mask = u64::MAX >> (64 - width);
+    let invalid = || format!("invalid value `{}`", word);
+
+    if word == "x" || word == "X" {
+        return Ok(Pattern { value: 0, care: 0 });
+    }
+
+    let pattern = if let Some(bits) = word.strip_prefix("0b") {
+        if bits.is_empty() || bits.len() > width {
+            return Err(invalid());
+        }
+
+        let mut pattern = Pattern { value: 0, care: 0 };
+        for c in bits.chars() {
+            pattern.value <<= 1;
+            pattern.care <<= 1;
+            match c {
+                '0' => pattern.care |= 1,
+                '1' => {
+                    pattern.value |= 1;
+                    pattern.care |= 1;
+                }
+                'x' | 'X' => {}
+                _ => return Err(invalid()),
+            }
+        }
+
+        // Bits above those written are zero, not don't-cares.
+        pattern.care |= mask & !(u64::MAX >> (64 - bits.len()));
+        pattern
+    } else {
+        let value = match word.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => word.parse(),
+        }
+        .map_err(|_| invalid())?;
+
+        Pattern { value, care: mask }
+    };
+
+    if pattern.value & !mask != 0 {
+        return Err(format!("value `{}` doesn't fit in {} bits", word, width));
+    }
+
+    Ok(pattern)
+}
+
+/// Formats a value as a single bit, as hex when every bit is cared about, or as binary with `x`
+/// for don't-care bits.
+fn format_pattern(pattern: Pattern, width: usize) -> String {
+    let mask = u64::MAX >> (64 - width);
+
+    if pattern.care & mask == 0 {
+        "x".to_string()
+    } else if width == 1 {
+        pattern.value.to_string()
+    } else if pattern.care & mask == mask {
+        format!("{:#x}", pattern.value)
+    } else {
+        let bits: String = (0..width)
+            .rev()
+            .map(
+                |bit| match (pattern.care >> bit & 1, pattern.value >> bit & 1) {
+                    (0, _) => 'x',
+                    (_, 0) => '0',
+                    _ => '1',
+                },
+            )
+            .collect();
+        format!("0b{}", bits)
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mask = u64::MAX >> (64 - self.width);
+
+        // Shown in the same form as the expectation, so the bits line up.
+        let actual = if self.width == 1 || self.expected.care & mask == mask {
+            format_pattern(
+                Pattern {
+                    value: self.actual,
+                    care: mask,
+                },
+                self.width,
+            )
+        } else {
+            format!("0b{:0width$b}", self.actual, width = self.width)
+        };
+
+        write!(
+            f,
+            "line {} (vector {}): {} expected {}, got {}",
+            self.line,
+            self.vector,
+            self.port,
+            format_pattern(self.expected, self.width),
+            actual
+        )
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.first_mismatch {
+            None => write!(f, "PASS: {} vectors", self.vectors),
+            Some(mismatch) => write!(
+                f,
+                "FAIL: {} of {} vectors failed, first on {}",
+                self.failed, self.vectors, mismatch
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::fixtures::wires;
+
+    #[test]
+    fn parses_buses_and_dont_cares() {
+        let bench = TestBench::parse(
+            "
+            inputs A B[4] # comment
+            outputs Y[4]
+            1 0x3 | 0b1x0
+            x 15  | x
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(bench.inputs[1].sockets, vec!["B0", "B1", "B2", "B3"]);
+        assert_eq!(bench.vectors[0].line, 4);
+        assert_eq!(
+            bench.vectors[0].inputs,
+            vec![
+                Pattern { value: 1, care: 1 },
+                Pattern {
+                    value: 3,
+                    care: 0xf
+                }
+            ]
+        );
+        assert_eq!(
+            bench.vectors[0].outputs,
+            vec![Pattern {
+                value: 0b100,
+                care: 0b1101
+            }]
+        );
+        assert_eq!(bench.vectors[1].inputs[0], Pattern { value: 0, care: 0 });
+
+        for (src, line) in [
+            ("1 | 1", 1),
+            ("inputs A\noutputs Y\n1 1", 3),
+            ("inputs A\noutputs Y\n1 | 2", 3),
+            ("inputs A\noutputs Y[2]\n1 | 0b1x1", 3),
+            ("inputs A[65]", 1),
+        ] {
+            match TestBench::parse(src) {
+                Err(TestBenchError::Parse { line: l, .. }) => assert_eq!(l, line, "{}", src),
+                other => panic!("{}: {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_the_first_mismatch() {
+        let buffer = wires();
+
+        let report = TestBench::parse("inputs IN[2]\noutputs OUT[2]\n1 | 1\n2 | 0bx0\n3 | 3")
+            .unwrap()
+            .run(&buffer)
+            .unwrap();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.to_string(), "PASS: 3 vectors");
+
+        let report =
+            TestBench::parse("inputs IN0 IN1\noutputs OUT0 OUT1\n1 0 | 1 0\nx 1 | 0 x\n0 x | 1 1")
+                .unwrap()
+                .run(&buffer)
+                .unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.failed, 2);
+        assert_eq!(
+            report.to_string(),
+            "FAIL: 2 of 3 vectors failed, first on line 4 (vector 1): OUT0 expected 0, got 1"
+        );
+
+        assert!(matches!(
+            TestBench::parse("inputs NOPE\noutputs OUT0\n1 | 1")
+                .unwrap()
+                .run(&buffer),
+            Err(TestBenchError::Simulator(SimulatorError::UnknownSocket(_)))
+        ));
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    coords::CellCoord,
+    impl_js_error,
+    simulator::batch::{BatchError, BatchSimulator},
+    substrate::buffer::Buffer,
+    utils::Selection,
+};
+
+/// The most inputs a truth table is extracted for (2^16 rows).
+pub const MAX_TRUTH_TABLE_INPUTS: usize = 16;
+
+/// The most inputs an output is minimized for. Minimization is exact, and grows as 3^n in the
+/// worst case.
+pub const MAX_MINIMIZED_INPUTS: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TruthTableError {
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+
+    #[error(
+        "{0} inputs is too many to simulate exhaustively, the limit is {}",
+        MAX_TRUTH_TABLE_INPUTS
+    )]
+    TooManyInputs(usize),
+
+    #[error(transparent)]
+    Batch(#[from] BatchError),
+}
+
+impl_js_error!(TruthTableError);
+
+/// The outputs of a combinational block for every combination of its inputs, found by
+/// exhaustively simulating it. Each row is simulated from a reset circuit, so sequential logic
+/// shows its power-on behavior only.
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    #[wasm_bindgen(skip)]
+    pub inputs: Vec<String>,
+
+    #[wasm_bindgen(skip)]
+    pub outputs: Vec<String>,
+
+    /// The output values of each row, in the order of `outputs`. In row `r`, the first input is
+    /// the most significant bit of `r`.
+    #[wasm_bindgen(skip)]
+    pub rows: Vec<Vec<bool>>,
+}
+
+#[wasm_bindgen]
+impl TruthTable {
+    /// Extracts the truth table of the cells in `selection`. Sockets outside the selection are
+    /// ignored, and connections leaving it are cut.
+    pub fn from_selection(
+        buffer: &Buffer,
+        selection: &Selection,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+    ) -> Result<TruthTable, TruthTableError> {
+        let anchor = selection.lower_left;
+        let mut region = buffer.clone_selection(selection, anchor);
+        region.fix_all_cells();
+
+        for socket in &buffer.sockets {
+            if selection.test_cell_in_selection(socket.cell_coord) {
+                region.set_socket(
+                    CellCoord(socket.cell_coord.0 - anchor.0),
+                    Some(socket.name.clone()),
+                );
+            }
+        }
+
+        Self::from_buffer(&region, inputs, outputs)
+    }
+
+    pub fn from_buffer(
+        buffer: &Buffer,
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+    ) -> Result<TruthTable, TruthTableError> {
+        if inputs.len() > MAX_TRUTH_TABLE_INPUTS {
+            return Err(TruthTableError::TooManyInputs(inputs.len()));
+        }
+
+        // Row `r` is vector `r`, with the first input as its most significant bit.
+        let row_count = 1 << inputs.len();
+        let mut vectors: HashMap<String, Vec<bool>> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let bit = inputs.len() - 1 - i;
+                let values = (0..row_count).map(|row| (row >> bit) & 1 == 1).collect();
+                (name.clone(), values)
+            })
+            .collect();
+
+        // Without inputs there is still the one row. Driving an output low (which drives
+        // nothing) gives the batch a vector to simulate.
+        if let (true, Some(output)) = (inputs.is_empty(), outputs.first()) {
+            vectors.insert(output.clone(), vec![false]);
+        }
+
+        let columns = BatchSimulator::new(buffer)
+            .run(&vectors, &outputs)
+            .map_err(|err| match err {
+                BatchError::UnknownSocket(name) => TruthTableError::UnknownSocket(name),
+                err => TruthTableError::Batch(err),
+            })?;
+        let rows = (0..row_count)
+            .map(|row| outputs.iter().map(|output| columns[output][row]).collect())
+            .collect();
+
+        Ok(TruthTable {
+            inputs,
+            outputs,
+            rows,
+        })
+    }
+
+    /// The table as text, one row per line, inputs then outputs.
+    pub fn to_text(&self) -> String {
+        self.to_string()
+    }
+
+    /// A minimized sum-of-products expression for each output, like `Y = (A & !B) | C`. Outputs
+    /// of tables with more than `MAX_MINIMIZED_INPUTS` inputs are omitted.
+    pub fn expressions(&self) -> Vec<String> {
+        self.outputs
+            .iter()
+            .filter_map(|output| {
+                self.expression(output)
+                    .map(|expression| format!("{} = {}", output, expression))
+            })
+            .collect()
+    }
+}
+
+impl TruthTable {
+    /// The value of each input in row `row`, in the order of `inputs`.
+    pub fn input_values(&self, row: usize) -> Vec<bool> {
+        let count = self.inputs.len();
+        (0..count)
+            .map(|i| (row >> (count - 1 - i)) & 1 == 1)
+            .collect()
+    }
+
+    /// A minimized sum-of-products expression for `output`: `0`, `1`, or products of input
+    /// literals (`!A` for low) joined by `|`. The prime implicants are exact (Quine-McCluskey),
+    /// the cover of them is chosen greedily, so it is minimal in most but not all cases.
+    pub fn expression(&self, output: &str) -> Option<String> {
+        let column = self.outputs.iter().position(|o| o == output)?;
+        if self.inputs.len() > MAX_MINIMIZED_INPUTS {
+            return None;
+        }
+
+        let minterms: Vec<u32> = (0..self.rows.len() as u32)
+            .filter(|&row| self.rows[row as usize][column])
+            .collect();
+
+        if minterms.is_empty() {
+            return Some("0".to_string());
+        }
+        if minterms.len() == self.rows.len() {
+            return Some("1".to_string());
+        }
+
+        let products: Vec<String> =
+            cover(&minterms, &prime_implicants(&minterms, self.inputs.len()))
+                .into_iter()
+                .map(|implicant| self.format_product(implicant))
+                .collect();
+
+        Some(if products.len() == 1 {
+            products[0].clone()
+        } else {
+            products
+                .iter()
+                .map(|product| {
+                    if product.contains('&') {
+                        format!("({})", product)
+                    } else {
+                        product.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" | ")
+        })
+    }
+
+    fn format_product(&self, implicant: Implicant) -> String {
+        let count = self.inputs.len();
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, input)| {
+                let bit = 1 << (count - 1 - i);
+                if implicant.mask & bit != 0 {
+                    None
+                } else if implicant.value & bit != 0 {
+                    Some(input.clone())
+                } else {
+                    Some(format!("!{}", input))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+}
+
+/// A product term: the rows matching `value` in every bit not set in `mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Implicant {
+    value: u32,
+    mask: u32,
+}
+
+impl Implicant {
+    fn covers(&self, row: u32) -> bool {
+        row & !self.mask == self.value
+    }
+}
+
+/// Repeatedly merges implicants that differ in a single bit, returning those that never merged.
+fn prime_implicants(minterms: &[u32], inputs: usize) -> Vec<Implicant> {
+    let mut primes = vec![];
+    let mut current: BTreeSet<Implicant> = minterms
+        .iter()
+        .map(|&value| Implicant { value, mask: 0 })
+        .collect();
+
+    while !current.is_empty() {
+        let mut next = BTreeSet::new();
+        let mut merged = HashSet::new();
+
+        for implicant in &current {
+            for bit in (0..inputs).map(|i| 1 << i) {
+                if (implicant.mask | implicant.value) & bit != 0 {
+                    continue;
+                }
+
+                let other = Implicant {
+                    value: implicant.value | bit,
+                    mask: implicant.mask,
+                };
+                if current.contains(&other) {
+                    merged.insert(*implicant);
+                    merged.insert(other);
+                    next.insert(Implicant {
+                        value: implicant.value,
+                        mask: implicant.mask | bit,
+                    });
+                }
+            }
+        }
+
+        primes.extend(current.iter().filter(|i| !merged.contains(i)));
+        current = next;
+    }
+
+    primes
+}
+
+/// Picks essential prime implicants, then greedily the one covering the most remaining rows.
+fn cover(minterms: &[u32], primes: &[Implicant]) -> Vec<Implicant> {
+    let mut chosen = BTreeSet::new();
+
+    let mut covering: HashMap<u32, Vec<Implicant>> = HashMap::new();
+    for &row in minterms {
+        covering.insert(
+            row,
+            primes.iter().copied().filter(|p| p.covers(row)).collect(),
+        );
+    }
+    for implicants in covering.values() {
+        if let [essential] = implicants[..] {
+            chosen.insert(essential);
+        }
+    }
+
+    let mut remaining: Vec<u32> = minterms
+        .iter()
+        .copied()
+        .filter(|&row| !chosen.iter().any(|c: &Implicant| c.covers(row)))
+        .collect();
+
+    while !remaining.is_empty() {
+        let best = *primes
+            .iter()
+            .max_by_key(|p| {
+                (
+                    remaining.iter().filter(|&&row| p.covers(row)).count(),
+                    p.mask.count_ones(),
+                    std::cmp::Reverse(**p),
+                )
+            })
+            .unwrap();
+
+        chosen.insert(best);
+        remaining.retain(|&row| !best.covers(row));
+    }
+
+    chosen.into_iter().collect()
+}
+
+impl fmt::Display for TruthTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} | {}", self.inputs.join(" "), self.outputs.join(" "))?;
+
+        for (row, outputs) in self.rows.iter().enumerate() {
+            let column = |(name, value): (&String, bool)| {
+                format!("{:<width$}", value as u8, width = name.len())
+            };
+            let inputs: Vec<String> = self
+                .inputs
+                .iter()
+                .zip(self.input_values(row))
+                .map(column)
+                .collect();
+            let outputs: Vec<String> = self
+                .outputs
+                .iter()
+                .zip(outputs.iter().copied())
+                .map(column)
+                .collect();
+
+            let line = format!("{} | {}", inputs.join(" "), outputs.join(" "));
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::fixtures::wires;
+
+    fn table(inputs: &[&str], outputs: &[&str], rows: &[&[bool]]) -> TruthTable {
+        TruthTable {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            rows: rows.iter().map(|r| r.to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn minimizes_expressions() {
+        let (f, t) = (false, true);
+        // Y = A & B, Z = A ^ B, W = !A
+        let and_xor = table(
+            &["A", "B"],
+            &["Y", "Z", "W", "ONE"],
+            &[&[f, f, t, t], &[f, t, t, t], &[f, t, f, t], &[t, f, f, t]],
+        );
+        assert_eq!(
+            and_xor.expressions(),
+            vec!["Y = A & B", "Z = (!A & B) | (A & !B)", "W = !A", "ONE = 1"]
+        );
+
+        // A 2:1 mux, Y = S ? B : A. The consensus term A & B is redundant.
+        let rows: Vec<Vec<bool>> = (0..8)
+            .map(|row| {
+                let (s, a, b) = (row & 4 != 0, row & 2 != 0, row & 1 != 0);
+                vec![if s { b } else { a }]
+            })
+            .collect();
+        let rows: Vec<&[bool]> = rows.iter().map(|r| r.as_slice()).collect();
+        let mux = table(&["S", "A", "B"], &["Y"], &rows);
+        assert_eq!(mux.expression("Y").unwrap(), "(!S & A) | (S & B)");
+        assert_eq!(mux.expression("NOPE"), None);
+    }
+
+    #[test]
+    fn extracts_tables_from_selections() {
+        // Two wires, but only the first is selected.
+        let buffer = wires();
+        let selection = Selection::from_rectangle_inclusive((0, 0), (1, 0));
+
+        let wire = TruthTable::from_selection(
+            &buffer,
+            &selection,
+            vec!["IN0".to_string()],
+            vec!["OUT0".to_string()],
+        )
+        .unwrap();
+        assert_eq!(wire.rows, vec![vec![false], vec![true]]);
+        assert_eq!(wire.to_string(), "IN0 | OUT0\n0   | 0\n1   | 1\n");
+        assert_eq!(wire.expressions(), vec!["OUT0 = IN0"]);
+
+        assert!(matches!(
+            TruthTable::from_selection(
+                &buffer,
+                &selection,
+                vec!["IN1".to_string()],
+                vec!["OUT0".to_string()],
+            ),
+            Err(TruthTableError::UnknownSocket(_))
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    impl_js_error,
+    simulator::{Simulator, SimulatorError},
+    substrate::buffer::Buffer,
+};
+
+impl_js_error!(SimulatorError);
+
+/// The JS bindings for `Simulator`, exported under the same name.
+#[wasm_bindgen(js_name = Simulator)]
+pub struct JsSimulator(Simulator);
+
+#[wasm_bindgen(js_class = Simulator)]
+impl JsSimulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(buffer: &Buffer) -> Result<JsSimulator, SimulatorError> {
+        Ok(Self(Simulator::new(buffer)?))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<JsSimulator, SimulatorError> {
+        Ok(Self(Simulator::from_bytes(bytes)?))
+    }
+
+    pub fn from_legacy_blueprint(json_str: String) -> Result<JsSimulator, SimulatorError> {
+        Ok(Self(Simulator::from_legacy_blueprint(json_str)?))
+    }
+
+    pub fn set_input(&mut self, name: &str, high: bool) -> Result<(), SimulatorError> {
+        self.0.set_input(name, high)
+    }
+
+    pub fn get_output(&self, name: &str) -> Result<bool, SimulatorError> {
+        self.0.get_output(name)
+    }
+
+    pub fn tick(&mut self) {
+        self.0.tick();
+    }
+
+    pub fn clock(&mut self, count: usize) -> Result<(), SimulatorError> {
+        self.0.clock(count)
+    }
+
+    pub fn add_breakpoint(&mut self, condition: &str) -> Result<usize, SimulatorError> {
+        self.0.add_breakpoint(condition)
+    }
+
+    pub fn remove_breakpoint(&mut self, handle: usize) {
+        self.0.remove_breakpoint(handle);
+    }
+
+    /// Describes each breakpoint that fired since this was last called.
+    pub fn take_breakpoint_hits(&mut self) -> Vec<String> {
+        self.0
+            .take_breakpoint_hits()
+            .iter()
+            .map(|hit| hit.to_string())
+            .collect()
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.0.set_threads(threads);
+    }
+
+    pub fn record(&mut self, names: Vec<String>, per_tick: bool) -> Result<(), SimulatorError> {
+        self.0.record(names, per_tick)
+    }
+
+    pub fn vcd(&self) -> Option<String> {
+        self.0.vcd()
+    }
+
+    pub fn record_toggle_coverage(&mut self) {
+        self.0.record_toggle_coverage();
+    }
+
+    pub fn toggle_report(&self) -> Option<String> {
+        self.0.toggle_report()
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.0.ticks()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn socket_names(&self) -> Vec<String> {
+        self.0.socket_names()
+    }
+}
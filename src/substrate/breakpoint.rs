@@ -0,0 +1,362 @@
+use std::{fmt, str::FromStr};
+
+use crate::impl_js_error;
+
+/// The widest bus a breakpoint can compare.
+pub const MAX_BUS_WIDTH: usize = 64;
+
+pub type BreakpointHandle = usize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BreakpointError {
+    #[error("invalid breakpoint \"{0}\"")]
+    Parse(String),
+
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+
+    #[error("no trace #{0}")]
+    UnknownTrace(usize),
+
+    #[error("bus \"{0}\" is wider than {} bits", MAX_BUS_WIDTH)]
+    BusTooWide(String),
+}
+
+impl_js_error!(BreakpointError);
+
+/// A single signal: a socket (by name) or a trace (by index, written `#12`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal {
+    Socket(String),
+    Trace(usize),
+}
+
+/// When a breakpoint fires. Conditions are checked after every tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// The signal goes from low to high.
+    Rising(Signal),
+
+    /// The signal goes from high to low.
+    Falling(Signal),
+
+    /// The bus becomes equal to `value`. The bus is the sockets `{bus}0` (the LSB) through
+    /// `{bus}N`, or the single socket named `bus` if there are none.
+    Equals { bus: String, value: u64 },
+
+    /// The tick count reaches `ticks`.
+    Ticks(usize),
+}
+
+/// A breakpoint that fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointHit {
+    pub handle: BreakpointHandle,
+
+    /// The number of ticks executed when it fired.
+    pub tick: usize,
+
+    pub condition: Condition,
+}
+
+/// The breakpoints of an ExecutionContext, with their signals resolved to traces.
+#[derive(Default)]
+pub struct Breakpoints {
+    /// Indexed by handle, None once removed.
+    breakpoints: Vec<Option<Breakpoint>>,
+
+    /// Hits not yet taken by `take_hits`, in order.
+    hits: Vec<BreakpointHit>,
+}
+
+struct Breakpoint {
+    condition: Condition,
+
+    /// The traces read, LSB first. Empty for tick conditions.
+    traces: Vec<usize>,
+
+    /// The value read on the previous check, to detect changes.
+    last: Option<u64>,
+}
+
+impl Breakpoints {
+    /// Adds a breakpoint, resolving its sockets to traces with `socket_trace`. Traces must be
+    /// below `trace_count`.
+    pub fn add(
+        &mut self,
+        condition: Condition,
+        trace_count: usize,
+        socket_trace: impl Fn(&str) -> Option<usize>,
+    ) -> Result<BreakpointHandle, BreakpointError> {
+        let traces = resolve(&condition, trace_count, &socket_trace)?;
+        self.breakpoints.push(Some(Breakpoint {
+            condition,
+            traces,
+            last: None,
+        }));
+
+        Ok(self.breakpoints.len() - 1)
+    }
+
+    pub fn remove(&mut self, handle: BreakpointHandle) {
+        if let Some(breakpoint) = self.breakpoints.get_mut(handle) {
+            *breakpoint = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.hits.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.iter().all(Option::is_none)
+    }
+
+    /// Forgets the values read by previous checks, so that jumping to another point in the
+    /// simulation (restoring a snapshot) isn't mistaken for an edge.
+    pub fn reset_edges(&mut self) {
+        for breakpoint in self.breakpoints.iter_mut().flatten() {
+            breakpoint.last = None;
+        }
+    }
+
+    /// Re-resolves every breakpoint after a recompile, removing those whose sockets no longer
+    /// resolve.
+    pub fn rebind(&mut self, trace_count: usize, socket_trace: impl Fn(&str) -> Option<usize>) {
+        for slot in &mut self.breakpoints {
+            if let Some(breakpoint) = slot {
+                match resolve(&breakpoint.condition, trace_count, &socket_trace) {
+                    Ok(traces) => {
+                        breakpoint.traces = traces;
+                        breakpoint.last = None;
+                    }
+                    Err(_) => *slot = None,
+                }
+            }
+        }
+    }
+
+    /// Checks every breakpoint against the trace states after a tick, recording hits.
+    pub fn check(&mut self, ticks: usize, trace_states: &[bool]) {
+        for (handle, breakpoint) in self.breakpoints.iter_mut().enumerate() {
+            let Some(breakpoint) = breakpoint else {
+                continue;
+            };
+
+            let value = match breakpoint.condition {
+                Condition::Ticks(_) => ticks as u64,
+                _ => breakpoint
+                    .traces
+                    .iter()
+                    .enumerate()
+                    .fold(0, |v, (i, &t)| v | ((trace_states[t] as u64) << i)),
+            };
+
+            let fired = match breakpoint.condition {
+                Condition::Rising(_) => breakpoint.last == Some(0) && value == 1,
+                Condition::Falling(_) => breakpoint.last == Some(1) && value == 0,
+                Condition::Equals { value: target, .. } => {
+                    value == target && breakpoint.last != Some(target)
+                }
+                // Exactly, so that rewinding past the tick doesn't fire it again straight away.
+                Condition::Ticks(target) => ticks == target,
+            };
+
+            breakpoint.last = Some(value);
+
+            if fired {
+                self.hits.push(BreakpointHit {
+                    handle,
+                    tick: ticks,
+                    condition: breakpoint.condition.clone(),
+                });
+            }
+        }
+    }
+
+    /// True if any breakpoint fired since the hits were last taken.
+    pub fn has_hits(&self) -> bool {
+        !self.hits.is_empty()
+    }
+
+    pub fn take_hits(&mut self) -> Vec<BreakpointHit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+fn resolve(
+    condition: &Condition,
+    trace_count: usize,
+    socket_trace: &impl Fn(&str) -> Option<usize>,
+) -> Result<Vec<usize>, BreakpointError> {
+    let signal_trace = |signal: &Signal| match signal {
+        Signal::Socket(name) => {
+            socket_trace(name).ok_or_else(|| BreakpointError::UnknownSocket(name.clone()))
+        }
+        Signal::Trace(trace) if *trace < trace_count => Ok(*trace),
+        Signal::Trace(trace) => Err(BreakpointError::UnknownTrace(*trace)),
+    };
+
+    match condition {
+        Condition::Rising(signal) | Condition::Falling(signal) => Ok(vec![signal_trace(signal)?]),
+        Condition::Equals { bus, .. } => {
+            let traces: Vec<usize> = (0..)
+                .map_while(|bit| socket_trace(&format!("{}{}", bus, bit)))
+                .take(MAX_BUS_WIDTH + 1)
+                .collect();
+
+            if traces.len() > MAX_BUS_WIDTH {
+                Err(BreakpointError::BusTooWide(bus.clone()))
+            } else if traces.is_empty() {
+                Ok(vec![signal_trace(&Signal::Socket(bus.clone()))?])
+            } else {
+                Ok(traces)
+            }
+        }
+        Condition::Ticks(_) => Ok(vec![]),
+    }
+}
+
+impl FromStr for Signal {
+    type Err = BreakpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('#') {
+            Some(trace) => trace
+                .parse()
+                .map(Signal::Trace)
+                .map_err(|_| BreakpointError::Parse(s.to_string())),
+            None if !s.is_empty() => Ok(Signal::Socket(s.to_string())),
+            None => Err(BreakpointError::Parse(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = BreakpointError;
+
+    /// Parses `rise <signal>`, `fall <signal>`, `<bus> == <value>` (decimal, or hex with `0x`) or
+    /// `tick <count>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_error = || BreakpointError::Parse(s.to_string());
+        let words: Vec<&str> = s.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["rise", signal] => Ok(Condition::Rising(signal.parse()?)),
+            ["fall", signal] => Ok(Condition::Falling(signal.parse()?)),
+            ["tick", ticks] => Ok(Condition::Ticks(ticks.parse().map_err(|_| parse_error())?)),
+            [bus, "==", value] => {
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => value.parse(),
+                }
+                .map_err(|_| parse_error())?;
+
+                Ok(Condition::Equals {
+                    bus: bus.to_string(),
+                    value,
+                })
+            }
+            _ => Err(parse_error()),
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signal::Socket(name) => write!(f, "{}", name),
+            Signal::Trace(trace) => write!(f, "#{}", trace),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Rising(signal) => write!(f, "rise {}", signal),
+            Condition::Falling(signal) => write!(f, "fall {}", signal),
+            Condition::Equals { bus, value } => write!(f, "{} == {:#x}", bus, value),
+            Condition::Ticks(ticks) => write!(f, "tick {}", ticks),
+        }
+    }
+}
+
+impl fmt::Display for BreakpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "breakpoint {} ({}) hit on tick {}",
+            self.handle, self.condition, self.tick
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_conditions() {
+        for text in ["rise CLK", "fall #12", "PC == 0x3000", "tick 100"] {
+            let condition: Condition = text.parse().unwrap();
+            assert_eq!(condition.to_string(), text);
+        }
+
+        assert_eq!(
+            "IR == 42".parse::<Condition>().unwrap(),
+            Condition::Equals {
+                bus: "IR".to_string(),
+                value: 42
+            }
+        );
+        assert!("rise".parse::<Condition>().is_err());
+        assert!("PC == 0xZZ".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn fires_on_edges_values_and_ticks() {
+        // Trace 1 is CLK, traces 2 and 3 are the bus B0 and B1.
+        let socket_trace = |name: &str| match name {
+            "CLK" => Some(1),
+            "B0" => Some(2),
+            "B1" => Some(3),
+            _ => None,
+        };
+
+        let mut breakpoints = Breakpoints::default();
+        for text in ["rise CLK", "fall CLK", "B == 2", "tick 3"] {
+            breakpoints
+                .add(text.parse().unwrap(), 4, socket_trace)
+                .unwrap();
+        }
+        assert!(matches!(
+            breakpoints.add("rise NOPE".parse().unwrap(), 4, socket_trace),
+            Err(BreakpointError::UnknownSocket(_))
+        ));
+        assert!(matches!(
+            breakpoints.add("rise #4".parse().unwrap(), 4, socket_trace),
+            Err(BreakpointError::UnknownTrace(4))
+        ));
+
+        let fired = |b: &mut Breakpoints, ticks, states: [bool; 4]| -> Vec<BreakpointHandle> {
+            b.check(ticks, &states);
+            b.take_hits().iter().map(|h| h.handle).collect()
+        };
+
+        let b = &mut breakpoints;
+        assert_eq!(fired(b, 1, [false, false, false, false]), vec![]);
+        assert_eq!(fired(b, 2, [false, true, false, true]), vec![0, 2]);
+        // Still equal, so the bus breakpoint doesn't fire again.
+        assert_eq!(fired(b, 3, [false, false, false, true]), vec![1, 3]);
+        assert_eq!(fired(b, 4, [false, false, false, true]), vec![]);
+
+        // Rewinding to after the tick breakpoint doesn't fire it, running through it again does.
+        b.reset_edges();
+        assert_eq!(fired(b, 5, [false; 4]), vec![]);
+        b.reset_edges();
+        assert_eq!(fired(b, 2, [false; 4]), vec![]);
+        assert_eq!(fired(b, 3, [false; 4]), vec![3]);
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     rc::Rc,
 };
 
-use glam::{IVec2, UVec2};
+use glam::IVec2;
 use wasm_bindgen::prelude::*;
 
 use crate::{
@@ -23,8 +23,11 @@ pub struct Buffer {
     /// this vec if very cheap to clone, as the chunk data is stored behind a Rc RefCell. This
     /// means that you can clone a Buffer, update one cell, and only a single chunk will actually
     /// be copied (the one that was copy-on-write updated).
-    #[wasm_bindgen(skip)]
-    pub chunks: Vec<BufferChunk>,
+    chunks: Vec<BufferChunk>,
+
+    /// Index into `chunks` by chunk coord, kept in sync with every chunk insert or repurpose. An
+    /// im::HashMap so that cloning the Buffer stays cheap.
+    chunk_lookup: im::HashMap<ChunkCoord, usize>,
 
     /// A socket is a named GPIO in the buffer, connected to the metal layer.
     #[wasm_bindgen(skip)]
@@ -45,7 +48,7 @@ pub struct BufferChunk {
     cells: Rc<RefCell<[u8; CHUNK_BYTE_LEN]>>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Socket {
     /// The unique (within a Buffer) name of the socket.
     pub name: String,
@@ -63,15 +66,11 @@ impl Buffer {
 
     /// Gets the cell at the given location, returning default if the chunk doesn't exist.
     pub fn get_cell(&self, cell_coord: CellCoord) -> UPC {
-        let chunk_coord: ChunkCoord = cell_coord.into();
-        for chunk in &self.chunks {
-            if chunk.chunk_coord == chunk_coord {
-                return chunk.get_cell(cell_coord);
-            }
+        match self.get_chunk(cell_coord) {
+            Some(chunk) => chunk.get_cell(cell_coord),
+            // Chunk isn't allocated
+            None => Default::default(),
         }
-
-        // Chunk isn't allocated
-        Default::default()
     }
 
     /// Sets the cell at the given location, allocating a chunk to house it if needed. Note that
@@ -87,24 +86,26 @@ impl Buffer {
     /// non-unique, a number will be appended to the end of the name. Setting the socket to None is
     /// the same as clearing it.
     pub fn set_socket(&mut self, cell_coord: CellCoord, name: Option<String>) {
+        let name = name.map(|name| {
+            make_name_unique(name, self.sockets.iter().map(|s| s.name.clone()).collect())
+        });
+        self.set_socket_unchecked(cell_coord, name);
+    }
+
+    /// Like `set_socket`, but uses `name` as given even if another socket already has it. For
+    /// restoring sockets exactly as they were (say, when applying a BufferDelta).
+    pub(crate) fn set_socket_unchecked(&mut self, cell_coord: CellCoord, name: Option<String>) {
         match name {
             Some(name) => {
-                let name =
-                    make_name_unique(name, self.sockets.iter().map(|s| s.name.clone()).collect());
-
                 // If the socket is already set (aka this is a just a rename)
                 if let Some(socket) = self.sockets.iter_mut().find(|s| s.cell_coord == cell_coord) {
-                    socket.name = name.to_string();
-                    return;
+                    socket.name = name;
                 } else {
                     // Set a new socket
                     let mut cell = self.get_cell(cell_coord);
                     Bit::set(&mut cell, Bit::SOCKET, true);
                     self.set_cell_unchecked(cell_coord, cell, false);
-                    self.sockets.push(Socket {
-                        cell_coord,
-                        name: name.to_string(),
-                    });
+                    self.sockets.push(Socket { cell_coord, name });
                 }
             }
             None => {
@@ -119,17 +120,10 @@ impl Buffer {
 
     pub fn clone_selection(&self, selection: &Selection, anchor: CellCoord) -> Buffer {
         let mut buffer = Buffer::default();
-        let ll = selection.lower_left.0;
-        let ur = selection.upper_right.0;
 
         // Clone cells.
-        for y in ll.y..ur.y {
-            for x in ll.x..ur.x {
-                let from_cell_coord = CellCoord(IVec2::new(x, y));
-                let to_cell_coord = CellCoord(IVec2::new(x, y) - anchor.0);
-                let cell = self.get_cell(from_cell_coord);
-                buffer.set_cell(to_cell_coord, cell);
-            }
+        for (cell_coord, cell) in self.iter_region(selection) {
+            buffer.set_cell(CellCoord(cell_coord.0 - anchor.0), cell);
         }
 
         buffer
@@ -141,28 +135,14 @@ impl Buffer {
         let mut ur = IVec2::new(i32::MIN, i32::MIN);
 
         // Paste cells
-        for chunk in &buffer.chunks {
-            let target_first_cell_offset = chunk.chunk_coord.first_cell_coord().0 + cell_coord.0;
-
-            for y in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let local_coord = LocalCoord(UVec2::new(x as u32, y as u32));
-                    let cell = chunk.get_cell(local_coord);
-
-                    if cell == Default::default() {
-                        continue;
-                    }
+        for (from_cell_coord, cell) in buffer.iter_cells() {
+            let target_cell_coord = CellCoord(from_cell_coord.0 + cell_coord.0);
 
-                    let target_cell_coord =
-                        CellCoord(target_first_cell_offset + IVec2::new(x as i32, y as i32));
+            // Tracking bounding rect.
+            ll = IVec2::min(ll, target_cell_coord.0);
+            ur = IVec2::max(ur, target_cell_coord.0);
 
-                    // Tracking bounding rect.
-                    ll = IVec2::min(ll, target_cell_coord.0);
-                    ur = IVec2::max(ur, target_cell_coord.0);
-
-                    self.set_cell(target_cell_coord, cell);
-                }
-            }
+            self.set_cell(target_cell_coord, cell);
         }
 
         // Then go through the outline of the bounding rect and fix any broken cells.
@@ -180,19 +160,9 @@ impl Buffer {
     pub fn rotate_to_new(&self) -> Self {
         let mut buffer = Self::default();
 
-        for chunk in &self.chunks {
-            for y in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let local_coord = LocalCoord(UVec2::new(x as u32, y as u32));
-                    let c = local_coord.to_cell_coord(&chunk.chunk_coord).0;
-
-                    // Rotate coordinates around the origin.
-                    buffer.set_cell(
-                        CellCoord(IVec2::new(c.y, -c.x)),
-                        chunk.get_cell(local_coord).rotate(),
-                    );
-                }
-            }
+        for (CellCoord(c), cell) in self.iter_cells() {
+            // Rotate coordinates around the origin.
+            buffer.set_cell(CellCoord(IVec2::new(c.y, -c.x)), cell.rotate());
         }
 
         buffer
@@ -201,19 +171,9 @@ impl Buffer {
     pub fn mirror_to_new(&self) -> Self {
         let mut buffer = Self::default();
 
-        for chunk in &self.chunks {
-            for y in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let local_coord = LocalCoord(UVec2::new(x as u32, y as u32));
-                    let c = local_coord.to_cell_coord(&chunk.chunk_coord).0;
-
-                    // Mirror around the x axis (flip Y values).
-                    buffer.set_cell(
-                        CellCoord(IVec2::new(c.x, -c.y)),
-                        chunk.get_cell(local_coord).mirror(),
-                    );
-                }
-            }
+        for (CellCoord(c), cell) in self.iter_cells() {
+            // Mirror around the x axis (flip Y values).
+            buffer.set_cell(CellCoord(IVec2::new(c.x, -c.y)), cell.mirror());
         }
 
         buffer
@@ -236,15 +196,13 @@ impl Buffer {
     }
 
     /// Sets the cell without first clearing socket or bond pad bits
-    fn set_cell_unchecked(&mut self, cell_coord: CellCoord, mut cell: UPC, preserve_socket: bool) {
+    fn set_cell_unchecked(&mut self, cell_coord: CellCoord, cell: UPC, preserve_socket: bool) {
         let chunk_coord: ChunkCoord = cell_coord.into();
 
         // Existing chunks
-        for chunk in &mut self.chunks {
-            if chunk.chunk_coord == chunk_coord {
-                chunk.set_cell(cell_coord, cell, preserve_socket);
-                return;
-            }
+        if let Some(&i) = self.chunk_lookup.get(&chunk_coord) {
+            self.chunks[i].set_cell(cell_coord, cell, preserve_socket);
+            return;
         }
 
         // If a default UPC is being set, there is nothing else to do (no point in making an empty
@@ -254,21 +212,37 @@ impl Buffer {
         }
 
         // See if we have an empty chunk we can repurpose
-        for chunk in &mut self.chunks {
-            if chunk.cell_count == 0 {
-                chunk.chunk_coord = chunk_coord;
-                chunk.set_cell(cell_coord, cell, preserve_socket);
-                return;
-            }
+        if let Some(i) = self.chunks.iter().position(|c| c.cell_count == 0) {
+            let chunk = &mut self.chunks[i];
+            self.chunk_lookup.remove(&chunk.chunk_coord);
+            self.chunk_lookup.insert(chunk_coord, i);
+            chunk.chunk_coord = chunk_coord;
+            chunk.set_cell(cell_coord, cell, preserve_socket);
+            return;
         }
 
         // Otherwise allocate a new chunk and push it to the back.
         let mut chunk = BufferChunk::new(chunk_coord);
         chunk.set_cell(cell_coord, cell, preserve_socket);
+        self.chunk_lookup.insert(chunk_coord, self.chunks.len());
         self.chunks.push(chunk);
     }
 
     fn fix_cell(&mut self, cell_coord: CellCoord) {
+        if self.get_cell(cell_coord) == Default::default() {
+            return;
+        }
+
+        // Write the cell back.
+        let new_upc: UPC = self.fixed_cell(cell_coord).into();
+        self.set_cell(cell_coord, new_upc);
+    }
+}
+
+impl Buffer {
+    /// The cell at `cell_coord` with every connection its neighbor doesn't reciprocate dropped. A
+    /// cell that differs from its fixed version is half-connected.
+    pub fn fixed_cell(&self, cell_coord: CellCoord) -> NormalizedCell {
         // Follow broken connection directions and connect them, if able. The following
         // connections will be made (every other connection will be dropped):
         // - Metal -> metal
@@ -282,13 +256,7 @@ impl Buffer {
         //
         // Otherwise the connection is culled.
 
-        let orig_upc = self.get_cell(cell_coord);
-
-        if orig_upc == Default::default() {
-            return;
-        }
-
-        let mut cell: NormalizedCell = orig_upc.into();
+        let mut cell: NormalizedCell = self.get_cell(cell_coord).into();
 
         // Handle metal (which is simple).
         if let Metal::Trace { placement: pl, .. } = &mut cell.metal {
@@ -393,9 +361,55 @@ impl Buffer {
             _ => {}
         }
 
-        // Write the cell back.
-        let new_upc: UPC = cell.into();
-        self.set_cell(cell_coord, new_upc);
+        cell
+    }
+
+    /// All allocated chunks, in allocation order. Chunks can be empty (they are kept around to be
+    /// repurposed).
+    pub fn chunks(&self) -> &[BufferChunk] {
+        &self.chunks
+    }
+
+    /// Constant-time lookup of the chunk housing `c`, if one is allocated.
+    pub fn get_chunk<T>(&self, c: T) -> Option<&BufferChunk>
+    where
+        T: Into<ChunkCoord>,
+    {
+        let coord: ChunkCoord = c.into();
+        self.chunk_lookup.get(&coord).map(|&i| &self.chunks[i])
+    }
+
+    /// Inserts a whole chunk, replacing any chunk already allocated at the same coord.
+    pub fn insert_chunk(&mut self, chunk: BufferChunk) {
+        if let Some(&i) = self.chunk_lookup.get(&chunk.chunk_coord) {
+            self.chunks[i] = chunk;
+        } else {
+            self.chunk_lookup
+                .insert(chunk.chunk_coord, self.chunks.len());
+            self.chunks.push(chunk);
+        }
+    }
+
+    /// Iterates all non-default cells in the buffer, chunk by chunk.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (CellCoord, UPC)> + '_ {
+        self.chunks.iter().flat_map(|chunk| chunk.iter_cells())
+    }
+
+    /// Iterates all non-default cells within the selection. Only the chunks overlapping the
+    /// selection are visited, unallocated ones are skipped entirely.
+    pub fn iter_region(
+        &self,
+        selection: &Selection,
+    ) -> impl Iterator<Item = (CellCoord, UPC)> + '_ {
+        let selection = *selection;
+        let ll: ChunkCoord = selection.lower_left.into();
+        let ur: ChunkCoord = CellCoord(selection.upper_right.0 - IVec2::ONE).into();
+
+        (ll.0.y..=ur.0.y)
+            .flat_map(move |y| (ll.0.x..=ur.0.x).map(move |x| ChunkCoord(IVec2::new(x, y))))
+            .filter_map(move |chunk_coord| self.get_chunk(chunk_coord))
+            .flat_map(|chunk| chunk.iter_cells())
+            .filter(move |(cell_coord, _)| selection.test_cell_in_selection(*cell_coord))
     }
 }
 
@@ -449,10 +463,26 @@ impl BufferChunk {
         slice.copy_from_slice(&cell.0);
     }
 
+    /// Iterates the non-default cells of the chunk, in row-major order.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (CellCoord, UPC)> + '_ {
+        (0..CHUNK_CELL_COUNT).filter_map(move |i| {
+            let local_coord = LocalCoord::from_upc_idx(i << LOG_UPC_BYTE_LEN);
+            let cell = self.get_cell(local_coord);
+            (cell != Default::default())
+                .then(|| (local_coord.to_cell_coord(&self.chunk_coord), cell))
+        })
+    }
+
     pub fn get_cells(&self) -> Ref<[u8; CHUNK_BYTE_LEN]> {
         self.cells.borrow()
     }
 
+    /// True if both chunks still share the same (copy-on-write) cells, in which case they are
+    /// known to be identical without comparing them.
+    pub fn shares_cells(&self, other: &BufferChunk) -> bool {
+        Rc::ptr_eq(&self.cells, &other.cells)
+    }
+
     pub fn get_cells_mut(&mut self) -> &mut [u8; CHUNK_BYTE_LEN] {
         Rc::make_mut(&mut self.cells).get_mut()
     }
@@ -10,9 +10,11 @@ use super::buffer::Buffer;
 
 /// Stores a sparse set of deltas, encoded as (CellCoord, Cell) tuples. It's not particularly
 /// compact in-memory, but compresses just fine. Because cell diffs get added one chunk at a time,
-/// a BufferDelta can also be efficiently applied to a Buffer.
+/// a BufferDelta can also be efficiently applied to a Buffer. Socket changes are tracked
+/// separately, as `Buffer::set_cell` never touches socket bits.
 pub struct BufferDelta {
     cell_deltas: Vec<CellDelta>,
+    socket_deltas: Vec<SocketDelta>,
 }
 
 pub struct CellDelta {
@@ -23,6 +25,13 @@ pub struct CellDelta {
     cell: UPC,
 }
 
+pub struct SocketDelta {
+    /// The cell coord of the socket.
+    cell_coord: CellCoord,
+    /// The socket's name after the delta is applied, or None if the socket was removed.
+    name: Option<String>,
+}
+
 impl BufferDelta {
     /// Create a delta from two buffers. `from` is the previous buffer, and `to` is the target
     /// buffer. In other words, the delta can be applied to `from` and will result in `to` as an
@@ -33,15 +42,23 @@ impl BufferDelta {
         // Start by collecting a full set of chunk coords we will be visiting, this is a union of
         // from and to chunks.
         let chunk_coords: HashSet<ChunkCoord> = from
-            .chunks
+            .chunks()
             .iter()
             .map(|c| c.chunk_coord)
-            .chain(to.chunks.iter().map(|c| c.chunk_coord))
+            .chain(to.chunks().iter().map(|c| c.chunk_coord))
             .collect();
 
         for chunk_coord in chunk_coords {
-            let from = from.chunks.iter().find(|c| c.chunk_coord == chunk_coord);
-            let to = to.chunks.iter().find(|c| c.chunk_coord == chunk_coord);
+            let from = from.get_chunk(chunk_coord);
+            let to = to.get_chunk(chunk_coord);
+
+            // Chunks a clone never wrote to are still shared, and can't have changed.
+            if let (Some(from), Some(to)) = (from, to) {
+                if from.shares_cells(to) {
+                    continue;
+                }
+            }
+
             let first_cell_coord = chunk_coord.first_cell_coord();
 
             for y in 0..CHUNK_SIZE {
@@ -83,7 +100,50 @@ impl BufferDelta {
             }
         }
 
-        Self { cell_deltas }
+        let mut socket_deltas: Vec<SocketDelta> = from
+            .sockets
+            .iter()
+            .filter(|f| !to.sockets.iter().any(|t| t.cell_coord == f.cell_coord))
+            .map(|f| SocketDelta {
+                cell_coord: f.cell_coord,
+                name: None,
+            })
+            .collect();
+
+        socket_deltas.extend(
+            to.sockets
+                .iter()
+                .filter(|t| !from.sockets.contains(t))
+                .map(|t| SocketDelta {
+                    cell_coord: t.cell_coord,
+                    name: Some(t.name.clone()),
+                }),
+        );
+
+        Self {
+            cell_deltas,
+            socket_deltas,
+        }
+    }
+
+    /// The number of cell and socket changes stored in the delta. Used as a rough measure of
+    /// how much memory the delta holds.
+    pub fn len(&self) -> usize {
+        self.cell_deltas.len() + self.socket_deltas.len()
+    }
+
+    /// True if applying the delta would have no effect.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every cell coord the delta touches, including those of added, removed or renamed sockets.
+    /// May contain duplicates.
+    pub fn cell_coords(&self) -> impl Iterator<Item = CellCoord> + '_ {
+        self.cell_deltas
+            .iter()
+            .map(|d| d.cell_coord)
+            .chain(self.socket_deltas.iter().map(|d| d.cell_coord))
     }
 
     /// Apply this delta to the `from` buffer, returning what would have been passed into the
@@ -95,6 +155,12 @@ impl BufferDelta {
             to.set_cell(cell_delta.cell_coord, cell_delta.cell);
         }
 
+        // Sockets are assigned exactly, without making names unique against the sockets still in
+        // the buffer. Those may be about to be renamed themselves (say, two names being swapped).
+        for socket_delta in &self.socket_deltas {
+            to.set_socket_unchecked(socket_delta.cell_coord, socket_delta.name.clone());
+        }
+
         to
     }
 }
@@ -1,18 +1,55 @@
 use base64::{engine::general_purpose::STANDARD, Engine};
-use std::io::prelude::*;
+use std::{collections::BTreeMap, io::prelude::*};
 use wasm_bindgen::prelude::*;
 
 use crate::{
-    coords::{ChunkCoord, CHUNK_CELL_COUNT},
-    substrate::buffer::{Buffer, BufferChunk},
+    coords::{CellCoord, ChunkCoord, CHUNK_CELL_COUNT},
+    impl_js_error,
+    substrate::buffer::{Buffer, BufferChunk, Socket},
     upc::UPC_BYTE_LEN,
 };
 
+#[derive(Debug, thiserror::Error)]
+pub enum BufferSerdeError {
+    #[error("failed to encode buffer: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+
+    #[error("failed to decode buffer: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+
+    #[error("invalid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("corrupt chunk data: {0}")]
+    Chunk(String),
+}
+
+impl_js_error!(BufferSerdeError);
+
 #[derive(bincode::Encode, bincode::Decode)]
 pub enum VersionWrapper {
     // Snappy compressed chunk data, using SNAPPY_CHANNELS bytes per cell with 128 cell wide
     // chunks. If either of these things change, the format version will need to be bumped.
     V1(SnappyBuffer),
+    // Identical chunk encoding to V1, plus the buffer's sockets and free-form metadata.
+    V2(BufferV2),
+}
+
+#[derive(bincode::Encode, bincode::Decode)]
+pub struct BufferV2 {
+    snappy_buffer: SnappyBuffer,
+    sockets: Vec<SerializedSocket>,
+
+    /// Opaque, keyed data that isn't part of the substrate itself (labels, module bindings and so
+    /// on). Unknown keys are carried through a load/save round trip untouched.
+    metadata: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(bincode::Encode, bincode::Decode)]
+pub struct SerializedSocket {
+    name: String,
+    x: i32,
+    y: i32,
 }
 
 #[derive(bincode::Encode, bincode::Decode)]
@@ -31,23 +68,102 @@ pub struct SnappyChunk {
 
 #[wasm_bindgen]
 impl Buffer {
-    pub fn to_base64_string(&self) -> Result<String, JsValue> {
+    pub fn to_base64_string(&self) -> Result<String, BufferSerdeError> {
         let bytes = self.to_bytes()?;
         Ok(STANDARD.encode(bytes))
     }
 
-    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
-        const SNAPPY_CHANNELS: usize = 2;
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BufferSerdeError> {
+        self.to_bytes_with_metadata(Default::default())
+    }
+
+    pub fn from_base64_string(base_64_string: &str) -> Result<Buffer, BufferSerdeError> {
+        let bytes = STANDARD.decode(base_64_string)?;
+        Buffer::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Buffer, BufferSerdeError> {
+        Ok(Buffer::from_bytes_with_metadata(bytes)?.0)
+    }
+}
+
+impl Buffer {
+    /// Encodes the buffer (as the latest format version) along with arbitrary metadata.
+    pub fn to_bytes_with_metadata(
+        &self,
+        metadata: BTreeMap<String, Vec<u8>>,
+    ) -> Result<Vec<u8>, BufferSerdeError> {
+        let sockets = self
+            .sockets
+            .iter()
+            .map(|socket| SerializedSocket {
+                name: socket.name.clone(),
+                x: socket.cell_coord.0.x,
+                y: socket.cell_coord.0.y,
+            })
+            .collect();
+
+        // Bincode the frames
+        let final_bytes = bincode::encode_to_vec(
+            VersionWrapper::V2(BufferV2 {
+                snappy_buffer: SnappyBuffer::from_buffer(self),
+                sockets,
+                metadata,
+            }),
+            bincode::config::standard(),
+        )?;
+
+        Ok(final_bytes)
+    }
+
+    /// Decodes a buffer from any known format version, returning it along with any metadata that
+    /// was stored with it (always empty for V1).
+    pub fn from_bytes_with_metadata(
+        bytes: &[u8],
+    ) -> Result<(Buffer, BTreeMap<String, Vec<u8>>), BufferSerdeError> {
+        let (version, _bytes_read): (VersionWrapper, _) =
+            bincode::decode_from_slice(bytes, bincode::config::standard())?;
+
+        match version {
+            VersionWrapper::V1(snappy_buffer) => Ok((snappy_buffer.to_buffer()?, BTreeMap::new())),
+            VersionWrapper::V2(BufferV2 {
+                snappy_buffer,
+                sockets,
+                metadata,
+            }) => {
+                let mut buffer = snappy_buffer.to_buffer()?;
+
+                // The socket bit itself is stored in the cell data, so there is no need to go
+                // through `set_socket` (which would also re-uniquify names).
+                buffer.sockets = sockets
+                    .into_iter()
+                    .map(|socket| Socket {
+                        name: socket.name,
+                        cell_coord: CellCoord((socket.x, socket.y).into()),
+                    })
+                    .collect();
+
+                Ok((buffer, metadata))
+            }
+        }
+    }
+}
+
+impl SnappyBuffer {
+    const SNAPPY_CHANNELS: usize = 2;
+
+    fn from_buffer(buffer: &Buffer) -> Self {
         let mut snappy_buffer = SnappyBuffer {
             chunks: Vec::new(),
-            channels: SNAPPY_CHANNELS as u32,
+            channels: Self::SNAPPY_CHANNELS as u32,
         };
 
-        for chunk in &self.chunks {
-            let mut snappy_image = [0_u8; CHUNK_CELL_COUNT * SNAPPY_CHANNELS];
+        for chunk in buffer.chunks() {
+            let mut snappy_image = [0_u8; CHUNK_CELL_COUNT * Self::SNAPPY_CHANNELS];
             for i in 0..CHUNK_CELL_COUNT {
-                for j in 0..SNAPPY_CHANNELS {
-                    snappy_image[i * SNAPPY_CHANNELS + j] = chunk.get_cells()[i * UPC_BYTE_LEN + j];
+                for j in 0..Self::SNAPPY_CHANNELS {
+                    snappy_image[i * Self::SNAPPY_CHANNELS + j] =
+                        chunk.get_cells()[i * UPC_BYTE_LEN + j];
                 }
             }
 
@@ -64,68 +180,115 @@ impl Buffer {
             });
         }
 
-        // Bincode the frames
-        let final_bytes = bincode::encode_to_vec(
-            VersionWrapper::V1(snappy_buffer),
-            bincode::config::standard(),
-        )
-        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        snappy_buffer
+    }
 
-        Ok(final_bytes)
+    fn to_buffer(&self) -> Result<Buffer, BufferSerdeError> {
+        let channels = self.channels as usize;
+        let mut buffer = Buffer::new();
+        let mut snappy_image = vec![0_u8; CHUNK_CELL_COUNT * channels];
+
+        for chunk in &self.chunks {
+            let mut reader = snap::read::FrameDecoder::new(&chunk.data[..]);
+            match reader.read(&mut snappy_image[..]) {
+                Ok(read_size) => {
+                    if read_size != CHUNK_CELL_COUNT * channels {
+                        return Err(BufferSerdeError::Chunk(format!(
+                            "expected {} bytes but {} bytes were read",
+                            CHUNK_CELL_COUNT * channels,
+                            read_size
+                        )));
+                    }
+                }
+                Err(e) => {
+                    return Err(BufferSerdeError::Chunk(format!("{}", e)));
+                }
+            }
+
+            // Convert to standard chunk
+            let mut buffer_chunk =
+                BufferChunk::new(ChunkCoord((chunk.chunk_x, chunk.chunk_y).into()));
+            let cells = buffer_chunk.get_cells_mut();
+
+            for i in 0..CHUNK_CELL_COUNT {
+                for j in 0..channels {
+                    cells[i * UPC_BYTE_LEN + j] = snappy_image[i * channels + j];
+                }
+            }
+
+            buffer_chunk.cell_count = chunk.cell_count as usize;
+            buffer.insert_chunk(buffer_chunk);
+        }
+
+        Ok(buffer)
     }
+}
 
-    pub fn from_base64_string(base_64_string: &str) -> Result<Buffer, JsValue> {
-        let bytes = STANDARD
-            .decode(base_64_string)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        Buffer::from_bytes(&bytes)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_buffer() -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.draw_metal_link(None, (0, 0).into());
+        for x in 1..200 {
+            buffer.draw_metal_link(Some((x - 1, 0).into()), (x, 0).into());
+        }
+        buffer.draw_si_link(None, (-10, -10).into(), true);
+        buffer.draw_si_link(Some((-10, -10).into()), (-10, -9).into(), true);
+        buffer.set_socket((0, 0).into(), Some("A".to_string()));
+        buffer.set_socket((199, 0).into(), Some("B".to_string()));
+        buffer
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<Buffer, JsValue> {
-        let (version, _bytes_read): (VersionWrapper, _) =
-            bincode::decode_from_slice(&bytes, bincode::config::standard())
-                .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    fn assert_cells_eq(a: &Buffer, b: &Buffer) {
+        assert_eq!(a.chunks().len(), b.chunks().len());
+        for chunk in a.chunks() {
+            let other = b
+                .get_chunk(chunk.chunk_coord)
+                .expect("chunk missing after round trip");
+            assert_eq!(chunk.cell_count, other.cell_count);
+            assert!(chunk.get_cells()[..] == other.get_cells()[..]);
+        }
+    }
 
-        match version {
-            VersionWrapper::V1(snappy_buffer) => {
-                let channels = snappy_buffer.channels as usize;
-                let mut buffer = Buffer::new();
-                let mut snappy_image = vec![0_u8; CHUNK_CELL_COUNT * channels];
-
-                for chunk in &snappy_buffer.chunks {
-                    let mut reader = snap::read::FrameDecoder::new(&chunk.data[..]);
-                    match reader.read(&mut snappy_image[..]) {
-                        Ok(read_size) => {
-                            if read_size != CHUNK_CELL_COUNT * channels {
-                                return Err(JsValue::from_str(&format!(
-                                    "expected {} bytes but {} bytes were read",
-                                    CHUNK_CELL_COUNT * channels,
-                                    read_size
-                                )));
-                            }
-                        }
-                        Err(e) => {
-                            return Err(JsValue::from_str(&format!("{}", e)));
-                        }
-                    }
+    #[test]
+    fn round_trip_keeps_sockets() {
+        let buffer = make_buffer();
+        let decoded = Buffer::from_bytes(&buffer.to_bytes().unwrap()).unwrap();
+
+        assert_cells_eq(&buffer, &decoded);
+        assert_eq!(decoded.sockets.len(), 2);
+        for (a, b) in buffer.sockets.iter().zip(decoded.sockets.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.cell_coord, b.cell_coord);
+        }
+    }
 
-                    // Convert to standard chunk
-                    let mut buffer_chunk =
-                        BufferChunk::new(ChunkCoord((chunk.chunk_x, chunk.chunk_y).into()));
-                    let cells = buffer_chunk.get_cells_mut();
+    #[test]
+    fn round_trip_keeps_metadata() {
+        let buffer = make_buffer();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("labels".to_string(), vec![1, 2, 3]);
 
-                    for i in 0..CHUNK_CELL_COUNT {
-                        for j in 0..channels {
-                            cells[i * UPC_BYTE_LEN + j] = snappy_image[i * channels + j];
-                        }
-                    }
+        let bytes = buffer.to_bytes_with_metadata(metadata.clone()).unwrap();
+        let (decoded, decoded_metadata) = Buffer::from_bytes_with_metadata(&bytes).unwrap();
 
-                    buffer_chunk.cell_count = chunk.cell_count as usize;
-                    buffer.chunks.push(buffer_chunk);
-                }
+        assert_cells_eq(&buffer, &decoded);
+        assert_eq!(metadata, decoded_metadata);
+    }
 
-                Ok(buffer)
-            }
-        }
+    #[test]
+    fn decodes_v1() {
+        let buffer = make_buffer();
+        let bytes = bincode::encode_to_vec(
+            VersionWrapper::V1(SnappyBuffer::from_buffer(&buffer)),
+            bincode::config::standard(),
+        )
+        .unwrap();
+
+        let decoded = Buffer::from_bytes(&bytes).unwrap();
+        assert_cells_eq(&buffer, &decoded);
+        assert!(decoded.sockets.is_empty());
     }
 }
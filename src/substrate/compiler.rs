@@ -1,14 +1,18 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     iter::FromIterator,
 };
 
+use glam::IVec2;
 use wasm_bindgen::prelude::*;
 
 use crate::{
     coords::{CellCoord, ChunkCoord, CHUNK_SIZE, LOG_CHUNK_SIZE},
     log,
-    substrate::buffer::Buffer,
+    substrate::{
+        buffer::{Buffer, BufferChunk},
+        buffer_delta::BufferDelta,
+    },
     upc::{Bit, Metal, NormalizedCell, Silicon},
 };
 
@@ -24,6 +28,15 @@ pub struct CompilerResults {
     pub trace_to_cell_part_index_by_chunk: HashMap<ChunkCoord, Vec<CellPartToTrace>>,
 }
 
+/// Where traces and gates moved to in a `CompilerResults::recompile`, indexed by their index
+/// before it. None for traces and gates invalidated by the edit, which either no longer exist or
+/// were re-explored under a new index.
+#[derive(Debug, Clone, Default)]
+pub struct IndexRemap {
+    pub traces: Vec<Option<usize>>,
+    pub gates: Vec<Option<usize>>,
+}
+
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 #[wasm_bindgen]
 pub struct Atom {
@@ -60,6 +73,8 @@ pub struct CellPartToTrace {
 
 #[derive(Debug, Clone, Copy)]
 pub struct Gate {
+    /// The cell of the MOSFET.
+    pub coord: CellCoord,
     pub is_npn: bool,
     pub base_trace: usize,
     pub left_ec_trace: usize,
@@ -100,96 +115,35 @@ impl CompilerResults {
                 continue;
             }
 
-            CompilerResults::explore_trace(
+            let trace = CompilerResults::explore_trace(
                 buffer,
                 atom,
+                traces.len(),
                 &mut trace_lookup_by_atom,
-                &mut traces,
                 &mut edge_set,
                 &mut base_atoms,
             );
+            traces.push(trace);
         }
 
-        let mut gates = vec![];
-        let mut trace_to_cell_part_index_by_chunk = HashMap::new();
-
         // Now that we have all traces built up, we can create the Gates with back-references to
         // trace index on their EC atoms.
-        for atom in base_atoms {
-            let cell = buffer.get_cell(atom.coord);
-            let gate = Gate {
-                is_npn: cell.get_bit(Bit::SI_N),
-                base_trace: *trace_lookup_by_atom.get(&atom).unwrap(),
-                left_ec_trace: *trace_lookup_by_atom
-                    .get(&Atom {
-                        coord: atom.coord.clone(),
-                        part: CellPart::EcUpLeft,
-                    })
-                    .unwrap(),
-                right_ec_trace: *trace_lookup_by_atom
-                    .get(&Atom {
-                        coord: atom.coord.clone(),
-                        part: CellPart::EcDownRight,
-                    })
-                    .unwrap(),
-            };
-
-            gates.push(gate);
-        }
+        let gates = base_atoms
+            .into_iter()
+            .map(|atom| Self::build_gate(buffer, &trace_lookup_by_atom, atom).unwrap())
+            .collect();
 
         // Create an index for quickly copying trace states over to a BufferMask.
-        for chunk in &buffer.chunks {
-            let mut trace_indexes = vec![];
-
-            // TODO: This can be made a lot more efficient if compilation times ever become a
-            // problem.
-            for y in 0..CHUNK_SIZE {
-                for x in 0..CHUNK_SIZE {
-                    let coord = CellCoord::from_offset_into_chunk(&chunk.chunk_coord, x, y);
-
-                    // Skip empty cells.
-                    if chunk.get_cell(coord.clone()) == Default::default() {
-                        continue;
-                    }
-
-                    let i = (y << LOG_CHUNK_SIZE) + x;
-
-                    trace_indexes.push(CellPartToTrace {
-                        cell_index_in_chunk: i,
-                        metal_trace: trace_lookup_by_atom
-                            .get(&Atom {
-                                coord: coord.clone(),
-                                part: CellPart::Metal,
-                            })
-                            .cloned()
-                            .unwrap_or_default(),
-                        si_trace: trace_lookup_by_atom
-                            .get(&Atom {
-                                coord: coord.clone(),
-                                part: CellPart::Si,
-                            })
-                            .cloned()
-                            .unwrap_or_default(),
-                        left_ec_trace: trace_lookup_by_atom
-                            .get(&Atom {
-                                coord: coord.clone(),
-                                part: CellPart::EcUpLeft,
-                            })
-                            .cloned()
-                            .unwrap_or_default(),
-                        right_ec_trace: trace_lookup_by_atom
-                            .get(&Atom {
-                                coord: coord.clone(),
-                                part: CellPart::EcDownRight,
-                            })
-                            .cloned()
-                            .unwrap_or_default(),
-                    });
-                }
-            }
-
-            trace_to_cell_part_index_by_chunk.insert(chunk.chunk_coord, trace_indexes);
-        }
+        let trace_to_cell_part_index_by_chunk = buffer
+            .chunks()
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.chunk_coord,
+                    Self::index_chunk(chunk, &trace_lookup_by_atom),
+                )
+            })
+            .collect();
 
         CompilerResults {
             traces,
@@ -204,31 +158,29 @@ impl CompilerResults {
         edge_set.push_back(edge_atom);
 
         let mut base_atoms = vec![];
-        let mut traces = vec![vec![]];
         let mut trace_lookup_by_atom = HashMap::new();
 
         CompilerResults::explore_trace(
             buffer,
             edge_atom,
+            1,
             &mut trace_lookup_by_atom,
-            &mut traces,
             &mut edge_set,
             &mut base_atoms,
-        );
-
-        traces[1].clone()
+        )
     }
 
+    /// Explores the trace conductively connected to `atom`, recording its atoms in the lookup
+    /// under `trace_idx` and returning them.
     fn explore_trace(
         buffer: &Buffer,
         atom: Atom,
+        trace_idx: usize,
         trace_lookup_by_atom: &mut HashMap<Atom, usize>,
-        traces: &mut Vec<Vec<Atom>>,
         edge_set: &mut VecDeque<Atom>,
         base_atoms: &mut Vec<Atom>,
-    ) {
+    ) -> Vec<Atom> {
         let mut trace = vec![];
-        let trace_idx = traces.len();
         let mut trace_edge_set = VecDeque::from_iter([atom.clone()]);
 
         while let Some(atom) = trace_edge_set.pop_front() {
@@ -452,6 +404,302 @@ impl CompilerResults {
             }
         }
 
-        traces.push(trace);
+        trace
+    }
+}
+
+impl CompilerResults {
+    /// Brings the results up to date with `buffer`, which must be the buffer they were compiled
+    /// from with `delta` applied. Only traces with an atom in or beside a changed cell (as
+    /// connections span neighboring cells) are invalidated and re-explored, every other trace
+    /// keeps its index, and gates keep their relative order. New traces reuse the indices of
+    /// invalidated ones before growing the list, which may leave some traces empty.
+    ///
+    /// Like `from_buffer`, only what's reachable from a socket is compiled: traces and gates that
+    /// an edit disconnected from every socket are dropped (and remapped to None).
+    pub fn recompile(&mut self, buffer: &Buffer, delta: &BufferDelta) -> IndexRemap {
+        let changed_coords: HashSet<CellCoord> = delta.cell_coords().collect();
+        let mut invalid_traces = HashSet::new();
+        let mut dirty_chunks: HashSet<ChunkCoord> = HashSet::new();
+        let mut edge_set = VecDeque::new();
+
+        for coord in &changed_coords {
+            dirty_chunks.insert(coord.into());
+
+            for (x, y) in [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let coord = CellCoord(coord.0 + IVec2::new(x, y));
+                for part in CellPart::ALL {
+                    if let Some(&trace) = self.trace_lookup_by_atom.get(&Atom { coord, part }) {
+                        invalid_traces.insert(trace);
+                    }
+                }
+            }
+
+            edge_set.extend(
+                CellPart::ALL
+                    .iter()
+                    .map(|&part| Atom {
+                        coord: *coord,
+                        part,
+                    })
+                    .filter(|atom| has_part(buffer, atom)),
+            );
+        }
+
+        // Sockets seed exploration just as they do in `from_buffer`, which also gives a socket on
+        // an empty cell its (single atom) trace.
+        edge_set.extend(buffer.sockets.iter().map(|socket| Atom {
+            coord: socket.cell_coord,
+            part: CellPart::Metal,
+        }));
+
+        // Un-compile invalidated traces, seeding exploration with whatever of them is left.
+        let mut free_traces: Vec<usize> = invalid_traces.iter().copied().collect();
+        free_traces.sort_unstable_by(|a, b| b.cmp(a));
+
+        for &trace in &free_traces {
+            for atom in std::mem::take(&mut self.traces[trace]) {
+                self.trace_lookup_by_atom.remove(&atom);
+                dirty_chunks.insert(atom.coord.into());
+                if has_part(buffer, &atom) {
+                    edge_set.push_back(atom);
+                }
+            }
+        }
+
+        let mut remap = IndexRemap {
+            traces: (0..self.traces.len())
+                .map(|i| (!invalid_traces.contains(&i)).then_some(i))
+                .collect(),
+            gates: vec![],
+        };
+
+        let mut base_atoms = vec![];
+        while let Some(atom) = edge_set.pop_front() {
+            if self.trace_lookup_by_atom.contains_key(&atom) {
+                continue;
+            }
+
+            let trace_idx = free_traces.pop().unwrap_or(self.traces.len());
+            let trace = CompilerResults::explore_trace(
+                buffer,
+                atom,
+                trace_idx,
+                &mut self.trace_lookup_by_atom,
+                &mut edge_set,
+                &mut base_atoms,
+            );
+
+            dirty_chunks.extend(trace.iter().map(|atom| ChunkCoord::from(atom.coord)));
+            if trace_idx == self.traces.len() {
+                self.traces.push(trace);
+            } else {
+                self.traces[trace_idx] = trace;
+            }
+        }
+
+        // Keep gates that only touch valid traces. The rest are rebuilt, including those whose
+        // base trace survived (and so was never re-explored to rediscover the base atom).
+        let mut gates = vec![];
+        let mut gate_coords = HashSet::new();
+        for gate in &self.gates {
+            let valid = [gate.base_trace, gate.left_ec_trace, gate.right_ec_trace]
+                .iter()
+                .all(|trace| !invalid_traces.contains(trace));
+
+            if valid {
+                remap.gates.push(Some(gates.len()));
+                gate_coords.insert(gate.coord);
+                gates.push(*gate);
+            } else {
+                remap.gates.push(None);
+                base_atoms.push(Atom {
+                    coord: gate.coord,
+                    part: CellPart::Si,
+                });
+            }
+        }
+
+        for atom in base_atoms {
+            if !gate_coords.insert(atom.coord) {
+                continue;
+            }
+
+            if let Some(gate) = Self::build_gate(buffer, &self.trace_lookup_by_atom, atom) {
+                gates.push(gate);
+            }
+        }
+
+        // Find every trace reachable from a socket. A gate connects its base and EC traces both
+        // ways, as `from_buffer` explores the ECs off a base and the base off an EC.
+        let mut gates_by_trace = vec![vec![]; self.traces.len()];
+        for (i, gate) in gates.iter().enumerate() {
+            for trace in [gate.base_trace, gate.left_ec_trace, gate.right_ec_trace] {
+                gates_by_trace[trace].push(i);
+            }
+        }
+
+        let mut reachable = vec![false; self.traces.len()];
+        let mut stack: Vec<usize> = buffer
+            .sockets
+            .iter()
+            .filter_map(|socket| {
+                self.trace_lookup_by_atom
+                    .get(&Atom {
+                        coord: socket.cell_coord,
+                        part: CellPart::Metal,
+                    })
+                    .copied()
+            })
+            .collect();
+
+        while let Some(trace) = stack.pop() {
+            if std::mem::replace(&mut reachable[trace], true) {
+                continue;
+            }
+
+            for &i in &gates_by_trace[trace] {
+                let gate = &gates[i];
+                stack.extend([gate.base_trace, gate.left_ec_trace, gate.right_ec_trace]);
+            }
+        }
+
+        // Drop the rest, along with their gates.
+        for (trace, &reachable) in reachable.iter().enumerate().skip(1) {
+            if reachable || self.traces[trace].is_empty() {
+                continue;
+            }
+
+            for atom in std::mem::take(&mut self.traces[trace]) {
+                self.trace_lookup_by_atom.remove(&atom);
+                dirty_chunks.insert(atom.coord.into());
+            }
+
+            if let Some(remapped) = remap.traces.get_mut(trace) {
+                *remapped = None;
+            }
+        }
+
+        let mut kept = vec![None; gates.len()];
+        let mut next = 0;
+        for (i, gate) in gates.iter().enumerate() {
+            if reachable[gate.base_trace] {
+                kept[i] = Some(next);
+                next += 1;
+            }
+        }
+        for remapped in &mut remap.gates {
+            *remapped = remapped.and_then(|i| kept[i]);
+        }
+        gates.retain(|gate| reachable[gate.base_trace]);
+        self.gates = gates;
+
+        for chunk_coord in dirty_chunks {
+            match buffer.get_chunk(chunk_coord) {
+                Some(chunk) => {
+                    self.trace_to_cell_part_index_by_chunk.insert(
+                        chunk_coord,
+                        Self::index_chunk(chunk, &self.trace_lookup_by_atom),
+                    );
+                }
+                None => {
+                    self.trace_to_cell_part_index_by_chunk.remove(&chunk_coord);
+                }
+            }
+        }
+
+        remap
+    }
+
+    /// Builds the gate for a MOSFET's base atom, or None if the cell isn't a MOSFET or any of its
+    /// traces haven't been compiled.
+    fn build_gate(
+        buffer: &Buffer,
+        trace_lookup_by_atom: &HashMap<Atom, usize>,
+        atom: Atom,
+    ) -> Option<Gate> {
+        let cell = buffer.get_cell(atom.coord);
+        let norm: NormalizedCell = cell.into();
+        if !matches!(norm.si, Silicon::Mosfet { .. }) {
+            return None;
+        }
+
+        let trace = |part| {
+            trace_lookup_by_atom
+                .get(&Atom {
+                    coord: atom.coord,
+                    part,
+                })
+                .copied()
+        };
+
+        Some(Gate {
+            coord: atom.coord,
+            is_npn: cell.get_bit(Bit::SI_N),
+            base_trace: trace(CellPart::Si)?,
+            left_ec_trace: trace(CellPart::EcUpLeft)?,
+            right_ec_trace: trace(CellPart::EcDownRight)?,
+        })
+    }
+
+    /// Maps each non-empty cell of the chunk to the traces of its parts.
+    fn index_chunk(
+        chunk: &BufferChunk,
+        trace_lookup_by_atom: &HashMap<Atom, usize>,
+    ) -> Vec<CellPartToTrace> {
+        let mut trace_indexes = vec![];
+
+        // TODO: This can be made a lot more efficient if compilation times ever become a
+        // problem.
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let coord = CellCoord::from_offset_into_chunk(&chunk.chunk_coord, x, y);
+
+                // Skip empty cells.
+                if chunk.get_cell(coord.clone()) == Default::default() {
+                    continue;
+                }
+
+                let i = (y << LOG_CHUNK_SIZE) + x;
+                let trace = |part| {
+                    trace_lookup_by_atom
+                        .get(&Atom {
+                            coord: coord.clone(),
+                            part,
+                        })
+                        .cloned()
+                        .unwrap_or_default()
+                };
+
+                trace_indexes.push(CellPartToTrace {
+                    cell_index_in_chunk: i,
+                    metal_trace: trace(CellPart::Metal),
+                    si_trace: trace(CellPart::Si),
+                    left_ec_trace: trace(CellPart::EcUpLeft),
+                    right_ec_trace: trace(CellPart::EcDownRight),
+                });
+            }
+        }
+
+        trace_indexes
+    }
+}
+
+impl CellPart {
+    pub const ALL: [CellPart; 4] = [
+        CellPart::Metal,
+        CellPart::Si,
+        CellPart::EcUpLeft,
+        CellPart::EcDownRight,
+    ];
+}
+
+/// True if the atom's cell (still) has that part.
+fn has_part(buffer: &Buffer, atom: &Atom) -> bool {
+    let cell: NormalizedCell = buffer.get_cell(atom.coord).into();
+    match atom.part {
+        CellPart::Metal => cell.metal != Metal::None,
+        CellPart::Si => cell.si != Silicon::None,
+        CellPart::EcUpLeft | CellPart::EcDownRight => matches!(cell.si, Silicon::Mosfet { .. }),
     }
 }
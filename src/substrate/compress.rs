@@ -316,8 +316,8 @@ mod tests {
         let file = Cursor::new(Vec::new());
         let mut writer = brotli::CompressorWriter::new(file, 4096, 7, 22);
 
-        for chunk in buffer.chunks.values() {
-            writer.write_all(&chunk.cells).unwrap();
+        for chunk in buffer.chunks() {
+            writer.write_all(&chunk.get_cells()[..]).unwrap();
         }
         writer.flush().unwrap();
 
@@ -334,11 +334,11 @@ mod tests {
         let file = Cursor::new(Vec::new());
         let mut writer = brotli::CompressorWriter::new(file, 4096, 7, 22);
 
-        for chunk in buffer.chunks.values() {
+        for chunk in buffer.chunks() {
             writer
                 .write_all(
                     &chunk
-                        .cells
+                        .get_cells()
                         .iter()
                         .enumerate()
                         .filter(|(i, _)| i % 4 < 2)
@@ -361,11 +361,11 @@ mod tests {
 
         let mut writer = snap::write::FrameEncoder::new(Vec::new());
 
-        for chunk in buffer.chunks.values() {
+        for chunk in buffer.chunks() {
             writer
                 .write_all(
                     &chunk
-                        .cells
+                        .get_cells()
                         .iter()
                         .enumerate()
                         .filter(|(i, _)| i % 4 < 2)
@@ -415,11 +415,11 @@ mod tests {
 
         let mut writer = snap::write::FrameEncoder::new(Vec::new());
 
-        for chunk in buffer.chunks.values() {
+        for chunk in buffer.chunks() {
             writer
                 .write_all(
                     &chunk
-                        .cells
+                        .get_cells()
                         .iter()
                         .enumerate()
                         .filter(|(i, _)| i % 4 < 2)
@@ -489,27 +489,27 @@ mod tests {
         let buffer = import_legacy_blueprint(json).unwrap();
 
         let x_min = buffer
-            .chunks
-            .keys()
-            .map(|k| k.first_cell_coord().0.x)
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_coord.first_cell_coord().0.x)
             .min()
             .unwrap();
         let y_min = buffer
-            .chunks
-            .keys()
-            .map(|k| k.first_cell_coord().0.y)
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_coord.first_cell_coord().0.y)
             .min()
             .unwrap();
         let x_max = buffer
-            .chunks
-            .keys()
-            .map(|k| k.last_cell_coord().0.x)
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_coord.last_cell_coord().0.x)
             .max()
             .unwrap();
         let y_max = buffer
-            .chunks
-            .keys()
-            .map(|k| k.last_cell_coord().0.y)
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_coord.last_cell_coord().0.y)
             .max()
             .unwrap();
 
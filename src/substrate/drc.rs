@@ -0,0 +1,270 @@
+use std::{collections::HashMap, fmt};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    coords::CellCoord,
+    substrate::{
+        buffer::Buffer,
+        compiler::{Atom, CellPart, CompilerResults},
+        mask::Mask,
+    },
+    upc::{Metal, NormalizedCell, Silicon},
+};
+
+/// A single design rule violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub cell_coord: CellCoord,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticKind {
+    /// Metal that points at a neighbor which doesn't point back.
+    HalfConnectedMetal,
+    /// Silicon (or a MOSFET) that points at a neighbor which can't or doesn't connect back.
+    HalfConnectedSi,
+    /// A via placed on a MOSFET, which the compiler won't connect to anything.
+    ViaOnMosfet,
+    /// A socket on a cell with no metal, so nothing can bond to it.
+    SocketWithoutMetal,
+    /// A MOSFET whose gate trace is connected to neither a socket nor another MOSFET's EC, so it
+    /// can never switch.
+    UndrivenGate,
+}
+
+/// The result of a design rule check (DRC) over a buffer.
+#[wasm_bindgen]
+pub struct DesignRuleCheck {
+    #[wasm_bindgen(skip)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[wasm_bindgen]
+impl DesignRuleCheck {
+    /// Compiles the buffer and checks it.
+    #[wasm_bindgen(constructor)]
+    pub fn from_buffer(buffer: &Buffer) -> DesignRuleCheck {
+        Self::from_compiled(buffer, &CompilerResults::from_buffer(buffer))
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Human readable descriptions of each diagnostic.
+    pub fn messages(&self) -> Vec<String> {
+        self.diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    /// A mask highlighting the offending part of every diagnosed cell.
+    pub fn to_mask(&self) -> Mask {
+        Mask::from_atoms(self.diagnostics.iter().flat_map(|diagnostic| {
+            diagnostic.kind.parts().iter().map(|&part| Atom {
+                coord: diagnostic.cell_coord,
+                part,
+            })
+        }))
+    }
+}
+
+impl DesignRuleCheck {
+    /// Checks a buffer that has already been compiled (`compiler_results` must be from `buffer`).
+    /// Diagnostics are ordered by cell (row by row, bottom to top), then kind.
+    pub fn from_compiled(buffer: &Buffer, compiler_results: &CompilerResults) -> DesignRuleCheck {
+        let mut diagnostics = vec![];
+
+        // Memoized per gate trace, as many gates tend to share one.
+        let mut driven_traces = HashMap::new();
+
+        for (cell_coord, upc) in buffer.iter_cells() {
+            let cell: NormalizedCell = upc.into();
+            let fixed = buffer.fixed_cell(cell_coord);
+            let mut push = |kind| diagnostics.push(Diagnostic { kind, cell_coord });
+
+            if cell.metal != fixed.metal {
+                push(DiagnosticKind::HalfConnectedMetal);
+            }
+
+            if cell.si != fixed.si {
+                push(DiagnosticKind::HalfConnectedSi);
+            }
+
+            if let Silicon::Mosfet { .. } = cell.si {
+                if let Metal::Trace { has_via: true, .. } = cell.metal {
+                    push(DiagnosticKind::ViaOnMosfet);
+                }
+
+                // Gates not reachable from any socket were never compiled, and so are undriven.
+                let driven = compiler_results
+                    .trace_lookup_by_atom
+                    .get(&Atom {
+                        coord: cell_coord,
+                        part: CellPart::Si,
+                    })
+                    .map(|&trace| {
+                        *driven_traces
+                            .entry(trace)
+                            .or_insert_with(|| is_trace_driven(buffer, compiler_results, trace))
+                    })
+                    .unwrap_or(false);
+
+                if !driven {
+                    push(DiagnosticKind::UndrivenGate);
+                }
+            }
+        }
+
+        for socket in &buffer.sockets {
+            let cell: NormalizedCell = buffer.get_cell(socket.cell_coord).into();
+            if cell.metal == Metal::None {
+                diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::SocketWithoutMetal,
+                    cell_coord: socket.cell_coord,
+                });
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.cell_coord.0.y, d.cell_coord.0.x, d.kind));
+
+        DesignRuleCheck { diagnostics }
+    }
+}
+
+impl DiagnosticKind {
+    /// The cell parts highlighted in the DRC mask for this kind of violation.
+    pub fn parts(&self) -> &'static [CellPart] {
+        match self {
+            DiagnosticKind::HalfConnectedMetal => &[CellPart::Metal],
+            DiagnosticKind::HalfConnectedSi => &[CellPart::Si],
+            DiagnosticKind::ViaOnMosfet => &[CellPart::Metal, CellPart::Si],
+            DiagnosticKind::SocketWithoutMetal => &[
+                CellPart::Metal,
+                CellPart::Si,
+                CellPart::EcUpLeft,
+                CellPart::EcDownRight,
+            ],
+            DiagnosticKind::UndrivenGate => &[CellPart::Si],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = (self.cell_coord.0.x, self.cell_coord.0.y);
+        let message = match self.kind {
+            DiagnosticKind::HalfConnectedMetal => {
+                "metal connects to a neighbor that doesn't connect back"
+            }
+            DiagnosticKind::HalfConnectedSi => {
+                "silicon connects to a neighbor that doesn't connect back"
+            }
+            DiagnosticKind::ViaOnMosfet => "via placed on a transistor",
+            DiagnosticKind::SocketWithoutMetal => "socket has no metal to bond to",
+            DiagnosticKind::UndrivenGate => "transistor gate is never driven",
+        };
+
+        write!(f, "({}, {}): {}", x, y, message)
+    }
+}
+
+/// A trace is driven if it reaches a socket (which modules bond to) or is the EC side of a
+/// MOSFET.
+fn is_trace_driven(buffer: &Buffer, compiler_results: &CompilerResults, trace: usize) -> bool {
+    compiler_results.traces[trace]
+        .iter()
+        .any(|atom| match atom.part {
+            CellPart::Metal => buffer.sockets.iter().any(|s| s.cell_coord == atom.coord),
+            CellPart::Si => false,
+            CellPart::EcUpLeft | CellPart::EcDownRight => true,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An NPN MOSFET at the origin, with its EC running vertically and its gate coming in from
+    /// the left.
+    fn mosfet_buffer() -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.draw_si_link(None, (0, -1).into(), true);
+        buffer.draw_si_link(Some((0, -1).into()), (0, 0).into(), true);
+        buffer.draw_si_link(Some((0, 0).into()), (0, 1).into(), true);
+        buffer.draw_si_link(None, (-1, 0).into(), false);
+        buffer.draw_si_link(Some((-1, 0).into()), (0, 0).into(), false);
+        buffer
+    }
+
+    fn kinds_at(check: &DesignRuleCheck, x: i32, y: i32) -> Vec<DiagnosticKind> {
+        check
+            .diagnostics
+            .iter()
+            .filter(|d| d.cell_coord == (x, y).into())
+            .map(|d| d.kind)
+            .collect()
+    }
+
+    #[test]
+    fn flags_undriven_gates_until_driven() {
+        let mut buffer = mosfet_buffer();
+        let check = DesignRuleCheck::from_buffer(&buffer);
+        assert_eq!(kinds_at(&check, 0, 0), vec![DiagnosticKind::UndrivenGate]);
+
+        // Drive the gate from a socket, through a via.
+        buffer.draw_metal_link(None, (-1, 0).into());
+        buffer.draw_via((-1, 0).into());
+        buffer.set_socket((-1, 0).into(), Some("G".to_string()));
+
+        let check = DesignRuleCheck::from_buffer(&buffer);
+        assert!(check.is_clean(), "{:?}", check.messages());
+    }
+
+    #[test]
+    fn flags_broken_cells_and_sockets() {
+        let mut buffer = mosfet_buffer();
+
+        // Half-connected metal: the right end of the trace is erased.
+        buffer.draw_metal_link(None, (5, 5).into());
+        buffer.draw_metal_link(Some((5, 5).into()), (6, 5).into());
+        buffer.set_cell((6, 5).into(), Default::default());
+
+        // A via forced onto the MOSFET.
+        let mut cell: NormalizedCell = buffer.get_cell((0, 0).into()).into();
+        cell.metal = Metal::Trace {
+            has_via: true,
+            has_socket: false,
+            has_bond_pad: false,
+            placement: Default::default(),
+        };
+        buffer.set_cell((0, 0).into(), cell.into());
+
+        buffer.set_socket((10, 10).into(), Some("NC".to_string()));
+        buffer.set_socket((3, -5).into(), Some("NC".to_string()));
+
+        let check = DesignRuleCheck::from_buffer(&buffer);
+        assert_eq!(
+            kinds_at(&check, 5, 5),
+            vec![DiagnosticKind::HalfConnectedMetal]
+        );
+        assert_eq!(
+            kinds_at(&check, 0, 0),
+            vec![DiagnosticKind::ViaOnMosfet, DiagnosticKind::UndrivenGate]
+        );
+        assert_eq!(
+            kinds_at(&check, 10, 10),
+            vec![DiagnosticKind::SocketWithoutMetal]
+        );
+
+        // Sorted by cell, socket diagnostics included.
+        let coords: Vec<CellCoord> = check.diagnostics.iter().map(|d| d.cell_coord).collect();
+        assert_eq!(
+            coords,
+            [(3, -5), (0, 0), (0, 0), (5, 5), (10, 10)].map(CellCoord::from)
+        );
+
+        let mask = check.to_mask();
+        assert!(mask.get_chunk(CellCoord::from((5, 5))).is_some());
+    }
+}
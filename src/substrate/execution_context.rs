@@ -2,15 +2,29 @@ use crate::{
     coords::CellCoord,
     module::{Module, ModuleGpioHandle},
     substrate::{
+        breakpoint::{BreakpointError, BreakpointHandle, Breakpoints, Condition},
         buffer::Buffer,
-        compiler::{Atom, CellPart, CompilerResults},
-        mask::{Mask, MASK_BYTE_LEN},
+        buffer_delta::BufferDelta,
+        compiler::{Atom, CellPart, CompilerResults, IndexRemap},
+        fault::{Fault, FaultError},
+        mask::Mask,
+        scheduler::Scheduler,
+        snapshot::{ModuleSnapshot, Snapshot, SnapshotError, SnapshotRing},
+        toggle_coverage::ToggleCoverage,
+        waveform::WaveformRecorder,
     },
 };
 
 pub type ModuleHandle = usize;
 pub type BondWireHandle = isize;
 
+/// How many of the final ticks of a clock that fails to settle are watched for switching traces
+/// and gates, to report in the OscillationError.
+const OSCILLATION_WINDOW: usize = 64;
+
+/// The default `max_ticks_per_clock`.
+pub const DEFAULT_MAX_TICKS_PER_CLOCK: usize = 100_000;
+
 pub struct ExecutionContext {
     pub compiler_results: CompilerResults,
     pub modules: Vec<Box<dyn Module>>,
@@ -21,19 +35,66 @@ pub struct ExecutionContext {
     // Pending module triggers, indexed by ModuleHandle. When the inner vec is empty, the module
     // has no pending triggers.
     pub pending_module_triggers: Vec<Vec<ModuleGpioHandle>>,
+    /// How many ticks a clock may take to settle before it fails with an OscillationError.
     pub max_ticks_per_clock: usize,
     pub buffer_mask: Mask,
     pub state: SimState,
+    /// When set, sampled after each tick and clock.
+    pub recorder: Option<WaveformRecorder>,
+    /// When set, a snapshot is pushed after each clock (see `record_snapshots`).
+    pub snapshots: Option<SnapshotRing>,
+    /// Checked after each tick.
+    pub breakpoints: Breakpoints,
+    /// When set, counts trace toggles after each tick (see `record_toggle_coverage`).
+    pub toggle_coverage: Option<ToggleCoverage>,
+    scheduler: Scheduler,
+    /// Injected faults (see `inject_fault`).
+    faults: Vec<Fault>,
+    /// The forced state of each gate with a fault, indexed by gate. Empty without gate faults.
+    gate_faults: Vec<Option<bool>>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Debug, bincode::Encode, bincode::Decode)]
 pub struct SimState {
-    pub micro_ticks: usize,
+    /// A measure of propagation work: the number of traces visited while propagating. Formerly
+    /// `micro_ticks`, which counted sweeps over every gate instead.
+    pub traces_visited: usize,
     pub ticks: usize,
+    /// Fundamental clocks completed.
+    pub clocks: usize,
     pub gate_states: Vec<bool>,
     pub trace_states: Vec<bool>,
 }
 
+impl SimState {
+    /// Carries state over a recompile. Remapped traces and gates keep their state, everything
+    /// else starts low.
+    pub fn remap(&self, remap: &IndexRemap, compiler_results: &CompilerResults) -> SimState {
+        let mut gate_states = vec![false; compiler_results.gates.len()];
+        let mut trace_states = vec![false; compiler_results.traces.len()];
+
+        for (&state, new) in self.gate_states.iter().zip(&remap.gates) {
+            if let Some(i) = new {
+                gate_states[*i] = state;
+            }
+        }
+
+        for (&state, new) in self.trace_states.iter().zip(&remap.traces) {
+            if let Some(i) = new {
+                trace_states[*i] = state;
+            }
+        }
+
+        SimState {
+            traces_visited: self.traces_visited,
+            ticks: self.ticks,
+            clocks: self.clocks,
+            gate_states,
+            trace_states,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BondWire {
     // The trace this bond wire ultimately attaches.
@@ -49,30 +110,152 @@ pub struct BondWire {
     pub gpio_handle: ModuleGpioHandle,
 }
 
+#[derive(Debug, thiserror::Error)]
 pub enum CompilerError {
+    #[error("no socket named \"{0}\" to bond the module GPIO to")]
     MissingBondWire(String),
 }
 
+/// A clock that didn't settle within `max_ticks_per_clock` ticks, typically because of a ring
+/// oscillator or other feedback loop with an odd number of inversions.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "the circuit did not settle within {ticks} ticks, {} gates are still switching",
+    .gates.len()
+)]
+pub struct OscillationError {
+    /// The ticks executed before giving up.
+    pub ticks: usize,
+
+    /// Traces that changed state in the final ticks of the clock, in order.
+    pub traces: Vec<usize>,
+
+    /// Gates that changed state in the final ticks of the clock, in order.
+    pub gates: Vec<usize>,
+}
+
+impl OscillationError {
+    /// A mask highlighting the switching traces, for pointing them out in the editor.
+    pub fn to_mask(&self, compiler_results: &CompilerResults) -> Mask {
+        Mask::from_atoms(
+            self.traces
+                .iter()
+                .flat_map(|&trace| compiler_results.traces[trace].iter().copied()),
+        )
+    }
+}
+
 impl ExecutionContext {
     pub fn compile_from_buffer(
         buffer: &Buffer,
+        modules: Vec<Box<dyn Module>>,
+    ) -> Result<Self, CompilerError> {
+        Self::from_compiler_results(buffer, CompilerResults::from_buffer(&buffer), modules)
+    }
+
+    /// Creates an execution context from results already compiled from `buffer`, for example by
+    /// `CompilerResults::recompile`.
+    pub fn from_compiler_results(
+        buffer: &Buffer,
+        compiler_results: CompilerResults,
         mut modules: Vec<Box<dyn Module>>,
     ) -> Result<Self, CompilerError> {
-        let compiler_results = CompilerResults::from_buffer(&buffer);
         let gate_states = vec![false; compiler_results.gates.len()];
         let trace_states = vec![false; compiler_results.traces.len()];
+        let (bond_wires, bonded_traces) =
+            Self::create_bond_wires(buffer, &compiler_results, &mut modules)?;
+        let pending_module_triggers = vec![vec![]; modules.len()];
+        let scheduler = Scheduler::new(&compiler_results);
+
+        Ok(Self {
+            compiler_results,
+            modules,
+            bond_wires,
+            bonded_traces,
+            pending_module_triggers,
+            max_ticks_per_clock: DEFAULT_MAX_TICKS_PER_CLOCK,
+            buffer_mask: Default::default(),
+            state: SimState {
+                traces_visited: 0,
+                ticks: 0,
+                clocks: 0,
+                gate_states,
+                trace_states,
+            },
+            recorder: None,
+            snapshots: None,
+            breakpoints: Default::default(),
+            toggle_coverage: None,
+            scheduler,
+            faults: vec![],
+            gate_faults: vec![],
+        })
+    }
+
+    /// Recompiles after `delta` was applied to the buffer (`buffer` is the edited buffer), without
+    /// stopping execution. Traces and gates the edit didn't touch keep their state, new ones start
+    /// low. On error, nothing is changed.
+    pub fn recompile(&mut self, buffer: &Buffer, delta: &BufferDelta) -> Result<(), CompilerError> {
+        Self::check_bonds(buffer, &mut self.modules)?;
+
+        let remap = self.compiler_results.recompile(buffer, delta);
+        let (bond_wires, bonded_traces) =
+            Self::create_bond_wires(buffer, &self.compiler_results, &mut self.modules)?;
+        self.bond_wires = bond_wires;
+        self.bonded_traces = bonded_traces;
+        self.state = self.state.remap(&remap, &self.compiler_results);
+        if let Some(toggle_coverage) = &mut self.toggle_coverage {
+            toggle_coverage.remap(&remap, &self.state.trace_states);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.remap(&remap, &self.compiler_results);
+        }
+
+        let threads = self.scheduler.threads();
+        let parallel_threshold = self.scheduler.parallel_threshold;
+        self.scheduler = Scheduler::new(&self.compiler_results);
+        self.set_threads(threads, parallel_threshold);
 
-        // Create bond wires (links between module GPIOs and Buffer Sockets
+        // Chunks may have been removed from the index, leaving stale mask chunks behind.
+        self.buffer_mask = Default::default();
+
+        // Faults are by index, and the scheduler they were applied to was rebuilt.
+        self.faults.clear();
+        self.gate_faults.clear();
+
+        let compiler_results = &self.compiler_results;
+        self.breakpoints
+            .rebind(compiler_results.traces.len(), |name| {
+                socket_trace(buffer, compiler_results, name)
+            });
+
+        // Snapshots of the old circuit can't be restored onto the new one.
+        if let Some(capacity) = self.snapshots.as_ref().map(SnapshotRing::capacity) {
+            self.record_snapshots(capacity);
+        }
+
+        Ok(())
+    }
+
+    /// Creates bond wires (links between module GPIOs and Buffer Sockets), returning them along
+    /// with the bond wire handle of each trace.
+    fn create_bond_wires(
+        buffer: &Buffer,
+        compiler_results: &CompilerResults,
+        modules: &mut [Box<dyn Module>],
+    ) -> Result<(Vec<BondWire>, Vec<BondWireHandle>), CompilerError> {
         let mut bond_wires = vec![];
         let mut bonded_traces = vec![-1; compiler_results.traces.len()];
 
         for (module_handle, module) in modules.iter_mut().enumerate() {
             for (gpio_handle, gpio) in module.get_gpios_mut().iter().enumerate() {
+                // GPIOs bond to the socket named by their bonding, or their own name by default.
+                let bonding = gpio.bonding.as_ref().unwrap_or(&gpio.name);
                 let socket = buffer
                     .sockets
                     .iter()
-                    .find(|s| s.name == gpio.name)
-                    .ok_or_else(|| CompilerError::MissingBondWire(gpio.name.clone()))?;
+                    .find(|s| &s.name == bonding)
+                    .ok_or_else(|| CompilerError::MissingBondWire(bonding.clone()))?;
 
                 let Some(&trace) = compiler_results.trace_lookup_by_atom.get(&Atom {
                     coord: socket.cell_coord,
@@ -91,24 +274,148 @@ impl ExecutionContext {
             }
         }
 
-        Ok(Self {
-            compiler_results,
-            modules,
-            bond_wires,
-            bonded_traces,
-            pending_module_triggers: vec![],
-            max_ticks_per_clock: 100_000,
-            buffer_mask: Default::default(),
-            state: SimState {
-                micro_ticks: 0,
-                ticks: 0,
-                gate_states,
-                trace_states,
-            },
-        })
+        Ok((bond_wires, bonded_traces))
+    }
+
+    /// Checks that every module GPIO has a socket to bond to, the only way `compile_from_buffer`
+    /// can fail. Lets callers keep hold of their modules when it would.
+    pub fn check_bonds(
+        buffer: &Buffer,
+        modules: &mut [Box<dyn Module>],
+    ) -> Result<(), CompilerError> {
+        for module in modules {
+            for gpio in module.get_gpios_mut().iter() {
+                let bonding = gpio.bonding.as_ref().unwrap_or(&gpio.name);
+                if !buffer.sockets.iter().any(|s| &s.name == bonding) {
+                    return Err(CompilerError::MissingBondWire(bonding.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forces the next tick to re-evaluate every trace and gate. Must be called after modifying
+    /// `state` from outside of the ExecutionContext.
+    pub fn invalidate(&mut self) {
+        self.scheduler.full_evaluation = true;
+    }
+
+    /// Sets how many threads each tick propagates on. Independent islands of the circuit are
+    /// spread across threads once a tick has at least `parallel_threshold` dirty traces. The
+    /// threads are spawned here, and kept until the count changes.
+    pub fn set_threads(&mut self, threads: usize, parallel_threshold: usize) {
+        self.scheduler.set_threads(threads);
+        self.scheduler.parallel_threshold = parallel_threshold;
     }
 
     pub fn tick_once(&mut self) {
+        // Starts a single tick (one transistor propagation-delay). Conceptually traces are reset
+        // to low, high states from modules are propagated through the stable gate network, then
+        // new gate states are computed from the trace values. Because our transistors don't have
+        // a source/drain (they are bi-directional) a trace ends up high exactly when some trace
+        // connected to it through conducting gates is driven high.
+        //
+        // Instead of sweeping every gate until nothing changes, only components that could have
+        // changed are re-evaluated: those with a trace whose module drive changed, or an EC of a
+        // gate that changed state last tick.
+        let full_evaluation = std::mem::take(&mut self.scheduler.full_evaluation);
+        let scheduler = &mut self.scheduler;
+        let gates = &self.compiler_results.gates;
+        let state = &mut self.state;
+
+        // Collect modules for input (from modules into the substrate) state. We skip this on the
+        // first ever tick to set a consistent gate state.
+        for bond_wire in &self.bond_wires {
+            scheduler.next_driven[bond_wire.trace] = false;
+        }
+        if state.ticks > 0 {
+            for bond_wire in &self.bond_wires {
+                scheduler.next_driven[bond_wire.trace] |= self.modules[bond_wire.module_handle]
+                    .get_gpios_mut()[bond_wire.gpio_handle]
+                    .si_input_high;
+            }
+        }
+        for trace in stuck_high_traces(&self.faults) {
+            scheduler.next_driven[trace] = true;
+        }
+        for bond_wire in &self.bond_wires {
+            let trace = bond_wire.trace;
+            if scheduler.driven[trace] != scheduler.next_driven[trace] {
+                scheduler.driven[trace] = scheduler.next_driven[trace];
+                scheduler.dirty_traces.push(trace);
+            }
+        }
+
+        if full_evaluation {
+            scheduler.dirty_traces.clear();
+            scheduler.dirty_traces.extend(0..state.trace_states.len());
+        } else {
+            for &gate_index in &scheduler.changed_gates {
+                scheduler.dirty_traces.push(gates[gate_index].left_ec_trace);
+                scheduler
+                    .dirty_traces
+                    .push(gates[gate_index].right_ec_trace);
+            }
+        }
+
+        state.traces_visited += scheduler.propagate(
+            &self.compiler_results,
+            &state.gate_states,
+            &mut state.trace_states,
+        );
+
+        // Update gate states, only those whose base changed (or all of them).
+        scheduler.changed_gates.clear();
+        let changed_gates = &mut scheduler.changed_gates;
+        let gate_faults = &self.gate_faults;
+        let mut update_gate = |i: usize| {
+            let base = state.trace_states[gates[i].base_trace];
+            let gate_state = match gate_faults.get(i) {
+                Some(&Some(closed)) => closed,
+                _ if gates[i].is_npn => base,
+                _ => !base,
+            };
+            if state.gate_states[i] != gate_state {
+                state.gate_states[i] = gate_state;
+                changed_gates.push(i);
+            }
+        };
+
+        if full_evaluation {
+            (0..gates.len()).for_each(&mut update_gate);
+        } else {
+            for &trace in &scheduler.changed_traces {
+                for &i in &scheduler.gates_by_base_trace[trace] {
+                    update_gate(i);
+                }
+            }
+        }
+
+        if let Some(toggle_coverage) = &mut self.toggle_coverage {
+            if full_evaluation {
+                toggle_coverage.sample(0..state.trace_states.len(), &state.trace_states);
+            } else {
+                let changed_traces = scheduler.changed_traces.iter().copied();
+                toggle_coverage.sample(changed_traces, &state.trace_states);
+            }
+        }
+
+        self.update_module_outputs();
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.sample_tick(self.state.ticks, &self.state.trace_states);
+        }
+
+        self.state.ticks += 1;
+        self.breakpoints
+            .check(self.state.ticks, &self.state.trace_states);
+    }
+
+    /// The original tick, which sweeps every gate in micro-ticks until trace states settle. Kept
+    /// as a reference for the event-driven tick.
+    #[cfg(test)]
+    fn tick_once_sweep(&mut self) {
         // Starts a single tick (one transistor propagation-delay) which is made up of resetting
         // traces to low, propagating high traces through the stable gate network via micro-ticks,
         // then computing new gate states based on the trace values.
@@ -164,7 +471,8 @@ impl ExecutionContext {
                 }
             }
 
-            self.state.micro_ticks += 1;
+            // Each micro-tick sweeps every gate, and so looks at every trace.
+            self.state.traces_visited += self.state.trace_states.len();
 
             if !change {
                 break;
@@ -177,6 +485,11 @@ impl ExecutionContext {
             self.state.gate_states[i] = if gate.is_npn { base } else { !base };
         }
 
+        self.update_module_outputs();
+        self.state.ticks += 1;
+    }
+
+    fn update_module_outputs(&mut self) {
         // Reset pending module triggers
         for triggers in &mut self.pending_module_triggers {
             triggers.clear();
@@ -186,7 +499,7 @@ impl ExecutionContext {
         // triggers.
         for bond_wire in &self.bond_wires {
             let gpio =
-                &mut self.modules[bond_wire.module_handle].get_gpios_mut()[bond_wire.module_handle];
+                &mut self.modules[bond_wire.module_handle].get_gpios_mut()[bond_wire.gpio_handle];
             let trace_state = self.state.trace_states[bond_wire.trace];
 
             // If the state changes and the GPIO is a trigger
@@ -198,40 +511,961 @@ impl ExecutionContext {
             gpio.si_output_high = trace_state;
         }
 
-        self.state.ticks += 1;
+        // Notify modules of their triggered GPIOs.
+        for (module, triggers) in self
+            .modules
+            .iter_mut()
+            .zip(self.pending_module_triggers.iter_mut())
+        {
+            if !triggers.is_empty() {
+                module.trigger(&self.state, std::mem::take(triggers));
+            }
+        }
+    }
+
+    /// Executes a single fundamental clock: each module is clocked, then the substrate is ticked
+    /// until it settles (no gate changed state, and no module changed its drive in response).
+    /// Returns the number of ticks taken.
+    pub fn clock_once(&mut self) -> Result<usize, OscillationError> {
+        for module in &mut self.modules {
+            module.clock(&self.state);
+        }
+
+        let mut ticks = 0;
+        let mut traces = vec![];
+        let mut gates = vec![];
+
+        loop {
+            self.tick_once();
+            ticks += 1;
+
+            if self.scheduler.changed_gates.is_empty() && !self.drive_changed() {
+                break;
+            }
+
+            if ticks + OSCILLATION_WINDOW > self.max_ticks_per_clock {
+                traces.extend_from_slice(&self.scheduler.changed_traces);
+                gates.extend_from_slice(&self.scheduler.changed_gates);
+            }
+
+            if ticks >= self.max_ticks_per_clock {
+                traces.sort_unstable();
+                traces.dedup();
+                gates.sort_unstable();
+                gates.dedup();
+                return Err(OscillationError {
+                    ticks,
+                    traces,
+                    gates,
+                });
+            }
+        }
+
+        self.state.clocks += 1;
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.sample_clock(self.state.clocks - 1, &self.state.trace_states);
+        }
+
+        if self.snapshots.is_some() {
+            let snapshot = self.snapshot();
+            self.snapshots.as_mut().unwrap().push(snapshot);
+        }
+
+        Ok(ticks)
+    }
+
+    /// True if a module's drive differs from what the last tick propagated, so another tick is
+    /// needed.
+    fn drive_changed(&mut self) -> bool {
+        let next_driven = &mut self.scheduler.next_driven;
+        for bond_wire in &self.bond_wires {
+            next_driven[bond_wire.trace] = false;
+        }
+        for bond_wire in &self.bond_wires {
+            next_driven[bond_wire.trace] |= self.modules[bond_wire.module_handle].get_gpios_mut()
+                [bond_wire.gpio_handle]
+                .si_input_high;
+        }
+        for trace in stuck_high_traces(&self.faults) {
+            next_driven[trace] = true;
+        }
+
+        self.bond_wires
+            .iter()
+            .any(|b| self.scheduler.next_driven[b.trace] != self.scheduler.driven[b.trace])
+    }
+
+    /// Injects a fault, replacing any fault already on the same trace or gate. Takes effect from
+    /// the next tick, until cleared. Faults are also cleared by a recompile. Fails if the fault's
+    /// trace or gate isn't in the compiled circuit, say because it was made before a recompile.
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), FaultError> {
+        fault.validate(&self.compiler_results)?;
+
+        let same_site = |other: &Fault| match (*other, fault) {
+            (Fault::TraceStuck { trace: a, .. }, Fault::TraceStuck { trace: b, .. }) => a == b,
+            (Fault::GateStuck { gate: a, .. }, Fault::GateStuck { gate: b, .. }) => a == b,
+            _ => false,
+        };
+
+        let previous = self.faults.clone();
+        self.faults.retain(|other| !same_site(other));
+        self.faults.push(fault);
+        self.apply_faults(&previous);
+
+        Ok(())
+    }
+
+    pub fn clear_faults(&mut self) {
+        let previous = std::mem::take(&mut self.faults);
+        self.apply_faults(&previous);
+    }
+
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    /// Brings the drive and gate states in line with `faults`, after they changed from
+    /// `previous`.
+    fn apply_faults(&mut self, previous: &[Fault]) {
+        for trace in stuck_high_traces(previous) {
+            self.scheduler.driven[trace] = false;
+            self.scheduler.next_driven[trace] = false;
+        }
+
+        self.gate_faults.clear();
+        self.scheduler.grounded.fill(false);
+        for fault in &self.faults {
+            match *fault {
+                Fault::TraceStuck { trace, high: true } => {
+                    self.scheduler.driven[trace] = true;
+                    self.scheduler.next_driven[trace] = true;
+                }
+                Fault::TraceStuck { trace, high: false } => {
+                    self.scheduler.grounded[trace] = true;
+                }
+                Fault::GateStuck { gate, closed } => {
+                    self.gate_faults
+                        .resize(self.compiler_results.gates.len(), None);
+                    self.gate_faults[gate] = Some(closed);
+                    self.state.gate_states[gate] = closed;
+                }
+            }
+        }
+
+        // Gates that are no longer stuck, and traces that are no longer driven, are found by
+        // re-evaluating everything.
+        self.invalidate();
+    }
+
+    /// Adds a breakpoint, resolving socket names against `buffer` (the buffer this context was
+    /// compiled from). Hits are collected in `breakpoints` as ticks execute.
+    pub fn add_breakpoint(
+        &mut self,
+        buffer: &Buffer,
+        condition: Condition,
+    ) -> Result<BreakpointHandle, BreakpointError> {
+        let compiler_results = &self.compiler_results;
+        self.breakpoints
+            .add(condition, compiler_results.traces.len(), |name| {
+                socket_trace(buffer, compiler_results, name)
+            })
+    }
+
+    /// Replaces every breakpoint, resolving socket names against `buffer`. All of them are
+    /// resolved before any are swapped in, so on error the current breakpoints are kept.
+    pub fn set_breakpoints(
+        &mut self,
+        buffer: &Buffer,
+        conditions: &[Condition],
+    ) -> Result<(), BreakpointError> {
+        let compiler_results = &self.compiler_results;
+        let mut breakpoints = Breakpoints::default();
+        for condition in conditions {
+            breakpoints.add(condition.clone(), compiler_results.traces.len(), |name| {
+                socket_trace(buffer, compiler_results, name)
+            })?;
+        }
+
+        self.breakpoints = breakpoints;
+        Ok(())
+    }
+
+    /// Captures the full simulation state, including modules.
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot {
+            sim_state: self.state.clone(),
+            modules: self
+                .modules
+                .iter_mut()
+                .map(|module| ModuleSnapshot {
+                    gpios: module
+                        .get_gpios_mut()
+                        .iter()
+                        .map(|gpio| (gpio.si_output_high, gpio.si_input_high))
+                        .collect(),
+                    state: module.save_state(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores a snapshot taken from this context (or one compiled from the same buffer, with
+    /// the same modules).
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let matches = snapshot.sim_state.trace_states.len() == self.state.trace_states.len()
+            && snapshot.sim_state.gate_states.len() == self.state.gate_states.len()
+            && snapshot.modules.len() == self.modules.len()
+            && snapshot
+                .modules
+                .iter()
+                .zip(self.modules.iter_mut())
+                .all(|(s, m)| s.gpios.len() == m.get_gpios_mut().len());
+
+        if !matches {
+            return Err(SnapshotError::Mismatch);
+        }
+
+        for (module_snapshot, module) in snapshot.modules.iter().zip(self.modules.iter_mut()) {
+            module.load_state(&module_snapshot.state)?;
+            for (&(output, input), gpio) in module_snapshot
+                .gpios
+                .iter()
+                .zip(module.get_gpios_mut().iter_mut())
+            {
+                gpio.si_output_high = output;
+                gpio.si_input_high = input;
+            }
+        }
+
+        self.state = snapshot.sim_state.clone();
+        self.breakpoints.reset_edges();
+        if let Some(toggle_coverage) = &mut self.toggle_coverage {
+            toggle_coverage.resync(&self.state.trace_states);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.resync(self.state.ticks, self.state.clocks);
+        }
+        self.invalidate();
+
+        Ok(())
+    }
+
+    /// Starts keeping a snapshot of each of the last `capacity` clocks (replacing any already
+    /// kept), starting with the current state.
+    pub fn record_snapshots(&mut self, capacity: usize) {
+        let mut snapshots = SnapshotRing::new(capacity);
+        snapshots.push(self.snapshot());
+        self.snapshots = Some(snapshots);
+    }
+
+    /// Starts counting trace toggles (replacing any prior counts), from the current state.
+    pub fn record_toggle_coverage(&mut self) {
+        self.toggle_coverage = Some(ToggleCoverage::new(&self.state.trace_states));
+    }
+
+    /// Steps the simulation back `clocks` recorded clocks.
+    pub fn rewind(&mut self, clocks: usize) -> Result<(), SnapshotError> {
+        let len = self.snapshots.as_ref().map_or(0, SnapshotRing::len);
+        let index = len
+            .checked_sub(clocks + 1)
+            .ok_or(SnapshotError::TooFarBack(clocks))?;
+        self.rewind_to(index)
+    }
+
+    /// Restores recorded snapshot `index` (0 is the oldest kept), dropping every snapshot after
+    /// it. Running on from there records a new timeline.
+    pub fn rewind_to(&mut self, index: usize) -> Result<(), SnapshotError> {
+        let snapshot = self
+            .snapshots
+            .as_ref()
+            .and_then(|snapshots| snapshots.get(index))
+            .cloned()
+            .ok_or(SnapshotError::NotRecorded(index))?;
+
+        self.restore(&snapshot)?;
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.truncate_after(index);
+        }
+
+        Ok(())
+    }
+
+    /// Shows the trace states of recorded snapshot `index` in `buffer_mask`, without changing the
+    /// simulation.
+    pub fn scrub_buffer_mask(&mut self, index: usize) -> Result<(), SnapshotError> {
+        let snapshot = self
+            .snapshots
+            .as_ref()
+            .and_then(|snapshots| snapshots.get(index))
+            .ok_or(SnapshotError::NotRecorded(index))?;
+
+        write_buffer_mask(
+            &mut self.buffer_mask,
+            &self.compiler_results,
+            &snapshot.sim_state.trace_states,
+        );
+
+        Ok(())
     }
 
     pub fn update_buffer_mask(&mut self) {
-        for (chunk_coord, cell_part_to_traces) in self
-            .compiler_results
-            .trace_to_cell_part_index_by_chunk
+        write_buffer_mask(
+            &mut self.buffer_mask,
+            &self.compiler_results,
+            &self.state.trace_states,
+        );
+    }
+}
+
+fn stuck_high_traces(faults: &[Fault]) -> impl Iterator<Item = usize> + '_ {
+    faults.iter().filter_map(|fault| match *fault {
+        Fault::TraceStuck { trace, high: true } => Some(trace),
+        _ => None,
+    })
+}
+
+/// The trace the named socket is on, if it compiled to one.
+pub(crate) fn socket_trace(
+    buffer: &Buffer,
+    compiler_results: &CompilerResults,
+    name: &str,
+) -> Option<usize> {
+    let socket = buffer.sockets.iter().find(|s| s.name == name)?;
+    compiler_results
+        .trace_lookup_by_atom
+        .get(&Atom {
+            coord: socket.cell_coord,
+            part: CellPart::Metal,
+        })
+        .copied()
+}
+
+fn write_buffer_mask(mask: &mut Mask, compiler_results: &CompilerResults, trace_states: &[bool]) {
+    mask.write_traces(compiler_results, |trace| trace_states[trace] as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        module::{ModuleGpio, ModuleProbe, ProbeEdge},
+        simulator::fixtures::{
+            add_random_sockets, draw_wire, paint_random_strokes, random_buffer, wire, wires, Rng,
+        },
+        substrate::{compiler::Gate, waveform::SampleRate},
+        upc::{Metal, NormalizedCell},
+        utils::convert::import_legacy_blueprint,
+    };
+    use std::collections::HashMap;
+
+    /// A module with one GPIO per socket, whose inputs are set directly by the test.
+    struct Pins {
+        gpios: Vec<ModuleGpio>,
+    }
+
+    impl Module for Pins {
+        fn get_name(&self) -> &str {
+            "pins"
+        }
+
+        fn reset(&mut self) {}
+
+        fn get_gpios_mut(&mut self) -> &mut Vec<ModuleGpio> {
+            &mut self.gpios
+        }
+    }
+
+    fn pins(buffer: &Buffer) -> Box<dyn Module> {
+        Box::new(Pins {
+            gpios: buffer
+                .sockets
+                .iter()
+                .enumerate()
+                .map(|(handle, socket)| ModuleGpio {
+                    handle,
+                    name: socket.name.clone(),
+                    bonding: None,
+                    trigger: false,
+                    si_output_high: false,
+                    si_input_high: false,
+                })
+                .collect(),
+        })
+    }
+
+    fn compile(buffer: &Buffer) -> ExecutionContext {
+        ExecutionContext::compile_from_buffer(buffer, vec![pins(buffer)]).unwrap()
+    }
+
+    /// Three PNP inverters powered from a driven VCC socket (trace 1), each output (traces 2
+    /// through 4) driving the next one's base. Closing the loop makes a ring oscillator, otherwise
+    /// the first inverter's base is the always low null trace.
+    fn inverter_chain(closed: bool) -> ExecutionContext {
+        let mut buffer = Buffer::new();
+        buffer.draw_metal_link(None, (0, 0).into());
+        buffer.set_socket((0, 0).into(), Some("VCC".to_string()));
+
+        let vcc = Atom {
+            coord: (0, 0).into(),
+            part: CellPart::Metal,
+        };
+        let inverter = |i: usize, base_trace| Gate {
+            coord: (i as i32 + 1, 0).into(),
+            is_npn: false,
+            base_trace,
+            left_ec_trace: 1,
+            right_ec_trace: i + 2,
+        };
+        let compiler_results = CompilerResults {
+            traces: vec![vec![], vec![vcc], vec![], vec![], vec![]],
+            trace_lookup_by_atom: HashMap::from([(vcc, 1)]),
+            gates: vec![
+                inverter(0, if closed { 4 } else { 0 }),
+                inverter(1, 2),
+                inverter(2, 3),
+            ],
+            trace_to_cell_part_index_by_chunk: Default::default(),
+        };
+
+        let mut context =
+            ExecutionContext::from_compiler_results(&buffer, compiler_results, vec![pins(&buffer)])
+                .unwrap();
+        context.modules[0].get_gpios_mut()[0].si_input_high = true;
+        context
+    }
+
+    /// Runs the event-driven tick and the reference sweep side by side with the same random
+    /// inputs, asserting identical state after every tick.
+    fn assert_matches_sweep(buffer: &Buffer, rng: &mut Rng, ticks: usize) {
+        let mut event = compile(buffer);
+        let mut sweep = compile(buffer);
+
+        for tick in 0..ticks {
+            // Change a few inputs every few ticks, leaving time for the network to settle.
+            if tick % 3 == 0 {
+                let gpio_count = event.modules[0].get_gpios_mut().len();
+                for _ in 0..1 + rng.below(2) {
+                    if gpio_count == 0 {
+                        break;
+                    }
+                    let handle = rng.below(gpio_count as u64) as usize;
+                    let high = rng.below(2) == 0;
+                    event.modules[0].get_gpios_mut()[handle].si_input_high = high;
+                    sweep.modules[0].get_gpios_mut()[handle].si_input_high = high;
+                }
+            }
+
+            event.tick_once();
+            sweep.tick_once_sweep();
+
+            // Every field of the SimState but `traces_visited`, which measures work and so is
+            // meant to differ.
+            assert_eq!(event.state.ticks, sweep.state.ticks);
+            assert_eq!(event.state.clocks, sweep.state.clocks);
+            assert_eq!(
+                event.state.trace_states, sweep.state.trace_states,
+                "trace states diverged on tick {}",
+                tick
+            );
+            assert_eq!(
+                event.state.gate_states, sweep.state.gate_states,
+                "gate states diverged on tick {}",
+                tick
+            );
+        }
+    }
+
+    #[test]
+    fn event_driven_matches_sweep_on_random_circuits() {
+        for seed in 1..=60_u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let buffer = random_buffer(&mut rng, 16, 150, 8);
+            assert_matches_sweep(&buffer, &mut rng, 60);
+        }
+    }
+
+    #[test]
+    fn set_breakpoints_keeps_the_old_ones_on_error() {
+        let buffer = wire();
+
+        let conditions =
+            |list: &[&str]| -> Vec<Condition> { list.iter().map(|c| c.parse().unwrap()).collect() };
+        let mut context = compile(&buffer);
+        context
+            .set_breakpoints(&buffer, &conditions(&["rise OUT"]))
+            .unwrap();
+        assert!(matches!(
+            context.set_breakpoints(&buffer, &conditions(&["fall IN", "rise NOPE"])),
+            Err(BreakpointError::UnknownSocket(_))
+        ));
+
+        context.clock_once().unwrap();
+        context.modules[0].get_gpios_mut()[0].si_input_high = true;
+        context.clock_once().unwrap();
+
+        let hits = context.breakpoints.take_hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].condition, conditions(&["rise OUT"])[0]);
+    }
+
+    #[test]
+    fn threaded_matches_single_threaded() {
+        for seed in 1..=20_u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x2545_F491_4F6C_DD1D));
+            let buffer = random_buffer(&mut rng, 16, 150, 8);
+            let mut single = compile(&buffer);
+            let mut threaded = compile(&buffer);
+            threaded.set_threads(4, 0);
+            while !threaded.scheduler.threads_ready() {
+                std::thread::yield_now();
+            }
+
+            for _ in 0..40 {
+                let handle = rng.below(buffer.sockets.len().max(1) as u64) as usize;
+                let high = rng.below(2) == 0;
+                for ctx in [&mut single, &mut threaded] {
+                    if let Some(gpio) = ctx.modules[0].get_gpios_mut().get_mut(handle) {
+                        gpio.si_input_high = high;
+                    }
+                }
+
+                single.tick_once();
+                threaded.tick_once();
+                assert_eq!(single.state.trace_states, threaded.state.trace_states);
+                assert_eq!(single.state.gate_states, threaded.state.gate_states);
+                assert_eq!(single.state.traces_visited, threaded.state.traces_visited);
+            }
+        }
+    }
+
+    /// Asserts that `incremental` compiled every atom of `full` into equivalent traces and gates.
+    fn assert_equivalent(incremental: &CompilerResults, full: &CompilerResults) {
+        let mut trace_map = HashMap::new();
+        let mut inverse_map = HashMap::new();
+        for (atom, &trace) in &full.trace_lookup_by_atom {
+            let mapped = incremental.trace_lookup_by_atom[atom];
+            assert_eq!(*trace_map.entry(trace).or_insert(mapped), mapped);
+            assert_eq!(*inverse_map.entry(mapped).or_insert(trace), trace);
+        }
+
+        // Nothing more is compiled than in a full compile, bar empty trace slots.
+        let nonempty = |c: &CompilerResults| c.traces.iter().filter(|t| !t.is_empty()).count();
+        assert_eq!(
+            incremental.trace_lookup_by_atom.len(),
+            full.trace_lookup_by_atom.len()
+        );
+        assert_eq!(nonempty(incremental), nonempty(full));
+        assert_eq!(incremental.gates.len(), full.gates.len());
+
+        let gates: HashMap<CellCoord, &Gate> =
+            incremental.gates.iter().map(|g| (g.coord, g)).collect();
+        for gate in &full.gates {
+            let mapped = gates[&gate.coord];
+            assert_eq!(mapped.is_npn, gate.is_npn);
+            assert_eq!(mapped.base_trace, trace_map[&gate.base_trace]);
+            assert_eq!(mapped.left_ec_trace, trace_map[&gate.left_ec_trace]);
+            assert_eq!(mapped.right_ec_trace, trace_map[&gate.right_ec_trace]);
+        }
+
+        assert_eq!(
+            incremental.trace_to_cell_part_index_by_chunk.len(),
+            full.trace_to_cell_part_index_by_chunk.len()
+        );
+        for (chunk_coord, index) in &full.trace_to_cell_part_index_by_chunk {
+            let mapped_index = &incremental.trace_to_cell_part_index_by_chunk[chunk_coord];
+            assert_eq!(mapped_index.len(), index.len());
+            for (a, b) in index.iter().zip(mapped_index) {
+                assert_eq!(a.cell_index_in_chunk, b.cell_index_in_chunk);
+                for (trace, mapped) in [
+                    (a.metal_trace, b.metal_trace),
+                    (a.si_trace, b.si_trace),
+                    (a.left_ec_trace, b.left_ec_trace),
+                    (a.right_ec_trace, b.right_ec_trace),
+                ] {
+                    if trace == 0 {
+                        assert_eq!(mapped, 0);
+                    } else {
+                        assert_eq!(trace_map[&trace], mapped);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn recompile_matches_full_compile() {
+        for seed in 1..=40_u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut buffer = random_buffer(&mut rng, 16, 120, 8);
+            buffer.fix_all_cells();
+            let mut compiler_results = CompilerResults::from_buffer(&buffer);
+
+            // Paint over and erase a few cells, and move a socket.
+            let mut edited = buffer.clone();
+            paint_random_strokes(&mut edited, &mut rng, 16, 4);
+            for _ in 0..4 {
+                let coord = (rng.below(16) as i32, rng.below(16) as i32).into();
+                edited.clear_cell_si(coord);
+                edited.clear_cell_metal(coord);
+            }
+            if let Some(socket) = edited.sockets.first().cloned() {
+                edited.set_socket(socket.cell_coord, None);
+            }
+            add_random_sockets(&mut edited, &mut rng, 16, 1);
+            edited.fix_all_cells();
+
+            let delta = BufferDelta::new(&buffer, &edited);
+            compiler_results.recompile(&edited, &delta);
+            assert_equivalent(&compiler_results, &CompilerResults::from_buffer(&edited));
+        }
+    }
+
+    #[test]
+    fn recompile_keeps_untouched_state() {
+        let mut rng = Rng(0xC0FFEE);
+        let buffer = random_buffer(&mut rng, 16, 150, 8);
+        let mut event = compile(&buffer);
+        let mut sweep = compile(&buffer);
+
+        for handle in 0..event.modules[0].get_gpios_mut().len() {
+            event.modules[0].get_gpios_mut()[handle].si_input_high = true;
+            sweep.modules[0].get_gpios_mut()[handle].si_input_high = true;
+        }
+
+        for _ in 0..10 {
+            event.tick_once();
+            sweep.tick_once_sweep();
+        }
+
+        // A wire off to the side touches nothing that was compiled.
+        let mut edited = buffer.clone();
+        edited.draw_metal_link(None, (40, 40).into());
+        edited.draw_metal_link(Some((40, 40).into()), (41, 40).into());
+        let delta = BufferDelta::new(&buffer, &edited);
+
+        let before = event.state.clone();
+        event.recompile(&edited, &delta).unwrap();
+        assert_eq!(event.state.ticks, before.ticks);
+        assert_eq!(event.state.gate_states, before.gate_states);
+        assert_eq!(
+            event.state.trace_states[..before.trace_states.len()],
+            before.trace_states[..]
+        );
+
+        // Now cut into the circuit, and keep ticking alongside the sweep.
+        let buffer = edited;
+        let mut edited = buffer.clone();
+        for x in 0..16 {
+            edited.clear_cell_metal((x, 8).into());
+        }
+        let delta = BufferDelta::new(&buffer, &edited);
+        event.recompile(&edited, &delta).unwrap();
+        sweep.recompile(&edited, &delta).unwrap();
+        assert_eq!(event.state.trace_states, sweep.state.trace_states);
+
+        for _ in 0..10 {
+            event.tick_once();
+            sweep.tick_once_sweep();
+            assert_eq!(event.state.trace_states, sweep.state.trace_states);
+            assert_eq!(event.state.gate_states, sweep.state.gate_states);
+        }
+    }
+
+    #[test]
+    fn recompile_keeps_recording() {
+        let buffer = wires();
+        let mut edited = buffer.clone();
+        for x in 0..2 {
+            edited.clear_cell_metal((x, 0).into());
+            edited.set_socket((x, 0).into(), None);
+        }
+        edited.draw_metal_link(Some((1, 1).into()), (2, 1).into());
+        let delta = BufferDelta::new(&buffer, &edited);
+
+        let mut context =
+            ExecutionContext::compile_from_buffer(&buffer, vec![pins(&edited)]).unwrap();
+        let trace = |context: &ExecutionContext| {
+            socket_trace(&edited, &context.compiler_results, "OUT1").unwrap()
+        };
+        let out0 = socket_trace(&buffer, &context.compiler_results, "OUT0").unwrap();
+        let out1 = trace(&context);
+
+        let mut recorder = WaveformRecorder::new(SampleRate::Tick);
+        recorder
+            .add_socket(&buffer, &context.compiler_results, "OUT1")
+            .unwrap();
+        recorder.add_trace("OUT0", out0);
+        context.recorder = Some(recorder);
+
+        context.modules[0].get_gpios_mut()[0].si_input_high = true;
+        context.clock_once().unwrap();
+
+        // Removing the first wire frees its trace, which the re-explored second wire takes over.
+        context.recompile(&edited, &delta).unwrap();
+        assert_ne!(trace(&context), out1);
+
+        for high in [false, true, false] {
+            context.modules[0].get_gpios_mut()[0].si_input_high = high;
+            context.clock_once().unwrap();
+        }
+
+        let recorder = context.recorder.as_ref().unwrap();
+        assert!(recorder.changes("OUT0").is_none());
+        let values: Vec<bool> = recorder
+            .changes("OUT1")
+            .unwrap()
             .iter()
-        {
-            let chunk = self.buffer_mask.get_or_create_chunk_mut(*chunk_coord);
-            for index in cell_part_to_traces {
-                let i = index.cell_index_in_chunk * MASK_BYTE_LEN;
-                let cell_slice = &mut chunk.cells[i..i + MASK_BYTE_LEN];
-                cell_slice[0] = if self.state.trace_states[index.metal_trace] {
-                    1
-                } else {
-                    0
-                };
-                cell_slice[1] = if self.state.trace_states[index.si_trace] {
-                    1
-                } else {
-                    0
-                };
-                cell_slice[2] = if self.state.trace_states[index.left_ec_trace] {
-                    1
-                } else {
-                    0
-                };
-                cell_slice[3] = if self.state.trace_states[index.right_ec_trace] {
-                    1
-                } else {
-                    0
-                };
+            .map(|c| c.1)
+            .collect();
+        assert_eq!(values, [false, true, false, true, false]);
+    }
+
+    #[test]
+    fn clock_ticks_until_settled() {
+        let mut context = inverter_chain(false);
+        assert_eq!(context.clock_once().unwrap(), 4);
+        assert_eq!(context.state.trace_states[2..], [true, false, true]);
+
+        // Nothing changed, so the next clock settles immediately.
+        assert_eq!(context.clock_once().unwrap(), 1);
+    }
+
+    #[test]
+    fn faults_force_traces_and_gates() {
+        let mut context = inverter_chain(false);
+        context.clock_once().unwrap();
+        assert_eq!(context.state.trace_states[2..], [true, false, true]);
+
+        // Holding the first inverter's output low flips the rest of the chain.
+        context
+            .inject_fault(Fault::TraceStuck {
+                trace: 2,
+                high: false,
+            })
+            .unwrap();
+        context.clock_once().unwrap();
+        assert_eq!(context.state.trace_states[2..], [false, true, false]);
+
+        // Grounding VCC passes no highs on, so every output stays low.
+        context.clear_faults();
+        context
+            .inject_fault(Fault::TraceStuck {
+                trace: 1,
+                high: false,
+            })
+            .unwrap();
+        context.clock_once().unwrap();
+        assert_eq!(context.state.trace_states[1..], [false; 4]);
+
+        // An open last inverter leaves its output low.
+        context.clear_faults();
+        context
+            .inject_fault(Fault::GateStuck {
+                gate: 2,
+                closed: false,
+            })
+            .unwrap();
+        context.clock_once().unwrap();
+        assert_eq!(context.state.trace_states[2..], [true, false, false]);
+
+        // Replaced by the opposite fault on the same gate.
+        context
+            .inject_fault(Fault::GateStuck {
+                gate: 2,
+                closed: true,
+            })
+            .unwrap();
+        assert_eq!(context.faults().len(), 1);
+
+        // Faults on traces or gates that don't exist are refused.
+        assert!(matches!(
+            context.inject_fault(Fault::TraceStuck {
+                trace: 5,
+                high: true
+            }),
+            Err(FaultError::UnknownTrace(5))
+        ));
+        assert!(matches!(
+            context.inject_fault(Fault::GateStuck {
+                gate: 3,
+                closed: true
+            }),
+            Err(FaultError::UnknownGate(3))
+        ));
+        assert_eq!(context.faults().len(), 1);
+
+        context.clear_faults();
+        context.clock_once().unwrap();
+        assert_eq!(context.state.trace_states[2..], [true, false, true]);
+    }
+
+    #[test]
+    fn clock_reports_oscillation() {
+        let mut context = inverter_chain(true);
+        context.max_ticks_per_clock = 100;
+
+        let err = context.clock_once().unwrap_err();
+        assert_eq!(err.ticks, 100);
+        assert_eq!(err.traces, vec![2, 3, 4]);
+        assert_eq!(err.gates, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rewinds_and_replays_recorded_clocks() {
+        let mut buffer = Buffer::new();
+        draw_wire(&mut buffer, 0, 10, "IN", Some("OUT"));
+
+        let mut probe = ModuleProbe::new("P", 1);
+        probe.get_gpios_mut()[0].bonding = Some("OUT".to_string());
+        let mut context =
+            ExecutionContext::compile_from_buffer(&buffer, vec![pins(&buffer), Box::new(probe)])
+                .unwrap();
+        context.record_snapshots(16);
+
+        fn probe_edges(context: &ExecutionContext) -> Vec<ProbeEdge> {
+            let module = context.modules[1].as_any();
+            module.downcast_ref::<ModuleProbe>().unwrap().edges.clone()
+        }
+
+        // Toggles IN each clock, recording the trace states and probe edges after it.
+        fn run(
+            context: &mut ExecutionContext,
+            clocks: std::ops::Range<usize>,
+            history: &mut Vec<(Vec<bool>, Vec<ProbeEdge>)>,
+        ) {
+            for clock in clocks {
+                context.modules[0].get_gpios_mut()[0].si_input_high = clock % 2 == 0;
+                context.clock_once().unwrap();
+                history.push((context.state.trace_states.clone(), probe_edges(context)));
+            }
+        }
+
+        let mut history = vec![];
+        run(&mut context, 0..6, &mut history);
+        let expected = history.clone();
+
+        context.rewind(3).unwrap();
+        assert_eq!(context.state.clocks, 3);
+        assert_eq!(context.state.trace_states, expected[2].0);
+        assert_eq!(probe_edges(&context), expected[2].1);
+
+        // Replaying the same inputs ends up in the same place.
+        history.clear();
+        run(&mut context, 3..6, &mut history);
+        assert_eq!(history, expected[3..]);
+
+        let bytes = context.snapshot().to_bytes().unwrap();
+        context.rewind(6).unwrap();
+        assert_eq!(context.state.clocks, 0);
+        context
+            .restore(&Snapshot::from_bytes(&bytes).unwrap())
+            .unwrap();
+        assert_eq!(context.state.clocks, 6);
+        assert_eq!(probe_edges(&context), expected[5].1);
+
+        assert!(matches!(
+            context.rewind(100),
+            Err(SnapshotError::TooFarBack(100))
+        ));
+    }
+
+    #[test]
+    fn rewinding_rewinds_the_recording() {
+        let buffer = wire();
+
+        // Records OUT per clock while driving IN with `inputs`, then returns the VCD.
+        fn run(context: &mut ExecutionContext, inputs: &[bool]) -> String {
+            for &high in inputs {
+                context.modules[0].get_gpios_mut()[0].si_input_high = high;
+                context.clock_once().unwrap();
+            }
+            context.recorder.as_ref().unwrap().to_vcd_string()
+        }
+
+        let record = |context: &mut ExecutionContext| {
+            let mut recorder = WaveformRecorder::new(SampleRate::Clock);
+            recorder
+                .add_socket(&buffer, &context.compiler_results, "OUT")
+                .unwrap();
+            context.recorder = Some(recorder);
+        };
+
+        let mut context = compile(&buffer);
+        record(&mut context);
+        context.record_snapshots(16);
+        run(&mut context, &[true, false, true, false]);
+        context.rewind(2).unwrap();
+        let rewound = run(&mut context, &[true, true]);
+
+        let mut expected = compile(&buffer);
+        record(&mut expected);
+        assert_eq!(rewound, run(&mut expected, &[true, false, true, true]));
+        assert!(rewound.ends_with("#0\n1!\n#1\n0!\n#2\n1!\n"));
+    }
+
+    #[test]
+    fn event_driven_matches_sweep_after_invalidate() {
+        let mut rng = Rng(0xDEAD_BEEF);
+        let buffer = random_buffer(&mut rng, 16, 150, 8);
+        let mut event = compile(&buffer);
+        let mut sweep = compile(&buffer);
+
+        for handle in 0..event.modules[0].get_gpios_mut().len() {
+            event.modules[0].get_gpios_mut()[handle].si_input_high = true;
+            sweep.modules[0].get_gpios_mut()[handle].si_input_high = true;
+        }
+
+        for _ in 0..10 {
+            event.tick_once();
+            sweep.tick_once_sweep();
+        }
+
+        // Scramble the state externally, both must recover identically.
+        for state in event.state.gate_states.iter_mut() {
+            *state = !*state;
+        }
+        sweep.state.gate_states = event.state.gate_states.clone();
+        event.invalidate();
+
+        for _ in 0..10 {
+            event.tick_once();
+            sweep.tick_once_sweep();
+            assert_eq!(event.state.trace_states, sweep.state.trace_states);
+            assert_eq!(event.state.gate_states, sweep.state.gate_states);
+        }
+    }
+
+    #[test]
+    fn event_driven_matches_sweep_on_cpu() {
+        let mut rng = Rng(42);
+        let mut buffer =
+            import_legacy_blueprint(include_str!("../../misc/cpu.lpbp").to_string()).unwrap();
+
+        // The legacy blueprint has no sockets, so drop some on random metal.
+        let (min, max) = buffer.chunks().iter().fold(
+            ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN)),
+            |(min, max), chunk| {
+                let first = chunk.chunk_coord.first_cell_coord().0;
+                let last = chunk.chunk_coord.last_cell_coord().0;
+                (
+                    (min.0.min(first.x), min.1.min(first.y)),
+                    (max.0.max(last.x), max.1.max(last.y)),
+                )
+            },
+        );
+        for i in 0..24 {
+            loop {
+                let x = min.0 + rng.below((max.0 - min.0) as u64) as i32;
+                let y = min.1 + rng.below((max.1 - min.1) as u64) as i32;
+                let cell: NormalizedCell = buffer.get_cell((x, y).into()).into();
+                if matches!(cell.metal, Metal::Trace { .. }) {
+                    buffer.set_socket((x, y).into(), Some(format!("S{}", i)));
+                    break;
+                }
             }
         }
+
+        assert_matches_sweep(&buffer, &mut rng, 100);
     }
 }
@@ -0,0 +1,156 @@
+use crate::{
+    coords::CellCoord,
+    impl_js_error,
+    substrate::{
+        buffer::Buffer,
+        compiler::{Atom, CellPart, CompilerResults},
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FaultError {
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+
+    #[error("no trace at {0:?}")]
+    NoTrace(Atom),
+
+    #[error("no gate at {0:?}")]
+    NoGate(CellCoord),
+
+    #[error("no trace #{0}")]
+    UnknownTrace(usize),
+
+    #[error("no gate #{0}")]
+    UnknownGate(usize),
+}
+
+impl_js_error!(FaultError);
+
+/// A permanent defect injected into a simulation (see `ExecutionContext::inject_fault`). Faults
+/// are by trace and gate index, so they only apply to the CompilerResults they were made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// The trace is held high, as if shorted to power, driving everything it connects to. Or held
+    /// low, as if shorted to ground: it reads low to the gates and modules attached to it, and
+    /// passes no highs between the traces it connects through gates.
+    TraceStuck { trace: usize, high: bool },
+
+    /// The gate always conducts (closed) or never does (open), whatever the state of its base.
+    GateStuck { gate: usize, closed: bool },
+}
+
+/// Where a trace fault is injected: the trace an atom is part of, or a socket's trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultSite {
+    Atom(Atom),
+    Socket(String),
+}
+
+impl Fault {
+    pub fn trace_stuck(
+        buffer: &Buffer,
+        compiler_results: &CompilerResults,
+        site: &FaultSite,
+        high: bool,
+    ) -> Result<Fault, FaultError> {
+        let atom = match site {
+            FaultSite::Atom(atom) => *atom,
+            FaultSite::Socket(name) => Atom {
+                coord: buffer
+                    .sockets
+                    .iter()
+                    .find(|s| &s.name == name)
+                    .ok_or_else(|| FaultError::UnknownSocket(name.clone()))?
+                    .cell_coord,
+                part: CellPart::Metal,
+            },
+        };
+
+        let trace = *compiler_results
+            .trace_lookup_by_atom
+            .get(&atom)
+            .ok_or(FaultError::NoTrace(atom))?;
+
+        Ok(Fault::TraceStuck { trace, high })
+    }
+
+    /// A fault on the gate (MOSFET) at `coord`.
+    pub fn gate_stuck(
+        compiler_results: &CompilerResults,
+        coord: CellCoord,
+        closed: bool,
+    ) -> Result<Fault, FaultError> {
+        let gate = compiler_results
+            .gates
+            .iter()
+            .position(|g| g.coord == coord)
+            .ok_or(FaultError::NoGate(coord))?;
+
+        Ok(Fault::GateStuck { gate, closed })
+    }
+
+    /// Checks that the fault's trace or gate exists in `compiler_results`.
+    pub fn validate(&self, compiler_results: &CompilerResults) -> Result<(), FaultError> {
+        match *self {
+            Fault::TraceStuck { trace, .. } if trace >= compiler_results.traces.len() => {
+                Err(FaultError::UnknownTrace(trace))
+            }
+            Fault::GateStuck { gate, .. } if gate >= compiler_results.gates.len() => {
+                Err(FaultError::UnknownGate(gate))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Every single stuck-at fault: each trace stuck high and low, and each gate stuck open and
+    /// closed. Excludes the null trace.
+    pub fn all(compiler_results: &CompilerResults) -> Vec<Fault> {
+        let traces = (1..compiler_results.traces.len()).flat_map(|trace| {
+            [false, true]
+                .iter()
+                .map(move |&high| Fault::TraceStuck { trace, high })
+        });
+        let gates = (0..compiler_results.gates.len()).flat_map(|gate| {
+            [false, true]
+                .iter()
+                .map(move |&closed| Fault::GateStuck { gate, closed })
+        });
+
+        traces.chain(gates).collect()
+    }
+
+    /// Describes the fault with the location of its trace or gate, like
+    /// `trace #12 at (3, -4) stuck high`.
+    pub fn describe(&self, compiler_results: &CompilerResults) -> String {
+        match *self {
+            Fault::TraceStuck { trace, high } => {
+                let at = compiler_results
+                    .traces
+                    .get(trace)
+                    .and_then(|atoms| atoms.first())
+                    .map(|atom| format!(" at ({}, {})", atom.coord.0.x, atom.coord.0.y))
+                    .unwrap_or_default();
+                format!(
+                    "trace #{}{} stuck {}",
+                    trace,
+                    at,
+                    if high { "high" } else { "low" }
+                )
+            }
+            Fault::GateStuck { gate, closed } => {
+                let at = compiler_results
+                    .gates
+                    .get(gate)
+                    .map(|gate| format!(" at ({}, {})", gate.coord.0.x, gate.coord.0.y))
+                    .unwrap_or_default();
+                format!(
+                    "gate #{}{} stuck {}",
+                    gate,
+                    at,
+                    if closed { "closed" } else { "open" }
+                )
+            }
+        }
+    }
+}
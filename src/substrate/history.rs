@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+
+use super::{buffer::Buffer, buffer_delta::BufferDelta};
+
+/// The default number of cell (and socket) changes the history holds across all undo and redo
+/// entries before the oldest entries are dropped.
+pub const DEFAULT_HISTORY_LIMIT: usize = 4_000_000;
+
+/// Undo/redo stacks of buffer checkpoints. Only deltas are stored, so the memory held is
+/// proportional to how much was edited, not to the size of the buffer.
+pub struct History {
+    /// The buffer as of the last checkpoint. Deltas are always taken against (and applied to)
+    /// this buffer, never to a buffer with in-progress edits.
+    head: Buffer,
+
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+
+    /// The total `len` of every delta held in both stacks.
+    size: usize,
+
+    /// The maximum `size` before the oldest undo entries are dropped.
+    limit: usize,
+}
+
+struct HistoryEntry {
+    /// Takes the buffer from before the checkpoint to after it.
+    redo: BufferDelta,
+    /// Takes the buffer from after the checkpoint to before it.
+    undo: BufferDelta,
+}
+
+impl History {
+    pub fn new(head: Buffer) -> Self {
+        Self {
+            head,
+            undo_stack: Default::default(),
+            redo_stack: vec![],
+            size: 0,
+            limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    /// Records `buffer` as the new head, pushing the changes since the last checkpoint onto the
+    /// undo stack and clearing the redo stack. Returns false if nothing changed.
+    pub fn checkpoint(&mut self, buffer: &Buffer) -> bool {
+        let entry = HistoryEntry {
+            redo: BufferDelta::new(&self.head, buffer),
+            undo: BufferDelta::new(buffer, &self.head),
+        };
+
+        if entry.redo.is_empty() {
+            return false;
+        }
+
+        for dropped in self.redo_stack.drain(..) {
+            self.size -= dropped.len();
+        }
+
+        self.size += entry.len();
+        self.undo_stack.push_back(entry);
+        self.head = buffer.clone();
+        self.enforce_limit();
+
+        true
+    }
+
+    /// Steps the head back one checkpoint, returning the restored buffer (if there was anything
+    /// to undo).
+    pub fn undo(&mut self) -> Option<Buffer> {
+        let entry = self.undo_stack.pop_back()?;
+        self.head = entry.undo.apply(&self.head);
+        self.redo_stack.push(entry);
+        Some(self.head.clone())
+    }
+
+    /// Steps the head forward one previously undone checkpoint, returning the restored buffer (if
+    /// there was anything to redo).
+    pub fn redo(&mut self) -> Option<Buffer> {
+        let entry = self.redo_stack.pop()?;
+        self.head = entry.redo.apply(&self.head);
+        self.undo_stack.push_back(entry);
+        Some(self.head.clone())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Sets the maximum number of cell changes held, dropping the oldest undo entries to fit.
+    /// The most recent entry is always kept, even if it alone exceeds the limit.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.enforce_limit();
+    }
+
+    fn enforce_limit(&mut self) {
+        while self.size > self.limit && self.undo_stack.len() > 1 {
+            if let Some(dropped) = self.undo_stack.pop_front() {
+                self.size -= dropped.len();
+            }
+        }
+    }
+}
+
+impl HistoryEntry {
+    fn len(&self) -> usize {
+        self.redo.len() + self.undo.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use crate::{coords::CellCoord, upc::UPC};
+
+    use super::*;
+
+    fn cell(x: i32) -> CellCoord {
+        CellCoord(IVec2::new(x, 0))
+    }
+
+    fn with_cell(buffer: &Buffer, x: i32) -> Buffer {
+        let mut buffer = buffer.clone();
+        buffer.set_cell(cell(x), UPC::from_slice(&[0xFF, 0, 0, 0]));
+        buffer
+    }
+
+    #[test]
+    fn undo_and_redo_restore_checkpoints() {
+        let empty = Buffer::default();
+        let mut history = History::new(empty.clone());
+
+        let one = with_cell(&empty, 0);
+        let two = with_cell(&one, 70);
+        assert!(history.checkpoint(&one));
+        assert!(history.checkpoint(&two));
+
+        // Checkpointing an unchanged buffer records nothing.
+        assert!(!history.checkpoint(&two));
+
+        assert_eq!(history.undo().unwrap().cell_count(), 1);
+        assert_eq!(history.undo().unwrap().cell_count(), 0);
+        assert!(history.undo().is_none());
+
+        let redone = history.redo().unwrap();
+        assert_eq!(redone.get_cell(cell(0)), one.get_cell(cell(0)));
+        assert_eq!(redone.cell_count(), 1);
+
+        // A new checkpoint drops everything that was undone.
+        assert!(history.checkpoint(&with_cell(&redone, 5)));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undo_restores_sockets() {
+        let empty = Buffer::default();
+        let mut history = History::new(empty.clone());
+
+        let mut socketed = with_cell(&empty, 0);
+        socketed.set_socket(cell(0), Some("A".to_string()));
+        assert!(history.checkpoint(&socketed));
+
+        assert!(history.undo().unwrap().sockets.is_empty());
+        let redone = history.redo().unwrap();
+        assert_eq!(redone.sockets.len(), 1);
+        assert_eq!(redone.sockets[0].name, "A");
+    }
+
+    #[test]
+    fn undo_and_redo_swap_socket_names() {
+        let mut original = with_cell(&with_cell(&Buffer::default(), 0), 1);
+        original.set_socket(cell(0), Some("A".to_string()));
+        original.set_socket(cell(1), Some("B".to_string()));
+        let mut history = History::new(original.clone());
+
+        // Swapped directly, as `set_socket` would make the names unique.
+        let mut swapped = original.clone();
+        swapped.sockets[0].name = "B".to_string();
+        swapped.sockets[1].name = "A".to_string();
+        assert!(history.checkpoint(&swapped));
+
+        let names = |buffer: &Buffer| -> Vec<(CellCoord, String)> {
+            let mut names: Vec<_> = buffer
+                .sockets
+                .iter()
+                .map(|s| (s.cell_coord, s.name.clone()))
+                .collect();
+            names.sort_by_key(|(coord, _)| coord.0.x);
+            names
+        };
+
+        assert_eq!(names(&history.undo().unwrap()), names(&original));
+        assert_eq!(names(&history.redo().unwrap()), names(&swapped));
+    }
+
+    #[test]
+    fn limit_drops_oldest_entries() {
+        let mut buffer = Buffer::default();
+        let mut history = History::new(buffer.clone());
+
+        for x in 0..4 {
+            buffer = with_cell(&buffer, x);
+            history.checkpoint(&buffer);
+        }
+
+        // Each single-cell checkpoint holds two cell deltas (undo and redo).
+        history.set_limit(4);
+        assert!(history.undo().is_some());
+        assert!(history.undo().is_some());
+        assert!(history.undo().is_none());
+    }
+}
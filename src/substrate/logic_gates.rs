@@ -0,0 +1,465 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::substrate::{
+    buffer::Buffer,
+    compiler::{Atom, CellPart, CompilerResults, Gate},
+};
+
+/// A higher-level view of the compiled transistor graph, with common topologies recognized as
+/// logic gates and everything else left as individual transistors.
+///
+/// There are no supply rails or pull-downs in Logic Paint: traces read low unless something
+/// connects them to a driven trace. So every recognized gate is a pull-up network between a
+/// `source` trace (usually a socket bonded to a constant high) and its output, and the output is
+/// high exactly when the source is high and the network conducts. Networks are found by
+/// collapsing transistors in series (through traces nothing else touches) and in parallel.
+pub struct GateNetlist {
+    pub gates: Vec<LogicGate>,
+
+    /// Indexes (into `CompilerResults::gates`) of transistors that weren't part of any recognized
+    /// gate.
+    pub unmatched: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicGate {
+    pub kind: LogicGateKind,
+
+    /// Input traces, in transistor order.
+    pub inputs: Vec<usize>,
+
+    /// The trace the pull-up network connects to its outputs.
+    pub source: usize,
+
+    /// Output traces. Latches have two (Q then !Q), all other gates one.
+    pub outputs: Vec<usize>,
+
+    /// Indexes (into `CompilerResults::gates`) of the transistors making up the gate.
+    pub transistors: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicGateKind {
+    /// A single PNP.
+    Inverter,
+    /// PNPs in parallel.
+    Nand,
+    /// PNPs in series.
+    Nor,
+    /// NPNs in series.
+    And,
+    /// NPNs in parallel.
+    Or,
+    /// An NPN and a PNP in parallel.
+    TransmissionGate,
+    /// Two inverting gates (inverters, NANDs or NORs) driving each other's inputs.
+    Latch,
+}
+
+/// A series-parallel network of transistors between two traces.
+enum Network {
+    Transistor(usize),
+    Series(Vec<Network>),
+    Parallel(Vec<Network>),
+}
+
+struct Edge {
+    ends: (usize, usize),
+    network: Network,
+}
+
+impl GateNetlist {
+    pub fn new(buffer: &Buffer, compiler_results: &CompilerResults) -> Self {
+        let ports: HashSet<usize> = buffer
+            .sockets
+            .iter()
+            .filter_map(|socket| {
+                compiler_results.trace_lookup_by_atom.get(&Atom {
+                    coord: socket.cell_coord,
+                    part: CellPart::Metal,
+                })
+            })
+            .cloned()
+            .collect();
+
+        Self::from_gates(&compiler_results.gates, &ports)
+    }
+
+    /// Recognizes gates among `gates`. Traces in `ports` are visible outside the netlist, so
+    /// transistors are never collapsed through them.
+    pub fn from_gates(gates: &[Gate], ports: &HashSet<usize>) -> Self {
+        let bases: HashSet<usize> = gates.iter().map(|g| g.base_trace).collect();
+        let mut unmatched = vec![];
+
+        let mut edges: Vec<Edge> = vec![];
+        for (i, gate) in gates.iter().enumerate() {
+            // A transistor shorted onto a single trace doesn't switch anything.
+            if gate.left_ec_trace == gate.right_ec_trace {
+                unmatched.push(i);
+            } else {
+                edges.push(Edge {
+                    ends: (gate.left_ec_trace, gate.right_ec_trace),
+                    network: Network::Transistor(i),
+                });
+            }
+        }
+
+        // Collapse until neither pass finds anything.
+        loop {
+            let before = edges.len();
+            edges = collapse_parallel(edges);
+            edges = collapse_series(edges, &bases, ports);
+            if edges.len() == before {
+                break;
+            }
+        }
+
+        // Sources fan out to many networks, outputs usually see just the one.
+        let mut degree: HashMap<usize, usize> = HashMap::new();
+        for edge in &edges {
+            *degree.entry(edge.ends.0).or_default() += 1;
+            *degree.entry(edge.ends.1).or_default() += 1;
+        }
+
+        let mut logic_gates = vec![];
+        for edge in edges {
+            let (a, b) = edge.ends;
+
+            // Networks that loop back onto a single trace don't switch anything either.
+            let Some(kind) = edge.network.classify(gates).filter(|_| a != b) else {
+                edge.network.transistors(&mut unmatched);
+                continue;
+            };
+
+            let rank = |trace: usize| (degree[&trace], !bases.contains(&trace));
+            let a_is_output = rank(a) < rank(b);
+            let (source, output) = if a_is_output { (b, a) } else { (a, b) };
+
+            let mut transistors = vec![];
+            edge.network.transistors(&mut transistors);
+
+            logic_gates.push(LogicGate {
+                kind,
+                inputs: transistors.iter().map(|&i| gates[i].base_trace).collect(),
+                source,
+                outputs: vec![output],
+                transistors,
+            });
+        }
+
+        unmatched.sort_unstable();
+
+        Self {
+            gates: pair_latches(logic_gates),
+            unmatched,
+        }
+    }
+
+    /// The number of each kind of recognized gate.
+    pub fn counts(&self) -> HashMap<LogicGateKind, usize> {
+        let mut counts = HashMap::new();
+        for gate in &self.gates {
+            *counts.entry(gate.kind).or_default() += 1;
+        }
+        counts
+    }
+}
+
+impl Network {
+    fn transistors(&self, out: &mut Vec<usize>) {
+        match self {
+            Network::Transistor(i) => out.push(*i),
+            Network::Series(networks) | Network::Parallel(networks) => {
+                networks.iter().for_each(|n| n.transistors(out))
+            }
+        }
+    }
+
+    /// The polarity of a lone transistor, or None for a compound network.
+    fn is_npn(&self, gates: &[Gate]) -> Option<bool> {
+        match self {
+            Network::Transistor(i) => Some(gates[*i].is_npn),
+            _ => None,
+        }
+    }
+
+    fn classify(&self, gates: &[Gate]) -> Option<LogicGateKind> {
+        let polarities = |networks: &[Network]| -> Option<Vec<bool>> {
+            networks.iter().map(|n| n.is_npn(gates)).collect()
+        };
+
+        match self {
+            Network::Transistor(i) if !gates[*i].is_npn => Some(LogicGateKind::Inverter),
+            Network::Transistor(_) => None,
+            Network::Series(networks) => match polarities(networks)?.as_slice() {
+                p if p.iter().all(|&npn| npn) => Some(LogicGateKind::And),
+                p if p.iter().all(|&npn| !npn) => Some(LogicGateKind::Nor),
+                _ => None,
+            },
+            Network::Parallel(networks) => match polarities(networks)?.as_slice() {
+                [true, false] | [false, true] => Some(LogicGateKind::TransmissionGate),
+                p if p.iter().all(|&npn| npn) => Some(LogicGateKind::Or),
+                p if p.iter().all(|&npn| !npn) => Some(LogicGateKind::Nand),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Merges edges that share both ends.
+fn collapse_parallel(edges: Vec<Edge>) -> Vec<Edge> {
+    let mut by_ends: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut merged: Vec<Edge> = vec![];
+
+    for edge in edges {
+        let (a, b) = edge.ends;
+        let key = (a.min(b), a.max(b));
+
+        match by_ends.get(&key) {
+            Some(&i) => {
+                let existing = &mut merged[i].network;
+                let mut networks = match std::mem::replace(existing, Network::Parallel(vec![])) {
+                    Network::Parallel(networks) => networks,
+                    network => vec![network],
+                };
+                match edge.network {
+                    Network::Parallel(inner) => networks.extend(inner),
+                    network => networks.push(network),
+                }
+                *existing = Network::Parallel(networks);
+            }
+            None => {
+                by_ends.insert(key, merged.len());
+                merged.push(edge);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Merges pairs of edges joined by a trace that nothing else touches (not a port, not a base and
+/// not the end of any other edge).
+fn collapse_series(edges: Vec<Edge>, bases: &HashSet<usize>, ports: &HashSet<usize>) -> Vec<Edge> {
+    let mut by_trace: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate() {
+        by_trace.entry(edge.ends.0).or_default().push(i);
+        by_trace.entry(edge.ends.1).or_default().push(i);
+    }
+
+    let mut edges: Vec<Option<Edge>> = edges.into_iter().map(Some).collect();
+    let mut joints: Vec<_> = by_trace
+        .into_iter()
+        .filter(|(trace, incident)| {
+            incident.len() == 2 && !bases.contains(trace) && !ports.contains(trace)
+        })
+        .collect();
+    joints.sort_unstable_by_key(|(trace, _)| *trace);
+
+    for (trace, incident) in joints {
+        // Either edge may already have been merged through another joint this pass, in which
+        // case the next pass picks this joint up again.
+        if incident[0] == incident[1]
+            || edges[incident[0]].is_none()
+            || edges[incident[1]].is_none()
+        {
+            continue;
+        }
+
+        let first = edges[incident[0]].take().unwrap();
+        let second = edges[incident[1]].take().unwrap();
+
+        let other_end = |edge: &Edge| {
+            if edge.ends.0 == trace {
+                edge.ends.1
+            } else {
+                edge.ends.0
+            }
+        };
+
+        let ends = (other_end(&first), other_end(&second));
+        let mut networks = vec![];
+        for network in [first.network, second.network] {
+            match network {
+                Network::Series(inner) => networks.extend(inner),
+                network => networks.push(network),
+            }
+        }
+
+        edges[incident[0]] = Some(Edge {
+            ends,
+            network: Network::Series(networks),
+        });
+    }
+
+    edges.into_iter().flatten().collect()
+}
+
+/// Pairs up inverting gates whose outputs drive each other's inputs into latches.
+fn pair_latches(gates: Vec<LogicGate>) -> Vec<LogicGate> {
+    let inverting = |g: &LogicGate| {
+        matches!(
+            g.kind,
+            LogicGateKind::Inverter | LogicGateKind::Nand | LogicGateKind::Nor
+        )
+    };
+
+    let by_output: HashMap<usize, usize> = gates
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| inverting(g))
+        .map(|(i, g)| (g.outputs[0], i))
+        .collect();
+
+    let mut partner = vec![None; gates.len()];
+    for (i, gate) in gates.iter().enumerate() {
+        if !inverting(gate) || partner[i].is_some() {
+            continue;
+        }
+
+        let found = gate
+            .inputs
+            .iter()
+            .filter_map(|input| by_output.get(input))
+            .find(|&&j| {
+                j != i && partner[j].is_none() && gates[j].inputs.contains(&gate.outputs[0])
+            });
+
+        if let Some(&j) = found {
+            partner[i] = Some(j);
+            partner[j] = Some(i);
+        }
+    }
+
+    let mut gates: Vec<Option<LogicGate>> = gates.into_iter().map(Some).collect();
+    let mut paired = vec![];
+    for i in 0..gates.len() {
+        let Some(gate) = gates[i].take() else {
+            continue;
+        };
+
+        let Some(j) = partner[i] else {
+            paired.push(gate);
+            continue;
+        };
+
+        let other = gates[j].take().unwrap();
+        let outputs = vec![gate.outputs[0], other.outputs[0]];
+
+        paired.push(LogicGate {
+            kind: LogicGateKind::Latch,
+            inputs: gate
+                .inputs
+                .iter()
+                .chain(&other.inputs)
+                .filter(|input| !outputs.contains(input))
+                .cloned()
+                .collect(),
+            source: gate.source,
+            outputs,
+            transistors: gate
+                .transistors
+                .into_iter()
+                .chain(other.transistors)
+                .collect(),
+        });
+    }
+
+    paired
+}
+
+impl fmt::Display for LogicGateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogicGateKind::Inverter => "inverter",
+            LogicGateKind::Nand => "NAND",
+            LogicGateKind::Nor => "NOR",
+            LogicGateKind::And => "AND",
+            LogicGateKind::Or => "OR",
+            LogicGateKind::TransmissionGate => "transmission gate",
+            LogicGateKind::Latch => "latch",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VCC: usize = 1;
+
+    fn fet(is_npn: bool, base_trace: usize, left_ec_trace: usize, right_ec_trace: usize) -> Gate {
+        Gate {
+            coord: Default::default(),
+            is_npn,
+            base_trace,
+            left_ec_trace,
+            right_ec_trace,
+        }
+    }
+
+    fn kinds(netlist: &GateNetlist) -> Vec<LogicGateKind> {
+        netlist.gates.iter().map(|g| g.kind).collect()
+    }
+
+    #[test]
+    fn recognizes_pull_up_networks() {
+        let gates = [
+            // NOR(2, 3) -> 10, through internal trace 20.
+            fet(false, 2, VCC, 20),
+            fet(false, 3, 20, 10),
+            // NAND(10, 4) -> 11
+            fet(false, 10, VCC, 11),
+            fet(false, 4, 11, VCC),
+            // Inverter(11) -> 12
+            fet(false, 11, 12, VCC),
+            // AND(12, 5) -> 13 through internal trace 21.
+            fet(true, 12, VCC, 21),
+            fet(true, 5, 21, 13),
+            // Transmission gate between 13 and 14, controlled by 6 and 7.
+            fet(true, 6, 13, 14),
+            fet(false, 7, 14, 13),
+        ];
+        let ports = HashSet::from([2, 3, 4, 5, 6, 7, 13, 14]);
+
+        let netlist = GateNetlist::from_gates(&gates, &ports);
+        assert_eq!(
+            kinds(&netlist),
+            vec![
+                LogicGateKind::Nor,
+                LogicGateKind::Nand,
+                LogicGateKind::Inverter,
+                LogicGateKind::And,
+                LogicGateKind::TransmissionGate,
+            ]
+        );
+        assert!(netlist.unmatched.is_empty());
+
+        let nor = &netlist.gates[0];
+        assert_eq!((nor.source, nor.outputs.clone()), (VCC, vec![10]));
+        assert_eq!(nor.inputs, vec![2, 3]);
+        assert_eq!(netlist.gates[2].outputs, vec![12]);
+        assert_eq!(netlist.counts()[&LogicGateKind::Nand], 1);
+    }
+
+    #[test]
+    fn pairs_cross_coupled_inverters_into_latches() {
+        let gates = [
+            fet(false, 10, VCC, 11),
+            fet(false, 11, VCC, 10),
+            // A lone NPN isn't a gate on its own.
+            fet(true, 2, VCC, 3),
+        ];
+
+        let netlist = GateNetlist::from_gates(&gates, &HashSet::new());
+        assert_eq!(kinds(&netlist), vec![LogicGateKind::Latch]);
+        assert_eq!(netlist.gates[0].outputs, vec![11, 10]);
+        assert!(netlist.gates[0].inputs.is_empty());
+        assert_eq!(netlist.unmatched, vec![2]);
+    }
+}
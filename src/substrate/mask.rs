@@ -23,10 +23,17 @@ pub struct Mask {
 #[allow(dead_code)]
 impl Mask {
     pub fn from_highlight_trace(buffer: &Buffer, atom: Atom) -> Mask {
+        Self::from_atoms(CompilerResults::get_trace_atoms(buffer, atom))
+    }
+
+    /// Highlights each of the given atoms.
+    pub fn from_atoms<I>(atoms: I) -> Mask
+    where
+        I: IntoIterator<Item = Atom>,
+    {
         let mut mask = Mask::default();
-        let trace = CompilerResults::get_trace_atoms(buffer, atom);
 
-        for atom in trace {
+        for atom in atoms {
             let chunk_coord: ChunkCoord = atom.coord.into();
             let local_coord: LocalCoord = atom.coord.into();
 
@@ -43,6 +50,27 @@ impl Mask {
         mask
     }
 
+    /// Sets every compiled cell part to `byte(trace)` for the trace it is part of, using the
+    /// index built by the compiler.
+    pub fn write_traces<F>(&mut self, compiler_results: &CompilerResults, byte: F)
+    where
+        F: Fn(usize) -> u8,
+    {
+        for (chunk_coord, cell_part_to_traces) in
+            compiler_results.trace_to_cell_part_index_by_chunk.iter()
+        {
+            let chunk = self.get_or_create_chunk_mut(*chunk_coord);
+            for index in cell_part_to_traces {
+                let i = index.cell_index_in_chunk * MASK_BYTE_LEN;
+                let cell_slice = &mut chunk.cells[i..i + MASK_BYTE_LEN];
+                cell_slice[0] = byte(index.metal_trace);
+                cell_slice[1] = byte(index.si_trace);
+                cell_slice[2] = byte(index.left_ec_trace);
+                cell_slice[3] = byte(index.right_ec_trace);
+            }
+        }
+    }
+
     pub fn get_chunk<T>(&self, c: T) -> Option<&BufferMaskChunk>
     where
         T: Into<ChunkCoord>,
@@ -1,10 +1,20 @@
+pub mod breakpoint;
 pub mod buffer;
 pub mod buffer_brush;
 pub mod buffer_delta;
 pub mod buffer_serde;
 pub mod compiler;
 pub mod compress;
+pub mod drc;
 pub mod execution_context;
+pub mod fault;
+pub mod history;
 pub mod io;
 pub mod label_builder;
+pub mod logic_gates;
 pub mod mask;
+pub mod netlist;
+pub mod scheduler;
+pub mod snapshot;
+pub mod toggle_coverage;
+pub mod waveform;
@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crate::substrate::{
+    buffer::Buffer,
+    compiler::{Atom, CellPart, CompilerResults, Gate},
+};
+
+/// A named, flat transistor netlist of a compiled buffer, for use in external simulators. Sockets
+/// become ports (named after the socket), every other trace becomes an internal net named after
+/// its trace index.
+///
+/// Logic Paint's transistors are bidirectional and there are no supply rails; a trace is high when
+/// it's connected to a driven socket through conducting transistors. NPN gates conduct while their
+/// base is high and PNP gates while it's low, so they map to NMOS and PMOS switches respectively.
+pub struct Netlist {
+    /// The Verilog module / SPICE subcircuit name.
+    pub name: String,
+
+    /// Port names in socket order, with the trace they connect to.
+    pub ports: Vec<(String, usize)>,
+
+    /// Net name by trace index. Empty for traces that nothing connects to.
+    pub nets: Vec<String>,
+
+    /// The transistors, in compiled gate order.
+    pub gates: Vec<Gate>,
+}
+
+impl Netlist {
+    pub fn new(name: &str, buffer: &Buffer, compiler_results: &CompilerResults) -> Self {
+        let mut nets = vec![String::new(); compiler_results.traces.len()];
+        let mut ports = vec![];
+        let mut taken = HashSet::new();
+
+        for socket in &buffer.sockets {
+            let Some(&trace) = compiler_results.trace_lookup_by_atom.get(&Atom {
+                coord: socket.cell_coord,
+                part: CellPart::Metal,
+            }) else {
+                continue;
+            };
+
+            let mut port = identifier(&socket.name);
+            while !taken.insert(port.clone()) {
+                port.push('_');
+            }
+
+            // The first socket on a trace names its net, others are tied to it.
+            if nets[trace].is_empty() {
+                nets[trace] = port.clone();
+            }
+
+            ports.push((port, trace));
+        }
+
+        for gate in &compiler_results.gates {
+            for trace in [gate.base_trace, gate.left_ec_trace, gate.right_ec_trace] {
+                if nets[trace].is_empty() {
+                    let mut net = format!("t{}", trace);
+                    while !taken.insert(net.clone()) {
+                        net.insert(0, '_');
+                    }
+                    nets[trace] = net;
+                }
+            }
+        }
+
+        Self {
+            name: identifier(name),
+            ports,
+            nets,
+            gates: compiler_results.gates.clone(),
+        }
+    }
+
+    /// Ports that share a trace with an earlier port, as (port, net) pairs.
+    fn ties(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.ports
+            .iter()
+            .filter(|(port, trace)| &self.nets[*trace] != port)
+            .map(|(port, trace)| (port.as_str(), self.nets[*trace].as_str()))
+    }
+
+    /// Internal (non-port) nets, in trace order.
+    fn internal_nets(&self) -> impl Iterator<Item = &str> {
+        let ports: HashSet<&str> = self.ports.iter().map(|(p, _)| p.as_str()).collect();
+        self.nets
+            .iter()
+            .map(|n| n.as_str())
+            .filter(move |n| !n.is_empty() && !ports.contains(n))
+    }
+
+    /// Writes a structural Verilog module using switch-level `nmos`/`pmos` primitives. Ports are
+    /// `inout`, as any socket can be driven by a module or read from. Note that the primitives
+    /// are unidirectional (left EC to right EC) where Logic Paint's transistors are not.
+    pub fn write_verilog(&self, w: &mut impl Write) -> io::Result<()> {
+        let ports: Vec<&str> = self.ports.iter().map(|(p, _)| p.as_str()).collect();
+
+        writeln!(w, "// Generated by logic-paint.")?;
+        writeln!(w, "module {} ({});", self.name, ports.join(", "))?;
+        for port in &ports {
+            writeln!(w, "  inout {};", port)?;
+        }
+        for net in self.internal_nets() {
+            writeln!(w, "  wire {};", net)?;
+        }
+        for (i, (port, net)) in self.ties().enumerate() {
+            writeln!(w, "  tran tie{} ({}, {});", i, port, net)?;
+        }
+        for (i, gate) in self.gates.iter().enumerate() {
+            writeln!(
+                w,
+                "  {} g{} ({}, {}, {});",
+                if gate.is_npn { "nmos" } else { "pmos" },
+                i,
+                self.nets[gate.right_ec_trace],
+                self.nets[gate.left_ec_trace],
+                self.nets[gate.base_trace],
+            )?;
+        }
+        writeln!(w, "endmodule")?;
+
+        Ok(())
+    }
+
+    pub fn to_verilog_string(&self) -> String {
+        let mut bytes = vec![];
+        self.write_verilog(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Writes a SPICE-like subcircuit with one MOSFET card per gate (bulk tied to source), and
+    /// zero volt sources tying ports that share a trace.
+    pub fn write_spice(&self, w: &mut impl Write) -> io::Result<()> {
+        let ports: Vec<&str> = self.ports.iter().map(|(p, _)| p.as_str()).collect();
+
+        writeln!(w, "* Generated by logic-paint.")?;
+        writeln!(w, ".subckt {} {}", self.name, ports.join(" "))?;
+        for (i, (port, net)) in self.ties().enumerate() {
+            writeln!(w, "Vtie{} {} {} 0", i, port, net)?;
+        }
+        for (i, gate) in self.gates.iter().enumerate() {
+            let source = &self.nets[gate.right_ec_trace];
+            writeln!(
+                w,
+                "M{} {} {} {} {} {}",
+                i,
+                self.nets[gate.left_ec_trace],
+                self.nets[gate.base_trace],
+                source,
+                source,
+                if gate.is_npn { "NMOS" } else { "PMOS" },
+            )?;
+        }
+        writeln!(w, ".ends {}", self.name)?;
+        writeln!(w, ".model NMOS NMOS")?;
+        writeln!(w, ".model PMOS PMOS")?;
+
+        Ok(())
+    }
+
+    pub fn to_spice_string(&self) -> String {
+        let mut bytes = vec![];
+        self.write_spice(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+/// Verilog (IEEE 1364-2005) reserved words, which can't be used as identifiers.
+const VERILOG_KEYWORDS: &str =
+    "always and assign automatic begin buf bufif0 bufif1 case casex casez cell cmos config \
+     deassign default defparam design disable edge else end endcase endconfig endfunction \
+     endgenerate endmodule endprimitive endspecify endtable endtask event for force forever fork \
+     function generate genvar highz0 highz1 if ifnone incdir include initial inout input instance \
+     integer join large liblist library localparam macromodule medium module nand negedge nmos nor \
+     noshowcancelled not notif0 notif1 or output parameter pmos posedge primitive pull0 pull1 \
+     pulldown pullup pulsestyle_ondetect pulsestyle_onevent rcmos real realtime reg release repeat \
+     rnmos rpmos rtran rtranif0 rtranif1 scalared showcancelled signed small specify specparam \
+     strong0 strong1 supply0 supply1 table task time tran tranif0 tranif1 tri tri0 tri1 triand \
+     trior trireg unsigned use uwire vectored wait wand weak0 weak1 while wire wor xnor xor";
+
+/// Maps a name onto `[A-Za-z_][A-Za-z0-9_]*`, which both Verilog and SPICE accept, suffixing
+/// Verilog keywords with `_`.
+fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+
+    if VERILOG_KEYWORDS.split_whitespace().any(|k| k == identifier) {
+        identifier.push('_');
+    }
+
+    identifier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single NPN MOSFET with its gate and both ECs brought out to sockets.
+    fn mosfet_buffer() -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.draw_si_link(None, (0, -1).into(), true);
+        buffer.draw_si_link(Some((0, -1).into()), (0, 0).into(), true);
+        buffer.draw_si_link(Some((0, 0).into()), (0, 1).into(), true);
+        buffer.draw_si_link(None, (-1, 0).into(), false);
+        buffer.draw_si_link(Some((-1, 0).into()), (0, 0).into(), false);
+
+        for (x, y, name) in [(-1, 0, "G"), (0, 1, "A"), (0, -1, "B out")] {
+            buffer.draw_metal_link(None, (x, y).into());
+            buffer.draw_via((x, y).into());
+            buffer.set_socket((x, y).into(), Some(name.to_string()));
+        }
+
+        // A second socket on B's trace.
+        buffer.draw_metal_link(Some((0, -1).into()), (1, -1).into());
+        buffer.set_socket((1, -1).into(), Some("B2".to_string()));
+
+        buffer
+    }
+
+    #[test]
+    fn writes_verilog_and_spice() {
+        let buffer = mosfet_buffer();
+        let compiler_results = CompilerResults::from_buffer(&buffer);
+        let netlist = Netlist::new("single fet", &buffer, &compiler_results);
+
+        assert_eq!(netlist.name, "single_fet");
+        assert_eq!(netlist.gates.len(), 1);
+
+        let verilog = netlist.to_verilog_string();
+        assert!(verilog.contains("module single_fet (G, A, B_out, B2);"));
+        assert!(verilog.contains("  inout B_out;"));
+        assert!(verilog.contains("  tran tie0 (B2, B_out);"));
+        assert!(!verilog.contains("wire"));
+        let fet = verilog.lines().find(|l| l.contains("nmos g0")).unwrap();
+        assert!(fet.contains("A") && fet.contains("B_out") && fet.ends_with(", G);"));
+
+        let spice = netlist.to_spice_string();
+        assert!(spice.contains(".subckt single_fet G A B_out B2"));
+        assert!(spice.contains("Vtie0 B2 B_out 0"));
+        let fet = spice.lines().find(|l| l.starts_with("M0 ")).unwrap();
+        assert!(fet.contains(" G ") && fet.ends_with(" NMOS"));
+    }
+
+    #[test]
+    fn identifiers_are_valid() {
+        assert_eq!(identifier("ALU out[3]"), "ALU_out_3_");
+        assert_eq!(identifier("3V"), "_3V");
+        for keyword in ["module", "wire", "input", "end"] {
+            assert_eq!(identifier(keyword), format!("{}_", keyword));
+        }
+        assert_eq!(identifier("Module"), "Module");
+    }
+}
@@ -0,0 +1,268 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{substrate::compiler::CompilerResults, utils::WorkerPool};
+
+/// The default minimum number of dirty traces in a tick before work is spread across threads.
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 4096;
+
+/// Bookkeeping for the event-driven tick. Trace states are no longer reset each tick, instead
+/// only the conducting-gate components (groups of traces connected through conducting gates) that
+/// could have changed are re-evaluated. A component can only change state when one of its traces
+/// changed drive (from a module), or a gate touching it by an EC changed state. Likewise a gate
+/// only needs re-evaluation when its base trace changed state.
+pub struct Scheduler {
+    /// Gates connected to a trace by either EC, indexed by trace.
+    pub gates_by_ec_trace: Vec<Vec<usize>>,
+
+    /// Gates whose base is a trace, indexed by trace.
+    pub gates_by_base_trace: Vec<Vec<usize>>,
+
+    /// Module drive (bond wire input) state as of the last tick, indexed by trace.
+    pub driven: Vec<bool>,
+
+    /// Scratch space for collecting this tick's module drive, indexed by trace.
+    pub next_driven: Vec<bool>,
+
+    /// Traces held low by a fault, indexed by trace. They are sinks: never set high, and never
+    /// passing a high on to the traces they connect to.
+    pub grounded: Vec<bool>,
+
+    /// Traces that must be re-evaluated this tick.
+    pub dirty_traces: Vec<usize>,
+
+    /// Traces that changed state this tick.
+    pub changed_traces: Vec<usize>,
+
+    /// Gates that changed state last tick.
+    pub changed_gates: Vec<usize>,
+
+    /// Set when the next tick needs to evaluate every trace and gate, for example on the first
+    /// tick or after the SimState was modified externally.
+    pub full_evaluation: bool,
+
+    /// The static island of each trace: traces connected through any gate EC, conducting or
+    /// not. Conducting components never span islands, so islands can be propagated on separate
+    /// threads without synchronization.
+    pub island_by_trace: Vec<usize>,
+
+    /// The minimum number of dirty traces in a tick before work is spread across threads. Below
+    /// this, the cost of handing work to threads dominates.
+    pub parallel_threshold: usize,
+
+    /// The threads to propagate on (see `set_threads`). None propagates on the calling thread.
+    pool: Option<WorkerPool>,
+
+    /// Visitation stamps, indexed by trace. A trace was visited this tick if its stamp equals
+    /// `epoch`, which saves clearing the whole list each tick. Atomic only so that workers can
+    /// share the list; each trace is only ever touched by the worker that owns its island.
+    visited: Vec<AtomicU32>,
+    epoch: u32,
+}
+
+impl Scheduler {
+    pub fn new(compiler_results: &CompilerResults) -> Self {
+        let trace_count = compiler_results.traces.len();
+        let mut gates_by_ec_trace = vec![vec![]; trace_count];
+        let mut gates_by_base_trace = vec![vec![]; trace_count];
+
+        for (i, gate) in compiler_results.gates.iter().enumerate() {
+            gates_by_ec_trace[gate.left_ec_trace].push(i);
+            if gate.right_ec_trace != gate.left_ec_trace {
+                gates_by_ec_trace[gate.right_ec_trace].push(i);
+            }
+            gates_by_base_trace[gate.base_trace].push(i);
+        }
+
+        // Union-find over all EC connections.
+        let mut parents: Vec<usize> = (0..trace_count).collect();
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+        for gate in &compiler_results.gates {
+            let left = root(&mut parents, gate.left_ec_trace);
+            let right = root(&mut parents, gate.right_ec_trace);
+            parents[left.max(right)] = left.min(right);
+        }
+        let island_by_trace = (0..trace_count).map(|i| root(&mut parents, i)).collect();
+
+        Self {
+            gates_by_ec_trace,
+            gates_by_base_trace,
+            driven: vec![false; trace_count],
+            next_driven: vec![false; trace_count],
+            grounded: vec![false; trace_count],
+            dirty_traces: vec![],
+            changed_traces: vec![],
+            changed_gates: vec![],
+            full_evaluation: true,
+            island_by_trace,
+            parallel_threshold: DEFAULT_PARALLEL_THRESHOLD,
+            pool: None,
+            visited: (0..trace_count).map(|_| AtomicU32::new(0)).collect(),
+            epoch: 0,
+        }
+    }
+
+    /// How many threads each tick propagates on.
+    pub fn threads(&self) -> usize {
+        self.pool.as_ref().map_or(1, |pool| pool.workers())
+    }
+
+    /// Spawns `threads` worker threads to propagate on (once, they are reused every tick), or
+    /// propagates on the calling thread for one. Ticks stay on the calling thread until the
+    /// workers have started.
+    pub fn set_threads(&mut self, threads: usize) {
+        if threads != self.threads() {
+            self.pool = (threads > 1).then(|| WorkerPool::new(threads));
+        }
+    }
+
+    /// True once the worker threads (if any) have started, so ticks can run on them.
+    pub fn threads_ready(&self) -> bool {
+        self.pool.as_ref().is_none_or(WorkerPool::is_ready)
+    }
+
+    /// Re-evaluates every component containing a dirty trace, setting each trace in it high if any
+    /// trace in it is driven. Grounded traces are the exception, and always end up low. Fills
+    /// `changed_traces` and returns the number of traces visited.
+    pub fn propagate(
+        &mut self,
+        compiler_results: &CompilerResults,
+        gate_states: &[bool],
+        trace_states: &mut [bool],
+    ) -> usize {
+        self.changed_traces.clear();
+        self.epoch = self.epoch.wrapping_add(1);
+        if self.epoch == 0 {
+            self.visited
+                .iter()
+                .for_each(|v| v.store(0, Ordering::Relaxed));
+            self.epoch = 1;
+        }
+
+        let mut dirty_traces = std::mem::take(&mut self.dirty_traces);
+        let mut changed_traces = std::mem::take(&mut self.changed_traces);
+        let mut visits = 0;
+
+        let pool = self.pool.as_ref().filter(|pool| pool.is_ready());
+        if let Some(pool) = pool.filter(|_| dirty_traces.len() >= self.parallel_threshold) {
+            // Islands are dealt out to workers round-robin by their id, each worker reports the
+            // traces that need to flip.
+            let threads = pool.workers();
+            let this = &*self;
+            let state = &*trace_states;
+            let dirty = &dirty_traces;
+
+            let results = pool.run(|worker| {
+                let mut changed = vec![];
+                let seeds = dirty
+                    .iter()
+                    .copied()
+                    .filter(|&t| this.island_by_trace[t] % threads == worker);
+                let visits = this.flood(seeds, compiler_results, gate_states, state, &mut changed);
+                (visits, changed)
+            });
+
+            for (worker_visits, changed) in results {
+                visits += worker_visits;
+                changed_traces.extend(changed);
+            }
+        } else {
+            visits = self.flood(
+                dirty_traces.iter().copied(),
+                compiler_results,
+                gate_states,
+                trace_states,
+                &mut changed_traces,
+            );
+        }
+
+        for &trace in &changed_traces {
+            trace_states[trace] = !trace_states[trace];
+        }
+
+        dirty_traces.clear();
+        self.dirty_traces = dirty_traces;
+        self.changed_traces = changed_traces;
+
+        visits
+    }
+
+    /// Floods the conducting component of each (not yet visited) seed, collecting the traces
+    /// whose state differs from the component's. Returns the number of traces visited.
+    fn flood(
+        &self,
+        seeds: impl Iterator<Item = usize>,
+        compiler_results: &CompilerResults,
+        gate_states: &[bool],
+        trace_states: &[bool],
+        changed: &mut Vec<usize>,
+    ) -> usize {
+        let mut visits = 0;
+        let mut component = vec![];
+
+        for seed in seeds {
+            if !self.visit(seed) {
+                continue;
+            }
+
+            component.clear();
+            component.push(seed);
+            let mut high = false;
+            let mut i = 0;
+
+            while i < component.len() {
+                let trace = component[i];
+                i += 1;
+
+                // Grounded traces join the component only to be held low, flooding stops there.
+                if self.grounded[trace] {
+                    continue;
+                }
+
+                high |= self.driven[trace];
+
+                for &gate_index in &self.gates_by_ec_trace[trace] {
+                    if !gate_states[gate_index] {
+                        continue;
+                    }
+
+                    let gate = &compiler_results.gates[gate_index];
+                    let other = if gate.left_ec_trace == trace {
+                        gate.right_ec_trace
+                    } else {
+                        gate.left_ec_trace
+                    };
+
+                    if self.visit(other) {
+                        component.push(other);
+                    }
+                }
+            }
+
+            visits += component.len();
+            changed.extend(
+                component
+                    .iter()
+                    .filter(|&&t| trace_states[t] != (high && !self.grounded[t])),
+            );
+        }
+
+        visits
+    }
+
+    /// Marks the trace visited, returning true if it wasn't already.
+    fn visit(&self, trace: usize) -> bool {
+        // Load then store (rather than swap) as there is never contention on a single trace.
+        if self.visited[trace].load(Ordering::Relaxed) == self.epoch {
+            return false;
+        }
+
+        self.visited[trace].store(self.epoch, Ordering::Relaxed);
+        true
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+
+use crate::substrate::execution_context::SimState;
+
+/// The default number of snapshots an ExecutionContext keeps when recording. A snapshot holds a
+/// byte per trace and gate, plus the state of every module (including the full contents of RAM
+/// modules), so this is kept fairly small.
+pub const DEFAULT_SNAPSHOT_CAPACITY: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("failed to encode snapshot: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+
+    #[error("failed to decode snapshot: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+
+    #[error("snapshot was taken from a different circuit or set of modules")]
+    Mismatch,
+
+    #[error("no snapshot recorded at index {0}")]
+    NotRecorded(usize),
+
+    #[error("can't rewind {0} clocks, not enough are recorded")]
+    TooFarBack(usize),
+}
+
+/// The state of a simulation at one point in time: the SimState, plus the GPIO levels and the
+/// internal state each module saves (see `Module::save_state`). Restoring a snapshot resumes the
+/// simulation where it was taken, as long as every module with state of its own saves it.
+#[derive(Clone, bincode::Encode, bincode::Decode)]
+pub struct Snapshot {
+    pub sim_state: SimState,
+    pub(crate) modules: Vec<ModuleSnapshot>,
+}
+
+#[derive(Clone, bincode::Encode, bincode::Decode)]
+pub(crate) struct ModuleSnapshot {
+    /// The (`si_output_high`, `si_input_high`) levels of each GPIO.
+    pub gpios: Vec<(bool, bool)>,
+
+    /// The module's own state, from `Module::save_state`.
+    pub state: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        Ok(bincode::encode_to_vec(self, bincode::config::standard())?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        Ok(bincode::decode_from_slice(bytes, bincode::config::standard())?.0)
+    }
+}
+
+/// A bounded ring of snapshots, oldest first. Once full, pushing a snapshot drops the oldest.
+pub struct SnapshotRing {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl SnapshotRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity.min(DEFAULT_SNAPSHOT_CAPACITY)),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// The snapshot at `index`, where 0 is the oldest still recorded.
+    pub fn get(&self, index: usize) -> Option<&Snapshot> {
+        self.snapshots.get(index)
+    }
+
+    /// Drops every snapshot after `index`.
+    pub fn truncate_after(&mut self, index: usize) {
+        self.snapshots.truncate(index + 1);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
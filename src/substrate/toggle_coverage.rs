@@ -0,0 +1,197 @@
+use std::io::{self, Write};
+
+use crate::substrate::{
+    compiler::{CompilerResults, IndexRemap},
+    mask::Mask,
+};
+
+/// Counts how many times each trace switched during a run, to find the parts of a design the run
+/// never exercised. Attach one to an ExecutionContext (see `record_toggle_coverage`) and it will
+/// be sampled automatically, only looking at the traces that changed each tick.
+#[derive(Debug, Clone)]
+pub struct ToggleCoverage {
+    /// Per trace, the number of times it went high.
+    pub rises: Vec<u64>,
+
+    /// Per trace, the number of times it went low.
+    pub falls: Vec<u64>,
+
+    /// Per trace, the state it was last counted in.
+    last: Vec<bool>,
+}
+
+impl ToggleCoverage {
+    /// Starts counting from the given trace states (usually the current ones).
+    pub fn new(trace_states: &[bool]) -> Self {
+        Self {
+            rises: vec![0; trace_states.len()],
+            falls: vec![0; trace_states.len()],
+            last: trace_states.to_vec(),
+        }
+    }
+
+    /// Counts a toggle for each of `traces` whose state differs from when it was last counted.
+    /// Called by the ExecutionContext after each tick.
+    pub fn sample<I>(&mut self, traces: I, trace_states: &[bool])
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        for trace in traces {
+            let state = trace_states[trace];
+            if state != self.last[trace] {
+                self.last[trace] = state;
+                if state {
+                    self.rises[trace] += 1;
+                } else {
+                    self.falls[trace] += 1;
+                }
+            }
+        }
+    }
+
+    /// Takes `trace_states` as the last counted states without counting any toggles, for when
+    /// the state jumped (say, a snapshot was restored).
+    pub fn resync(&mut self, trace_states: &[bool]) {
+        self.last.copy_from_slice(trace_states);
+    }
+
+    /// Carries the counts over a recompile. New traces start from zero.
+    pub fn remap(&mut self, remap: &IndexRemap, trace_states: &[bool]) {
+        let mut rises = vec![0; trace_states.len()];
+        let mut falls = vec![0; trace_states.len()];
+
+        for (old, new) in remap.traces.iter().enumerate() {
+            if let Some(new) = *new {
+                rises[new] = self.rises[old];
+                falls[new] = self.falls[old];
+            }
+        }
+
+        self.rises = rises;
+        self.falls = falls;
+        self.last = trace_states.to_vec();
+    }
+
+    pub fn toggles(&self, trace: usize) -> u64 {
+        self.rises[trace] + self.falls[trace]
+    }
+
+    /// The number of traces (excluding the null trace) that both rose and fell.
+    pub fn covered(&self) -> usize {
+        (1..self.rises.len())
+            .filter(|&trace| self.rises[trace] > 0 && self.falls[trace] > 0)
+            .count()
+    }
+
+    /// The fraction of traces that both rose and fell, from 0 to 1.
+    pub fn coverage(&self) -> f64 {
+        let traces = self.rises.len().saturating_sub(1);
+        if traces == 0 {
+            1.0
+        } else {
+            self.covered() as f64 / traces as f64
+        }
+    }
+
+    /// The traces (excluding the null trace) that never switched at all.
+    pub fn never_switched(&self) -> Vec<usize> {
+        (1..self.rises.len())
+            .filter(|&trace| self.toggles(trace) == 0)
+            .collect()
+    }
+
+    /// A heatmap of the counts, for overlaying the buffer. Parts of traces that never switched
+    /// are active (bit 0, like any other mask), the rest are shaded by how often they switched
+    /// (bits 1 through 7, on a log scale up to the busiest trace).
+    pub fn to_mask(&self, compiler_results: &CompilerResults) -> Mask {
+        let busiest = (0..self.rises.len())
+            .map(|trace| self.toggles(trace))
+            .max()
+            .unwrap_or(0);
+        let scale = 126.0 / (busiest as f64).ln_1p().max(f64::MIN_POSITIVE);
+
+        let mut mask = Mask::default();
+        mask.write_traces(compiler_results, |trace| match self.toggles(trace) {
+            0 if trace == 0 => 0,
+            0 => 1,
+            toggles => (1 + ((toggles as f64).ln_1p() * scale) as u8) << 1,
+        });
+        mask
+    }
+
+    /// Writes a report of the counts as tab separated values, with the least switched traces
+    /// first. Each trace is located by its first atom.
+    pub fn write_report(
+        &self,
+        w: &mut impl Write,
+        compiler_results: &CompilerResults,
+    ) -> io::Result<()> {
+        writeln!(
+            w,
+            "# {} of {} traces rose and fell ({:.1}% toggle coverage), {} never switched",
+            self.covered(),
+            self.rises.len().saturating_sub(1),
+            self.coverage() * 100.0,
+            self.never_switched().len()
+        )?;
+        writeln!(w, "trace\tx\ty\trises\tfalls")?;
+
+        let mut traces: Vec<usize> = (1..self.rises.len()).collect();
+        traces.sort_by_key(|&trace| (self.toggles(trace), trace));
+
+        for trace in traces {
+            let (x, y) = compiler_results
+                .traces
+                .get(trace)
+                .and_then(|atoms| atoms.first())
+                .map_or((0, 0), |atom| (atom.coord.0.x, atom.coord.0.y));
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}",
+                trace, x, y, self.rises[trace], self.falls[trace]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_report_string(&self, compiler_results: &CompilerResults) -> String {
+        let mut bytes = vec![];
+        self.write_report(&mut bytes, compiler_results).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_toggles() {
+        let mut coverage = ToggleCoverage::new(&[false, false, true]);
+
+        coverage.sample(0..3, &[false, true, false]);
+        coverage.sample([1], &[false, false, false]);
+        // Only the listed traces are looked at.
+        coverage.sample([1], &[false, false, true]);
+
+        assert_eq!(coverage.rises, vec![0, 1, 0]);
+        assert_eq!(coverage.falls, vec![0, 1, 1]);
+        assert_eq!(coverage.covered(), 1);
+        assert_eq!(coverage.never_switched(), Vec::<usize>::new());
+
+        // A jump in state isn't counted.
+        coverage.resync(&[false, true, true]);
+        coverage.sample(0..3, &[false, true, true]);
+        assert_eq!(coverage.toggles(1), 2);
+
+        let remap = IndexRemap {
+            traces: vec![Some(0), None, Some(1)],
+            gates: vec![],
+        };
+        coverage.remap(&remap, &[false, true, false]);
+        assert_eq!(coverage.rises, vec![0, 0, 0]);
+        assert_eq!(coverage.falls, vec![0, 1, 0]);
+        assert_eq!(coverage.never_switched(), vec![2]);
+    }
+}
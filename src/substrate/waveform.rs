@@ -0,0 +1,268 @@
+use std::io::{self, Write};
+
+use crate::substrate::{
+    buffer::Buffer,
+    compiler::{Atom, CellPart, CompilerResults, IndexRemap},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WaveformError {
+    #[error("no socket named \"{0}\"")]
+    UnknownSocket(String),
+
+    #[error("{0:?} is not part of any compiled trace")]
+    UnknownAtom(Atom),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRate {
+    /// Sample after every tick, timestamped by tick.
+    Tick,
+
+    /// Sample after every fundamental clock, timestamped by clock (counting from zero, so the
+    /// first clock is at time 0).
+    Clock,
+}
+
+/// Records the state of selected traces over time, storing only changes. Attach one to an
+/// ExecutionContext and it will be sampled automatically.
+pub struct WaveformRecorder {
+    pub sample_rate: SampleRate,
+    signals: Vec<Signal>,
+}
+
+struct Signal {
+    name: String,
+    trace: usize,
+    /// The atom the signal was added by, if any, which it follows across recompiles.
+    atom: Option<Atom>,
+    last: Option<bool>,
+    changes: Vec<(usize, bool)>,
+}
+
+impl WaveformRecorder {
+    pub fn new(sample_rate: SampleRate) -> Self {
+        Self {
+            sample_rate,
+            signals: vec![],
+        }
+    }
+
+    pub fn add_trace(&mut self, name: impl Into<String>, trace: usize) {
+        self.push(name.into(), trace, None);
+    }
+
+    fn push(&mut self, name: String, trace: usize, atom: Option<Atom>) {
+        self.signals.push(Signal {
+            name,
+            trace,
+            atom,
+            last: None,
+            changes: vec![],
+        });
+    }
+
+    pub fn add_atom(
+        &mut self,
+        name: impl Into<String>,
+        compiler_results: &CompilerResults,
+        atom: Atom,
+    ) -> Result<(), WaveformError> {
+        let trace = *compiler_results
+            .trace_lookup_by_atom
+            .get(&atom)
+            .ok_or(WaveformError::UnknownAtom(atom))?;
+        self.push(name.into(), trace, Some(atom));
+        Ok(())
+    }
+
+    /// Carries the signals over a recompile. Signals added by atom (or socket) are re-resolved
+    /// from it, others follow their trace through `remap`. Signals whose trace no longer exists
+    /// are dropped, along with their recording.
+    pub fn remap(&mut self, remap: &IndexRemap, compiler_results: &CompilerResults) {
+        self.signals.retain_mut(|signal| {
+            let trace = match signal.atom {
+                Some(atom) => compiler_results.trace_lookup_by_atom.get(&atom).copied(),
+                None => remap.traces.get(signal.trace).copied().flatten(),
+            };
+
+            match trace {
+                Some(trace) => {
+                    signal.trace = trace;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// Records the trace under the named socket (the socket's metal).
+    pub fn add_socket(
+        &mut self,
+        buffer: &Buffer,
+        compiler_results: &CompilerResults,
+        name: &str,
+    ) -> Result<(), WaveformError> {
+        let socket = buffer
+            .sockets
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| WaveformError::UnknownSocket(name.to_string()))?;
+
+        self.add_atom(
+            name,
+            compiler_results,
+            Atom {
+                coord: socket.cell_coord,
+                part: CellPart::Metal,
+            },
+        )
+    }
+
+    /// Called by the ExecutionContext after each tick.
+    pub fn sample_tick(&mut self, tick: usize, trace_states: &[bool]) {
+        if self.sample_rate == SampleRate::Tick {
+            self.sample(tick, trace_states);
+        }
+    }
+
+    /// Called by the ExecutionContext after each fundamental clock.
+    pub fn sample_clock(&mut self, clock: usize, trace_states: &[bool]) {
+        if self.sample_rate == SampleRate::Clock {
+            self.sample(clock, trace_states);
+        }
+    }
+
+    /// Forgets everything recorded after the simulation was at `ticks` and `clocks`, for when it
+    /// was stepped back there (say, a snapshot was restored). Recording carries on from there.
+    pub fn resync(&mut self, ticks: usize, clocks: usize) {
+        let end = match self.sample_rate {
+            SampleRate::Tick => ticks,
+            SampleRate::Clock => clocks,
+        };
+
+        for signal in &mut self.signals {
+            signal.changes.retain(|&(time, _)| time < end);
+            signal.last = signal.changes.last().map(|&(_, value)| value);
+        }
+    }
+
+    fn sample(&mut self, time: usize, trace_states: &[bool]) {
+        for signal in &mut self.signals {
+            let value = trace_states[signal.trace];
+            if signal.last != Some(value) {
+                signal.last = Some(value);
+                signal.changes.push((time, value));
+            }
+        }
+    }
+
+    /// Iterates the recorded changes of the named signal as (time, value) pairs.
+    pub fn changes(&self, name: &str) -> Option<&[(usize, bool)]> {
+        self.signals
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| &s.changes[..])
+    }
+
+    /// Writes the recording as a Value Change Dump, viewable in GTKWave and friends. Each tick or
+    /// clock (depending on the sample rate) is one nanosecond.
+    pub fn write_vcd(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "$version logic-paint $end")?;
+        writeln!(w, "$timescale 1ns $end")?;
+        writeln!(w, "$scope module logic_paint $end")?;
+        for (i, signal) in self.signals.iter().enumerate() {
+            let name: String = signal
+                .name
+                .chars()
+                .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+                .collect();
+            writeln!(w, "$var wire 1 {} {} $end", vcd_id(i), name)?;
+        }
+        writeln!(w, "$upscope $end")?;
+        writeln!(w, "$enddefinitions $end")?;
+
+        let mut changes: Vec<_> = self
+            .signals
+            .iter()
+            .enumerate()
+            .flat_map(|(i, s)| s.changes.iter().map(move |&(t, v)| (t, i, v)))
+            .collect();
+        changes.sort_by_key(|&(t, i, _)| (t, i));
+
+        let mut time = None;
+        for (t, i, value) in changes {
+            if time != Some(t) {
+                writeln!(w, "#{}", t)?;
+                time = Some(t);
+            }
+            writeln!(w, "{}{}", if value { '1' } else { '0' }, vcd_id(i))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_vcd_string(&self) -> String {
+        let mut bytes = vec![];
+        self.write_vcd(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+/// VCD identifiers are short strings of printable ASCII ('!' through '~').
+fn vcd_id(mut i: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (i % 94) as u8) as char);
+        i /= 94;
+        if i == 0 {
+            return id;
+        }
+        i -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_changes_and_writes_vcd() {
+        let mut recorder = WaveformRecorder::new(SampleRate::Tick);
+        recorder.add_trace("A", 1);
+        recorder.add_trace("B out", 2);
+
+        recorder.sample_tick(0, &[false, false, true]);
+        recorder.sample_tick(1, &[false, true, true]);
+        recorder.sample_tick(2, &[false, true, true]);
+        recorder.sample_tick(3, &[false, false, false]);
+
+        // Clock samples are ignored at a tick sample rate.
+        recorder.sample_clock(0, &[false, true, true]);
+
+        assert_eq!(
+            recorder.changes("A").unwrap(),
+            &[(0, false), (1, true), (3, false)]
+        );
+
+        assert_eq!(
+            recorder.to_vcd_string(),
+            "$version logic-paint $end\n\
+             $timescale 1ns $end\n\
+             $scope module logic_paint $end\n\
+             $var wire 1 ! A $end\n\
+             $var wire 1 \" B_out $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n0!\n1\"\n\
+             #1\n1!\n\
+             #3\n0!\n0\"\n"
+        );
+    }
+
+    #[test]
+    fn vcd_ids_are_unique() {
+        let ids: std::collections::HashSet<_> = (0..20_000).map(vcd_id).collect();
+        assert_eq!(ids.len(), 20_000);
+    }
+}
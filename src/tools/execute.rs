@@ -0,0 +1,68 @@
+use crate::substrate::buffer::Buffer;
+
+use super::{ExecutionCommand, Tool, ToolInput, ToolOutput};
+
+/// Execution mode. The tool itself is stateless, the Project owns the ExecutionContext (and the
+/// run mode) and acts on the commands this tool emits.
+#[derive(Default)]
+pub struct ToolExecute {}
+
+impl Tool for ToolExecute {
+    fn get_name(&self) -> &str {
+        "execute"
+    }
+
+    fn activate(&mut self, _buffer: Buffer) -> ToolOutput {
+        ToolOutput {
+            cursor_style: Some("default".to_string()),
+            execution: Some(ExecutionCommand::Start),
+            ..Default::default()
+        }
+    }
+
+    fn deactivate(&mut self, _buffer: Buffer) -> ToolOutput {
+        ToolOutput {
+            mask: Some(Default::default()),
+            cursor_style: Some("default".to_string()),
+            execution: Some(ExecutionCommand::Stop),
+            ..Default::default()
+        }
+    }
+
+    fn dispatch_event(
+        &mut self,
+        ToolInput {
+            active, io_state, ..
+        }: &ToolInput,
+    ) -> ToolOutput {
+        if io_state.get_key_code("KeyE").clicked {
+            return ToolOutput {
+                take_active: true,
+                ..Default::default()
+            };
+        }
+
+        if !active {
+            return Default::default();
+        }
+
+        let execution = if io_state.get_key_code("KeyR").clicked {
+            Some(ExecutionCommand::Run)
+        } else if io_state.get_key_code("KeyC").clicked {
+            Some(ExecutionCommand::Clock)
+        } else if io_state.get_key_code("KeyP").clicked {
+            Some(ExecutionCommand::Pause)
+        } else if io_state.get_key_code("KeyT").clicked {
+            Some(ExecutionCommand::Tick)
+        } else if io_state.get_key_code("KeyB").clicked {
+            Some(ExecutionCommand::StepBack)
+        } else {
+            None
+        };
+
+        ToolOutput {
+            execution,
+            ..Default::default()
+        }
+    }
+}
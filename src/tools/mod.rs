@@ -12,6 +12,7 @@ use crate::{
 pub mod camera_controller;
 pub mod draw_metal;
 pub mod draw_si;
+pub mod execute;
 pub mod place_socket;
 pub mod visual;
 
@@ -68,4 +69,24 @@ pub struct ToolOutput {
     pub take_active: bool,
     /// The selection to persist to the current Editor and Viewport.
     pub selection: Option<Selection>,
+    /// A command for the Project's ExecutionContext (which tools don't have access to).
+    pub execution: Option<ExecutionCommand>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionCommand {
+    /// Compile the buffer and enter execution, freezing the buffer until `Stop`.
+    Start,
+    /// Leave execution, dropping all simulation state.
+    Stop,
+    /// Execute one fundamental clock per frame.
+    Run,
+    /// Stop executing clocks each frame.
+    Pause,
+    /// Pause (if running) and execute a single fundamental clock.
+    Clock,
+    /// Pause (if running) and execute a single tick.
+    Tick,
+    /// Pause (if running) and rewind one recorded clock.
+    StepBack,
 }
@@ -45,7 +45,7 @@ impl Tool for ToolPlaceSocket {
             active, io_state, ..
         }: &ToolInput,
     ) -> ToolOutput {
-        if io_state.get_key_code("KeyE").clicked {
+        if io_state.get_key_code("KeyI").clicked {
             return ToolOutput {
                 take_active: true,
                 ..Default::default()
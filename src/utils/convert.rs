@@ -4,16 +4,21 @@ use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-use crate::{coords::ChunkCoord, substrate::buffer::Buffer, upc::UPC};
+use crate::{coords::ChunkCoord, impl_js_error, substrate::buffer::Buffer, upc::UPC};
 
 #[derive(Serialize, Deserialize)]
 struct Blueprint {
     chunks: HashMap<ChunkCoord, String>,
-    #[allow(dead_code)]
-    #[serde(skip)]
-    modules: JsValue,
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum LegacyImportError {
+    #[error("invalid legacy blueprint JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl_js_error!(LegacyImportError);
+
 #[derive(bincode::Decode)]
 struct Cell {
     upc_idx: u16,
@@ -24,9 +29,8 @@ struct Cell {
 /// Convert a legacy blueprint JSON file into a Buffer (which can then be saved into the latest
 /// format). Does not support modules, only the substrate is loaded.
 #[wasm_bindgen]
-pub fn import_legacy_blueprint(json_str: String) -> Result<Buffer, JsValue> {
-    let blueprint: Blueprint =
-        serde_json::from_str(&json_str).map_err(|e| JsValue::from_str(&e.to_string()))?;
+pub fn import_legacy_blueprint(json_str: String) -> Result<Buffer, LegacyImportError> {
+    let blueprint: Blueprint = serde_json::from_str(&json_str)?;
 
     let mut buffer = Buffer::default();
 
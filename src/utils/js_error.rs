@@ -0,0 +1,14 @@
+/// Implements `From<Error> for JsValue` for each error type given, so that wasm-bindgen exports
+/// can return them. JS sees the error's message.
+#[macro_export]
+macro_rules! impl_js_error {
+    ($($error:ty),+ $(,)?) => {
+        $(
+            impl From<$error> for ::wasm_bindgen::JsValue {
+                fn from(e: $error) -> Self {
+                    ::wasm_bindgen::JsValue::from_str(&e.to_string())
+                }
+            }
+        )+
+    };
+}
@@ -1,8 +1,11 @@
 pub use iter::*;
 pub use selection::*;
+pub use worker_pool::*;
 pub mod convert;
 
 mod iter;
+mod js_error;
 mod log;
 pub mod names;
 mod selection;
+mod worker_pool;
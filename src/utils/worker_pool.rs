@@ -0,0 +1,134 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+#[cfg(target_arch = "wasm32")]
+use wasm_thread as thread;
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of worker threads, spawned once and fed a task each per `run`. Workers park on a
+/// channel between runs, so a run costs a channel send per worker rather than a thread spawn.
+///
+/// On wasm the caller never parks while waiting on workers, as it's usually the browser main
+/// thread, which isn't allowed to block. It spins on the result channel instead (see `receive`).
+pub struct WorkerPool {
+    senders: Vec<mpsc::Sender<Task>>,
+
+    /// The number of workers that have started. Web workers only start once the main thread
+    /// yields to the event loop, so runs before then would spin forever.
+    started: Arc<AtomicUsize>,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Self {
+        let started = Arc::new(AtomicUsize::new(0));
+        let senders = (0..workers)
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel::<Task>();
+                let started = started.clone();
+
+                // Detached, the worker exits once the pool (and so its sender) is dropped.
+                thread::spawn(move || {
+                    started.fetch_add(1, Ordering::Release);
+                    while let Ok(task) = receiver.recv() {
+                        task();
+                    }
+                });
+
+                sender
+            })
+            .collect();
+
+        Self { senders, started }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// True once every worker has started and can take work.
+    pub fn is_ready(&self) -> bool {
+        self.started.load(Ordering::Acquire) == self.senders.len()
+    }
+
+    /// Runs `job(worker)` once on each worker, returning the results in worker order. Returns
+    /// only once every worker is done with `job`, which is what allows `job` to borrow from the
+    /// caller. Panics if a worker panicked (or exited).
+    pub fn run<R, F>(&self, job: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(usize) -> R + Sync,
+    {
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job = &job;
+
+        for (worker, sender) in self.senders.iter().enumerate() {
+            let result_sender = result_sender.clone();
+            let task: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                let _ = result_sender.send((worker, job(worker)));
+            });
+
+            // SAFETY: Each task holds a result sender, which is dropped once the task has run (or
+            // unwound, or was never sent). The wait below doesn't return until every result
+            // sender is dropped, so nothing the task borrows is dropped while it can still run.
+            let task = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + '_>, Task>(task) };
+
+            // A failed send hands the task back to be dropped here, along with its sender.
+            let _ = sender.send(task);
+        }
+        drop(result_sender);
+
+        let mut results: Vec<Option<R>> = (0..self.senders.len()).map(|_| None).collect();
+        while let Some((worker, result)) = receive(&result_receiver) {
+            results[worker] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("a worker thread panicked"))
+            .collect()
+    }
+}
+
+/// The next message, or None once every sender is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+fn receive<T>(receiver: &mpsc::Receiver<T>) -> Option<T> {
+    receiver.recv().ok()
+}
+
+/// Like the native `receive`, but spins rather than parking the thread. The wait is short, as
+/// `run` is used for work split evenly across workers.
+#[cfg(target_arch = "wasm32")]
+fn receive<T>(receiver: &mpsc::Receiver<T>) -> Option<T> {
+    loop {
+        match receiver.try_recv() {
+            Ok(message) => return Some(message),
+            Err(mpsc::TryRecvError::Empty) => std::hint::spin_loop(),
+            Err(mpsc::TryRecvError::Disconnected) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_borrowing_jobs_on_every_worker() {
+        let pool = WorkerPool::new(3);
+        while !pool.is_ready() {
+            std::thread::yield_now();
+        }
+
+        let values: Vec<usize> = (0..100).collect();
+        for _ in 0..10 {
+            let sums = pool.run(|worker| values.iter().filter(|v| *v % 3 == worker).sum::<usize>());
+            assert_eq!(sums.len(), 3);
+            assert_eq!(sums.iter().sum::<usize>(), values.iter().sum::<usize>());
+        }
+    }
+}
@@ -120,7 +120,7 @@ impl Viewport {
         self.gl.draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, 6);
 
         // Then render all non-empty chunks
-        for chunk in &editor.buffer.chunks {
+        for chunk in editor.buffer.chunks() {
             // Frustum cull chunks that aren't visible
             let coord = chunk.chunk_coord;
             if coord.0.x < ll.0.x || coord.0.x > ur.0.x || coord.0.y < ll.0.y || coord.0.y > ur.0.y